@@ -1,5 +1,9 @@
 //! Module containing code for the 'arena' that stores AST nodes.
 
+use std::cell::Cell;
+use std::hash::Hash;
+
+use elsa::FrozenIndexSet;
 use typed_arena::Arena as TyArena;
 
 /// An item that is stored in the [`Arena`].  This allows the [`Arena`] to build on
@@ -25,29 +29,106 @@ impl<T> Item<T> {
 /// This also differs from standard arena allocators in the following ways:
 /// - Nodes added to an [`Arena`] are **always immutable**.  Once they are added they can be cloned
 ///   but not changed.
-/// - This does not merge syntax tree nodes (where rustc does).  Sapling relies on the fact that
-///   within a given tree in the arena, all the nodes in that tree must have unique references.
-///   Nodes **can** exist inside multiple trees at once.
+/// - By default, this does not merge syntax tree nodes (where rustc does), so every call to
+///   [`alloc`](Arena::alloc) returns a new reference.  An `Arena` made with
+///   [`with_interning`](Arena::with_interning) instead merges structurally equal nodes (see
+///   below).  Either way, nodes **can** exist inside multiple trees at once, so Sapling always
+///   identifies nodes within a tree by their [`Path`](crate::core::Path) rather than by their
+///   address.
+///
+/// An interning `Arena` uses the nodes' [`Hash`] and [`Eq`] implementations to return the existing
+/// reference whenever a node equal to one already in the `Arena` is allocated.  Because children
+/// are allocated before their parents, every copy of a subtree is then stored once, and two trees
+/// are equal exactly when their roots have the same address.  The cost is that every allocation
+/// hashes the whole subtree of the new node.
+///
+/// Nodes are only freed when the whole `Arena` is dropped, so the editor periodically copies the
+/// nodes it still needs into a new `Arena` (see [`crate::editor::dag::Detacher`]).
 pub struct Arena<T> {
     base_arena: TyArena<Item<T>>,
+    /// Every node stored in an interning `Arena` (in which case `base_arena` is unused), or `None`
+    /// if this `Arena` doesn't intern its nodes
+    interned: Option<FrozenIndexSet<Box<T>>>,
+    /// The number of nodes that have been allocated in this `Arena`
+    len: Cell<usize>,
 }
 
-impl<T> Arena<T> {
+impl<T: Eq + Hash> Arena<T> {
     /// Creates an empty `Arena` of a given type.
     pub fn new() -> Arena<T> {
         Arena {
             base_arena: TyArena::new(),
+            interned: None,
+            len: Cell::new(0),
+        }
+    }
+
+    /// Creates an empty `Arena` which merges structurally equal nodes, so allocating a node equal
+    /// to one that is already in the `Arena` returns the existing node.
+    pub fn with_interning() -> Arena<T> {
+        Arena {
+            base_arena: TyArena::new(),
+            interned: Some(FrozenIndexSet::new()),
+            len: Cell::new(0),
         }
     }
 
     /// Add a new node to the `Arena`, and returns an immutable reference to its final location.
+    /// If the `Arena` is [interning](Arena::with_interning) and already contains an equal node,
+    /// then that node is returned instead.
     pub fn alloc(&self, node: T) -> &T {
-        &self.base_arena.alloc(Item::new(node)).node
+        match &self.interned {
+            Some(interned) => {
+                let (index, node) = interned.insert_full(Box::new(node));
+                // New nodes are always added to the end of the set
+                if index == self.len.get() {
+                    self.len.set(index + 1);
+                }
+                node
+            }
+            None => {
+                self.len.set(self.len.get() + 1);
+                &self.base_arena.alloc(Item::new(node)).node
+            }
+        }
+    }
+
+    /// Returns the number of nodes that have been allocated in this `Arena`.  For an interning
+    /// `Arena`, this only counts distinct nodes.
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    /// Returns `true` if no nodes have been allocated in this `Arena`
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 }
 
-impl<T> Default for Arena<T> {
+impl<T: Eq + Hash> Default for Arena<T> {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Arena;
+
+    #[test]
+    fn interning() {
+        let arena: Arena<Vec<u8>> = Arena::new();
+        let a = arena.alloc(vec![1, 2]);
+        let b = arena.alloc(vec![1, 2]);
+        assert!(!std::ptr::eq(a, b));
+        assert_eq!(arena.len(), 2);
+
+        let arena: Arena<Vec<u8>> = Arena::with_interning();
+        let a = arena.alloc(vec![1, 2]);
+        let b = arena.alloc(vec![3]);
+        let c = arena.alloc(vec![1, 2]);
+        assert!(std::ptr::eq(a, c));
+        assert!(!std::ptr::eq(a, b));
+        assert_eq!(arena.len(), 2);
+    }
+}
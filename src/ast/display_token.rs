@@ -4,7 +4,7 @@ use super::Ast;
 use std::borrow::Cow;
 
 /// How many spaces corespond to one indentation level
-const INDENT_WIDTH: usize = 4;
+pub const INDENT_WIDTH: usize = 4;
 
 /// A module of `const`s that represent the default [`SyntaxCategories`](SyntaxCategory)
 pub mod syntax_category {
@@ -1,7 +1,7 @@
 //! A hard-coded specification of JSON ASTs in a format editable by Sapling
 
 use super::display_token::{syntax_category, DisplayToken, RecTok};
-use super::{Ast, AstClass, DeleteError, InsertError};
+use super::{Ast, AstClass, DeleteError, InsertError, TextError};
 use crate::arena::Arena;
 use crate::ast_class;
 use crate::core::Size;
@@ -10,6 +10,11 @@ use serde_json::Value;
 
 /// Converts a [`serde_json::Value`] tree into a [`Json`] tree, whilst allocating the nodes into a
 /// given arena.
+///
+/// A [`Value`] doesn't store the text of its numbers, so they are written in `serde_json`'s own
+/// format (e.g. `1.50` becomes `1.5` and `1e3` becomes `1000.0`).  Text which is being loaded from
+/// a file should be parsed with [`Ast::parse_to_arena`] instead, which keeps numbers as they were
+/// written.
 pub fn add_value_to_arena<'arena>(
     json: Value,
     arena: &'arena Arena<Json<'arena>>,
@@ -18,7 +23,7 @@ pub fn add_value_to_arena<'arena>(
         Value::Null => arena.alloc(Json::Null),
         Value::Bool(true) => arena.alloc(Json::True),
         Value::Bool(false) => arena.alloc(Json::False),
-        Value::Number(_) => unimplemented!(),
+        Value::Number(n) => arena.alloc(Json::Number(n.to_string())),
         Value::String(s) => arena.alloc(Json::Str(s)),
         Value::Array(children) => arena.alloc(Json::Array(
             children
@@ -40,6 +45,297 @@ pub fn add_value_to_arena<'arena>(
     }
 }
 
+/// Parses JSON text directly into a [`Json`] tree, whilst allocating the nodes into a given arena.
+///
+/// Unlike going through [`serde_json::Value`], this keeps the text of every number exactly as it
+/// was written (so `1E+10` won't get normalised to `1e10` or `10000000000.0`), and keeps
+/// duplicated object keys rather than silently dropping all but one of them.
+pub fn parse_to_arena<'arena>(
+    text: &str,
+    arena: &'arena Arena<Json<'arena>>,
+) -> Result<&'arena Json<'arena>, ParseError> {
+    let mut parser = Parser {
+        text,
+        index: 0,
+        arena,
+    };
+    parser.skip_whitespace();
+    let root = parser.parse_value()?;
+    parser.skip_whitespace();
+    // Make sure that there isn't anything left over after the root value
+    match parser.peek() {
+        Some(c) => Err(parser.error(ParseErrorKind::TrailingChar(c))),
+        None => Ok(root),
+    }
+}
+
+/// Renders the contents of a JSON string as it appears in JSON text, i.e. surrounded by quotes and
+/// with any special characters escaped.
+fn escaped_string(content: &str) -> String {
+    // Serialising a `str` can never fail
+    serde_json::to_string(content).unwrap()
+}
+
+/// Returns `true` if `text` is exactly one number in JSON's number syntax
+fn is_valid_number(text: &str) -> bool {
+    let arena = Arena::new();
+    let mut parser = Parser {
+        text,
+        index: 0,
+        arena: &arena,
+    };
+    parser.parse_number().is_ok() && parser.index == text.len()
+}
+
+/// The different ways that parsing JSON text can fail
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ParseErrorKind {
+    /// The text ended in the middle of a value
+    UnexpectedEof,
+    /// A [`char`] was found which can't start or continue the current value
+    UnexpectedChar(char),
+    /// Some text was found after the root value
+    TrailingChar(char),
+    /// A number didn't follow JSON's number syntax
+    InvalidNumber,
+    /// A string contained an invalid escape sequence or control character
+    InvalidString,
+    /// The text couldn't be read
+    Io(String),
+}
+
+impl std::fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::UnexpectedEof => write!(f, "unexpected end of file"),
+            ParseErrorKind::UnexpectedChar(c) => write!(f, "unexpected character {:?}", c),
+            ParseErrorKind::TrailingChar(c) => {
+                write!(f, "unexpected character {:?} after the end of the value", c)
+            }
+            ParseErrorKind::InvalidNumber => write!(f, "invalid number"),
+            ParseErrorKind::InvalidString => write!(f, "invalid string"),
+            ParseErrorKind::Io(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// An error generated whilst parsing JSON text, along with where in the text it occurred
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ParseError {
+    /// What went wrong
+    pub kind: ParseErrorKind,
+    /// The line of the text on which the error occurred, starting from `1`
+    pub line: usize,
+    /// The column of the line at which the error occurred, starting from `1`
+    pub column: usize,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at line {} column {}",
+            self.kind, self.line, self.column
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A recursive descent parser which turns JSON text into [`Json`] nodes.  This is only used by
+/// [`parse_to_arena`].
+struct Parser<'t, 'arena> {
+    text: &'t str,
+    /// The byte index of the next unparsed [`char`] of `text`
+    index: usize,
+    arena: &'arena Arena<Json<'arena>>,
+}
+
+impl<'t, 'arena> Parser<'t, 'arena> {
+    /// Creates a [`ParseError`] which occurred at the parser's current location
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        let parsed_text = &self.text[..self.index];
+        ParseError {
+            kind,
+            line: parsed_text.matches('\n').count() + 1,
+            column: parsed_text.chars().rev().take_while(|c| *c != '\n').count() + 1,
+        }
+    }
+
+    /// Returns the next unparsed [`char`], without consuming it
+    fn peek(&self) -> Option<char> {
+        self.text[self.index..].chars().next()
+    }
+
+    /// Consumes the next [`char`] if it's equal to `c`, returning `true` if it was consumed
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.index += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consumes the next [`char`], which must be equal to `c`
+    fn expect(&mut self, c: char) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(next_char) if next_char == c => {
+                self.index += c.len_utf8();
+                Ok(())
+            }
+            Some(next_char) => Err(self.error(ParseErrorKind::UnexpectedChar(next_char))),
+            None => Err(self.error(ParseErrorKind::UnexpectedEof)),
+        }
+    }
+
+    /// Consumes as many ASCII digits as possible, returning how many were consumed
+    fn eat_digits(&mut self) -> usize {
+        let num_digits = self.text[self.index..]
+            .bytes()
+            .take_while(u8::is_ascii_digit)
+            .count();
+        self.index += num_digits;
+        num_digits
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.index += 1;
+        }
+    }
+
+    /// Parse a single JSON value, which must start at the current location
+    fn parse_value(&mut self) -> Result<&'arena Json<'arena>, ParseError> {
+        let node = match self.peek() {
+            None => return Err(self.error(ParseErrorKind::UnexpectedEof)),
+            Some('t') => self.parse_keyword("true", Json::True)?,
+            Some('f') => self.parse_keyword("false", Json::False)?,
+            Some('n') => self.parse_keyword("null", Json::Null)?,
+            Some('"') => Json::Str(self.parse_string()?),
+            Some('-' | '0'..='9') => Json::Number(self.parse_number()?),
+            Some('[') => {
+                self.index += 1;
+                let mut children = Vec::new();
+                self.parse_delimited(']', |this| {
+                    children.push(this.parse_value()?);
+                    Ok(())
+                })?;
+                Json::Array(children)
+            }
+            Some('{') => {
+                self.index += 1;
+                let mut fields = Vec::new();
+                self.parse_delimited('}', |this| {
+                    if this.peek() != Some('"') {
+                        return Err(match this.peek() {
+                            Some(c) => this.error(ParseErrorKind::UnexpectedChar(c)),
+                            None => this.error(ParseErrorKind::UnexpectedEof),
+                        });
+                    }
+                    let key = this.arena.alloc(Json::Str(this.parse_string()?));
+                    this.skip_whitespace();
+                    this.expect(':')?;
+                    this.skip_whitespace();
+                    let value = this.parse_value()?;
+                    fields.push(this.arena.alloc(Json::Field([key, value])));
+                    Ok(())
+                })?;
+                Json::Object(fields)
+            }
+            Some(c) => return Err(self.error(ParseErrorKind::UnexpectedChar(c))),
+        };
+        Ok(self.arena.alloc(node))
+    }
+
+    /// Parse a comma-separated sequence of items up to and including a closing [`char`].  The
+    /// opening bracket must already have been consumed.
+    fn parse_delimited(
+        &mut self,
+        close: char,
+        mut parse_item: impl FnMut(&mut Self) -> Result<(), ParseError>,
+    ) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        // Special case: an empty sequence (like `[]` or `{ }`)
+        if self.eat(close) {
+            return Ok(());
+        }
+        loop {
+            self.skip_whitespace();
+            parse_item(self)?;
+            self.skip_whitespace();
+            if !self.eat(',') {
+                return self.expect(close);
+            }
+        }
+    }
+
+    /// Parse a fixed keyword (like `true`), returning the node that it represents
+    fn parse_keyword(
+        &mut self,
+        keyword: &str,
+        node: Json<'arena>,
+    ) -> Result<Json<'arena>, ParseError> {
+        for c in keyword.chars() {
+            self.expect(c)?;
+        }
+        Ok(node)
+    }
+
+    /// Parse a number, returning its text exactly as it was written
+    fn parse_number(&mut self) -> Result<String, ParseError> {
+        let start_index = self.index;
+        self.eat('-');
+        // Integer part: either a single `0` or a sequence of digits without leading zeros
+        if !self.eat('0') && self.eat_digits() == 0 {
+            return Err(self.error(ParseErrorKind::InvalidNumber));
+        }
+        // Optional fractional part
+        if self.eat('.') && self.eat_digits() == 0 {
+            return Err(self.error(ParseErrorKind::InvalidNumber));
+        }
+        // Optional exponent
+        if self.eat('e') || self.eat('E') {
+            if !self.eat('+') {
+                self.eat('-');
+            }
+            if self.eat_digits() == 0 {
+                return Err(self.error(ParseErrorKind::InvalidNumber));
+            }
+        }
+        Ok(self.text[start_index..self.index].to_owned())
+    }
+
+    /// Parse a string literal (including its quotes), returning its unescaped contents
+    fn parse_string(&mut self) -> Result<String, ParseError> {
+        let start_index = self.index;
+        self.expect('"')?;
+        // Find the closing quote, skipping over any escaped chars
+        loop {
+            match self.peek() {
+                None => return Err(self.error(ParseErrorKind::UnexpectedEof)),
+                Some('"') => break,
+                Some('\\') => {
+                    self.index += 1;
+                    // The escaped char may be more than one byte long, even though it then isn't
+                    // a valid escape (which `serde_json` reports below)
+                    match self.peek() {
+                        None => return Err(self.error(ParseErrorKind::UnexpectedEof)),
+                        Some(c) => self.index += c.len_utf8(),
+                    }
+                }
+                Some(c) => self.index += c.len_utf8(),
+            }
+        }
+        self.index += 1;
+        // Let `serde_json` handle unescaping the contents of the string
+        serde_json::from_str(&self.text[start_index..self.index]).map_err(|_| {
+            self.index = start_index;
+            self.error(ParseErrorKind::InvalidString)
+        })
+    }
+}
+
 /// An enum to hold the different ways that a JSON AST can be formatted
 #[derive(Eq, PartialEq, Copy, Clone)]
 pub enum JsonFormat {
@@ -50,17 +346,29 @@ pub enum JsonFormat {
     Pretty,
 }
 
+impl std::str::FromStr for JsonFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "compact" => Ok(JsonFormat::Compact),
+            "pretty" => Ok(JsonFormat::Pretty),
+            _ => Err(()),
+        }
+    }
+}
+
 ast_class!(
     True => 't', "true";
     False => 'f', "false";
     Null => 'n', "null";
     Array => 'a', "array";
     Object => 'o', "object";
-    Str => 's', "string"
+    Str => 's', "string";
+    Number => 'N', "number"
 );
 
-/// The sapling representation of the AST for a subset of JSON (where all values are either 'true'
-/// or 'false', and keys only contain ASCII).
+/// The sapling representation of the AST for JSON.
 #[derive(Debug, Eq, PartialEq, Clone, Hash)]
 pub enum Json<'arena> {
     /// The JSON value for 'true'.  Corresponds to the string `true`.
@@ -82,6 +390,10 @@ pub enum Json<'arena> {
     Field([&'arena Json<'arena>; 2]),
     /// A JSON string
     Str(String),
+    /// A JSON number, stored as the exact text that represents it (e.g. `-12`, `3.50` or `1E+10`).
+    /// The text is kept verbatim so that numbers round-trip exactly, rather than being normalised
+    /// through a float.
+    Number(String),
 }
 
 impl Default for Json<'_> {
@@ -93,7 +405,7 @@ impl Default for Json<'_> {
 impl<'arena> Ast<'arena> for Json<'arena> {
     type FormatStyle = JsonFormat;
     type Class = Class;
-    type ParseErr = serde_json::Error;
+    type ParseErr = ParseError;
 
     /* FORMATTING FUNCTIONS */
 
@@ -107,9 +419,10 @@ impl<'arena> Ast<'arena> for Json<'arena> {
             Json::False => vec![RecTok::from_str("false", syntax_category::CONST)],
             Json::Null => vec![RecTok::from_str("null", syntax_category::KEYWORD)],
             Json::Str(string) => vec![RecTok::from_string(
-                format!(r#""{}""#, string),
+                escaped_string(string),
                 syntax_category::LITERAL,
             )],
+            Json::Number(text) => vec![RecTok::from_string(text.clone(), syntax_category::LITERAL)],
             Json::Field([key, value]) => vec![
                 RecTok::Child(key),
                 RecTok::from_str(": ", syntax_category::DEFAULT),
@@ -195,11 +508,16 @@ impl<'arena> Ast<'arena> for Json<'arena> {
     }
 
     fn parse_to_arena(
-        text: impl std::io::Read,
-        arena: &'arena mut Arena<Self>,
+        mut text: impl std::io::Read,
+        arena: &'arena Arena<Self>,
     ) -> Result<&'arena Self, Self::ParseErr> {
-        let value = serde_json::from_reader::<_, Value>(text)?;
-        Ok(add_value_to_arena(value, arena))
+        let mut string = String::new();
+        text.read_to_string(&mut string).map_err(|e| ParseError {
+            kind: ParseErrorKind::Io(e.to_string()),
+            line: 1,
+            column: 1,
+        })?;
+        parse_to_arena(&string, arena)
     }
 
     fn size(&self, format_style: &Self::FormatStyle) -> Size {
@@ -209,9 +527,8 @@ impl<'arena> Ast<'arena> for Json<'arena> {
                     Json::True => Size::new(0, 4),  // same as Size::from("true")
                     Json::False => Size::new(0, 5), // same as Size::from("false")
                     Json::Null => Size::new(0, 4),  // same as Size::from("null")
-                    Json::Str(string) => {
-                        Size::new(0, 1) + Size::from(string.as_str()) + Size::new(0, 1)
-                    }
+                    Json::Str(string) => Size::from(escaped_string(string).as_str()),
+                    Json::Number(text) => Size::from(text.as_str()),
                     Json::Field([key, value]) => {
                         key.size(format_style) + Size::new(0, 2) + value.size(format_style)
                     }
@@ -256,9 +573,8 @@ impl<'arena> Ast<'arena> for Json<'arena> {
                     Json::True => Size::new(0, 4),  // same as Size::from("true")
                     Json::False => Size::new(0, 5), // same as Size::from("false")
                     Json::Null => Size::new(0, 4),  // same as Size::from("null")
-                    Json::Str(string) => {
-                        Size::new(0, 1) + Size::from(string.as_str()) + Size::new(0, 1)
-                    }
+                    Json::Str(string) => Size::from(escaped_string(string).as_str()),
+                    Json::Number(text) => Size::from(text.as_str()),
                     Json::Field([key, value]) => {
                         key.size(format_style) + Size::new(0, 2) + value.size(format_style)
                     }
@@ -303,16 +619,16 @@ impl<'arena> Ast<'arena> for Json<'arena> {
 
     fn children<'s>(&'s self) -> &'s [&'arena Json<'arena>] {
         match self {
-            Json::True | Json::False | Json::Null | Json::Str(_) => &[],
-            Json::Array(children) => &children,
-            Json::Object(fields) => &fields,
+            Json::True | Json::False | Json::Null | Json::Str(_) | Json::Number(_) => &[],
+            Json::Array(children) => children,
+            Json::Object(fields) => fields,
             Json::Field(key_value) => &key_value[..],
         }
     }
 
     fn children_mut<'s>(&'s mut self) -> &'s mut [&'arena Json<'arena>] {
         match self {
-            Json::True | Json::False | Json::Null | Json::Str(_) => &mut [],
+            Json::True | Json::False | Json::Null | Json::Str(_) | Json::Number(_) => &mut [],
             Json::Array(children) => children,
             Json::Object(fields) => fields,
             Json::Field(key_value) => &mut key_value[..],
@@ -326,7 +642,7 @@ impl<'arena> Ast<'arena> for Json<'arena> {
         index: usize,
     ) -> Result<(), InsertError> {
         match self {
-            Json::True | Json::False | Json::Null | Json::Str(_) => {
+            Json::True | Json::False | Json::Null | Json::Str(_) | Json::Number(_) => {
                 Err(InsertError::TooManyChildren {
                     name: self.display_name(),
                     max_children: 0,
@@ -336,6 +652,12 @@ impl<'arena> Ast<'arena> for Json<'arena> {
                 name: self.display_name(),
                 max_children: 2,
             }),
+            // Fields (e.g. ones which have been cut from another object) can be inserted
+            // straight into an object
+            Json::Object(fields) if matches!(new_node, Json::Field(_)) => {
+                fields.insert(index, new_node);
+                Ok(())
+            }
             Json::Object(fields) => {
                 /* Inserting into an object is a special case, since we need to allocate more
                  * objects in order to preserve the validity of the tree. */
@@ -347,6 +669,13 @@ impl<'arena> Ast<'arena> for Json<'arena> {
                 fields.insert(index, field);
                 Ok(())
             }
+            // Fields can only be children of objects
+            Json::Array(_) if matches!(new_node, Json::Field(_)) => {
+                Err(InsertError::InvalidChild {
+                    name: self.display_name(),
+                    child_name: new_node.display_name(),
+                })
+            }
             Json::Array(children) => {
                 children.insert(index, new_node);
                 Ok(())
@@ -356,7 +685,7 @@ impl<'arena> Ast<'arena> for Json<'arena> {
 
     fn delete_child(&mut self, index: usize) -> Result<(), DeleteError> {
         match self {
-            Json::True | Json::False | Json::Null | Json::Str(_) => {
+            Json::True | Json::False | Json::Null | Json::Str(_) | Json::Number(_) => {
                 // We shouldn't be able to delete the child of a node with no children - this would
                 // require first selecting the non-existent child, which should be caught by the
                 // cursor path code.
@@ -400,6 +729,7 @@ impl<'arena> Ast<'arena> for Json<'arena> {
             Json::Object(_) => "object".to_string(),
             Json::Field(_) => "field".to_string(),
             Json::Str(content) => format!(r#""{}""#, content),
+            Json::Number(text) => text.clone(),
         }
     }
 
@@ -413,13 +743,28 @@ impl<'arena> Ast<'arena> for Json<'arena> {
             Class::Array => Json::Array(vec![]),
             Class::Object => Json::Object(vec![]),
             Class::Str => Json::Str("".to_string()),
+            Class::Number => Json::Number("0".to_string()),
+        }
+    }
+
+    fn class(&self) -> Option<Self::Class> {
+        match self {
+            Json::True => Some(Class::True),
+            Json::False => Some(Class::False),
+            Json::Null => Some(Class::Null),
+            Json::Array(_) => Some(Class::Array),
+            Json::Object(_) => Some(Class::Object),
+            Json::Str(_) => Some(Class::Str),
+            Json::Number(_) => Some(Class::Number),
+            // Fields are created implicitly when inserting into objects
+            Json::Field(_) => None,
         }
     }
 
     fn is_valid_child(&self, index: usize, node_type: Self::Class) -> bool {
         match self {
             // values like 'true' and 'false' can never have children
-            Json::True | Json::False | Json::Str(_) | Json::Null => false,
+            Json::True | Json::False | Json::Str(_) | Json::Number(_) | Json::Null => false,
             // arrays and objects can have any children (except `field` inside `array`, which can't
             // be inserted)
             Json::Array(_) | Json::Object(_) => true,
@@ -438,12 +783,106 @@ impl<'arena> Ast<'arena> for Json<'arena> {
         true
     }
 
+    fn text(&self) -> Option<&str> {
+        match self {
+            Json::Str(content) => Some(content),
+            Json::Number(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn key(&self) -> Option<&str> {
+        match self {
+            Json::Field([key, _]) => key.text(),
+            _ => None,
+        }
+    }
+
+    fn set_text(&mut self, new_text: String) -> Result<(), TextError> {
+        match self {
+            Json::Str(content) => {
+                *content = new_text;
+                Ok(())
+            }
+            Json::Number(text) => {
+                if !is_valid_number(&new_text) {
+                    return Err(TextError::InvalidText {
+                        name: self.display_name(),
+                        text: new_text,
+                    });
+                }
+                *text = new_text;
+                Ok(())
+            }
+            _ => Err(TextError::NoText {
+                name: self.display_name(),
+            }),
+        }
+    }
+
+    fn increment(&self, delta: i64) -> Option<Self> {
+        match self {
+            // Only integers can be incremented, since changing the value of a float or exponent
+            // would require normalising its text
+            Json::Number(text) => {
+                let value = text.parse::<i128>().ok()?;
+                let new_value = value.checked_add(i128::from(delta))?;
+                Some(Json::Number(new_value.to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    fn to_undo_data(&self) -> Value {
+        match self {
+            Json::True => Value::from("true"),
+            Json::False => Value::from("false"),
+            Json::Null => Value::from("null"),
+            Json::Array(_) => Value::from("array"),
+            Json::Object(_) => Value::from("object"),
+            Json::Field(_) => Value::from("field"),
+            Json::Str(content) => serde_json::json!({ "string": content }),
+            Json::Number(text) => serde_json::json!({ "number": text }),
+        }
+    }
+
+    fn from_undo_data(data: &Value, children: Vec<&'arena Self>) -> Option<Self> {
+        let is_field = |node: &&Json| matches!(node, Json::Field(_));
+        if let Some(name) = data.as_str() {
+            return match (name, children.as_slice()) {
+                ("true", []) => Some(Json::True),
+                ("false", []) => Some(Json::False),
+                ("null", []) => Some(Json::Null),
+                // Arrays can't contain fields, and objects can only contain fields
+                ("array", _) if !children.iter().any(is_field) => Some(Json::Array(children)),
+                ("object", _) if children.iter().all(is_field) => Some(Json::Object(children)),
+                ("field", [key @ Json::Str(_), value]) if !is_field(value) => {
+                    Some(Json::Field([key, value]))
+                }
+                _ => None,
+            };
+        }
+        // Strings and numbers are stored as single-entry objects containing their text
+        let (name, text) = match data.as_object()?.iter().collect::<Vec<_>>().as_slice() {
+            [(name, Value::String(text))] if children.is_empty() => (name.as_str(), text),
+            _ => return None,
+        };
+        let mut node = match name {
+            "string" => Json::Str(String::new()),
+            "number" => Json::Number("0".to_string()),
+            _ => return None,
+        };
+        node.set_text(text.clone()).ok()?;
+        Some(node)
+    }
+
     fn debug_name(&self) -> String {
         match self {
             Self::True => "True".to_owned(),
             Self::False => "False".to_owned(),
             Self::Null => "Null".to_owned(),
             Self::Str(string) => format!("\"{}\"", string),
+            Self::Number(text) => text.clone(),
             Self::Array(_) => "Array".to_owned(),
             Self::Field(_) => "Field".to_owned(),
             Self::Object(_) => "Object".to_owned(),
@@ -459,6 +898,7 @@ impl PartialEq<Value> for Json<'_> {
             | (Json::False, Value::Bool(false))
             | (Json::Null, Value::Null) => true,
             (Json::Str(s1), Value::String(s2)) => s1 == s2,
+            (Json::Number(text), Value::Number(n)) => *text == n.to_string(),
             (Json::Array(cs1), Value::Array(cs2)) => {
                 cs1.iter().zip(cs2.iter()).all(|(a, b)| *a == b)
             }
@@ -488,7 +928,7 @@ impl PartialEq<Value> for Json<'_> {
 
 #[cfg(test)]
 mod tests {
-    use super::{add_value_to_arena, JsonFormat};
+    use super::{add_value_to_arena, parse_to_arena, Json, JsonFormat, ParseError, ParseErrorKind};
     use crate::arena::Arena;
     use crate::ast::Ast;
     use crate::core::Size;
@@ -580,4 +1020,113 @@ mod tests {
             assert_eq!(s, *tree_string);
         }
     }
+
+    #[test]
+    fn number_round_trip() {
+        for (source, expected_compact_string) in &[
+            ("0", "0"),
+            ("-12", "-12"),
+            ("3.50", "3.50"),
+            ("1E+10", "1E+10"),
+            ("-2.5e-3", "-2.5e-3"),
+            (
+                "123456789012345678901234567890",
+                "123456789012345678901234567890",
+            ),
+            ("[1, 2.0, {\"x\": -0}]", "[1, 2.0, {\"x\": -0}]"),
+        ] {
+            println!("Testing {}", source);
+
+            let arena = Arena::new();
+            let root = parse_to_arena(source, &arena).unwrap();
+            let compact_string = root.to_text(&JsonFormat::Compact);
+            assert_eq!(compact_string, *expected_compact_string);
+            assert_eq!(
+                root.size(&JsonFormat::Compact),
+                Size::from(*expected_compact_string)
+            );
+            let pretty_string = root.to_text(&JsonFormat::Pretty);
+            assert_eq!(root.size(&JsonFormat::Pretty), Size::from(&*pretty_string));
+        }
+    }
+
+    #[test]
+    fn parse() {
+        for source in &[
+            "true",
+            "null",
+            r#""a \"quoted\" string""#,
+            r#""unescaped string""#,
+            r#"{"key": [1, 2.5, -0.0], "key": {}, "other": []}"#,
+            r#"["\\n\t", {"nested": {"deeper": [[], false]}}]"#,
+        ] {
+            println!("Testing {}", source);
+
+            let arena = Arena::new();
+            let root = parse_to_arena(source, &arena).unwrap();
+            // Parsing should agree with `serde_json` (except for duplicate keys, which
+            // `serde_json` will merge)
+            if !source.contains(r#""key": {}"#) {
+                assert_eq!(
+                    *root,
+                    serde_json::from_str::<serde_json::Value>(source).unwrap()
+                );
+            }
+            assert_eq!(root.to_text(&JsonFormat::Compact), *source);
+        }
+        // Whitespace is allowed anywhere between tokens
+        let arena = Arena::new();
+        let root = parse_to_arena(" [\n\t1 ,{ \"a\" :true } ]\r\n", &arena).unwrap();
+        assert_eq!(root.to_text(&JsonFormat::Compact), r#"[1, {"a": true}]"#);
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (source, kind, line, column) in &[
+            ("", ParseErrorKind::UnexpectedEof, 1, 1),
+            ("[true,", ParseErrorKind::UnexpectedEof, 1, 7),
+            ("[true false]", ParseErrorKind::UnexpectedChar('f'), 1, 7),
+            ("{\n  true: 1\n}", ParseErrorKind::UnexpectedChar('t'), 2, 3),
+            ("{\"a\" 1}", ParseErrorKind::UnexpectedChar('1'), 1, 6),
+            ("tru", ParseErrorKind::UnexpectedEof, 1, 4),
+            ("[1, 2]]", ParseErrorKind::TrailingChar(']'), 1, 7),
+            ("01", ParseErrorKind::TrailingChar('1'), 1, 2),
+            ("-", ParseErrorKind::InvalidNumber, 1, 2),
+            ("[1.]", ParseErrorKind::InvalidNumber, 1, 4),
+            ("1e+", ParseErrorKind::InvalidNumber, 1, 4),
+            ("[\n\"\\q\"]", ParseErrorKind::InvalidString, 2, 1),
+            ("[\"\\é\"]", ParseErrorKind::InvalidString, 1, 2),
+            ("\"unterminated", ParseErrorKind::UnexpectedEof, 1, 14),
+        ] {
+            println!("Testing {:?}", source);
+
+            let arena = Arena::new();
+            assert_eq!(
+                parse_to_arena(source, &arena),
+                Err(ParseError {
+                    kind: kind.clone(),
+                    line: *line,
+                    column: *column,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn increment() {
+        for (number, delta, expected) in &[
+            ("0", 1, Some("1")),
+            ("-1", 3, Some("2")),
+            ("10", -15, Some("-5")),
+            ("2.5", 1, None),
+            ("1e3", 1, None),
+        ] {
+            let node = Json::Number(number.to_string());
+            assert_eq!(
+                node.increment(*delta),
+                expected.map(|n| Json::Number(n.to_string()))
+            );
+        }
+        assert_eq!(Json::True.increment(1), None);
+    }
 }
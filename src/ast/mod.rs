@@ -19,6 +19,13 @@ pub enum InsertError {
         /// The maximum number of children that the node being inserted into could have
         max_children: usize,
     },
+    /// The node being inserted can never be a child of the node being inserted into
+    InvalidChild {
+        /// The [`display_name`](Ast::display_name) of the node being inserted into
+        name: String,
+        /// The [`display_name`](Ast::display_name) of the node being inserted
+        child_name: String,
+    },
 }
 
 impl std::fmt::Display for InsertError {
@@ -29,6 +36,9 @@ impl std::fmt::Display for InsertError {
                 "Can't exceed child count limit of {} in {}",
                 max_children, name
             ),
+            InsertError::InvalidChild { name, child_name } => {
+                write!(f, "{} can't be a child of {}", child_name, name)
+            }
         }
     }
 }
@@ -74,6 +84,36 @@ impl std::fmt::Display for DeleteError {
     }
 }
 
+/// The possible ways that setting the text contents of a node could fail
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum TextError {
+    /// The node doesn't have any text contents which can be edited
+    NoText {
+        /// The [`display_name`](Ast::display_name) of the node
+        name: String,
+    },
+    /// The new text isn't valid for this type of node (e.g. `1.2.3` isn't a valid JSON number)
+    InvalidText {
+        /// The [`display_name`](Ast::display_name) of the node
+        name: String,
+        /// The text which was rejected
+        text: String,
+    },
+}
+
+impl std::fmt::Display for TextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextError::NoText { name } => write!(f, "{} doesn't contain any text", name),
+            TextError::InvalidText { name, text } => {
+                write!(f, "{:?} isn't valid text for {}", text, name)
+            }
+        }
+    }
+}
+
+impl Error for TextError {}
+
 /// A function that recursively writes the tree view of a node and all its children to a given
 /// [`String`].  To avoid allocations, this function modifies a [`String`] buffer
 /// `indentation_string`, which will be appended to the front of every line, and will cause the
@@ -131,6 +171,13 @@ macro_rules! ast_class {
                     _ => None,
                 }
             }
+
+            fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $( $name => Some(Class::$variant_name), )+
+                    _ => None,
+                }
+            }
         }
     };
 }
@@ -145,12 +192,17 @@ pub trait AstClass: Copy + std::fmt::Debug + Eq + std::hash::Hash {
 
     /// Creates a `AstClass` from a [`char`], returning [`None`] if invalid.
     fn from_char(c: char) -> Option<Self>;
+
+    /// Creates a `AstClass` from its [`name`](AstClass::name), returning [`None`] if invalid.
+    fn from_name(name: &str) -> Option<Self>;
 }
 
 /// The specification of an AST that sapling can edit
 pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash {
-    /// A type parameter that will represent the different ways this AST can be rendered
-    type FormatStyle;
+    /// A type parameter that will represent the different ways this AST can be rendered.  This can
+    /// be parsed from a string so that the user can choose the format style (e.g. with
+    /// `:set format=<style>`).
+    type FormatStyle: std::str::FromStr + Clone;
     /// A type parameter that will represent the different node types this AST can use
     type Class: AstClass;
     /// The error type for ways that parsing can fail
@@ -168,7 +220,7 @@ pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash
     /// Parses from text and adds to an arena, return a pointer to the allocated root node.
     fn parse_to_arena(
         text: impl std::io::Read,
-        arena: &'arena mut Arena<Self>,
+        arena: &'arena Arena<Self>,
     ) -> Result<&'arena Self, Self::ParseErr>;
 
     /// Uses [`display_tokens_rec`](Self::display_tokens_rec) to build a stream of
@@ -268,12 +320,56 @@ pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash
     /// Generate a new node from a AstClass.
     fn from_class(node_type: Self::Class) -> Self;
 
+    /// Returns the [`AstClass`] of this node, or `None` if this node doesn't correspond to a class
+    /// (i.e. it can't be created on its own, like the fields of a JSON object).
+    fn class(&self) -> Option<Self::Class>;
+
     /// Returns whether or not a given index and [`char`] is a valid child
     fn is_valid_child(&self, index: usize, node_type: Self::Class) -> bool;
 
     /// Returns whether or not a give index and ['char'] is a valid root
     fn is_valid_root(&self, node_type: Self::Class) -> bool;
 
+    /// Returns the editable text contents of this node, or `None` if this node has no text that
+    /// can be edited.  For example, a JSON string's text is its contents without the quotes.  By
+    /// default, nodes have no text.
+    fn text(&self) -> Option<&str> {
+        None
+    }
+
+    /// Returns the key which identifies this node among its siblings (e.g. the key of a JSON
+    /// field), or `None` if this node is only identified by its position.  By default, nodes have
+    /// no key.
+    fn key(&self) -> Option<&str> {
+        None
+    }
+
+    /// Replaces the editable text contents of this node (see [`text`](Ast::text)).  By default,
+    /// nodes have no text and so this always fails.
+    fn set_text(&mut self, _text: String) -> Result<(), TextError> {
+        Err(TextError::NoText {
+            name: self.display_name(),
+        })
+    }
+
+    /// Returns a copy of this node with its numeric value changed by `delta`, or `None` if this
+    /// node doesn't represent a number that can be incremented.  By default, no nodes can be
+    /// incremented.
+    fn increment(&self, _delta: i64) -> Option<Self> {
+        None
+    }
+
     /// The name of this node as should be displayed in the DAG debug graph
     fn debug_name(&self) -> String;
+
+    /* UNDO FILE FUNCTIONS */
+
+    /// Returns a JSON value containing everything needed to recreate this node, apart from its
+    /// children.  This is used to store nodes in undo files.
+    fn to_undo_data(&self) -> serde_json::Value;
+
+    /// Recreates a node from the data returned by [`to_undo_data`](Ast::to_undo_data) and the
+    /// node's children, returning `None` if they don't make a valid node.  The data comes from a
+    /// file, so it should never be trusted.
+    fn from_undo_data(data: &serde_json::Value, children: Vec<&'arena Self>) -> Option<Self>;
 }
@@ -1,10 +1,42 @@
-//! Module to hold all user-configurable parameters, until we find a better way to handle
-//! configuration
+//! Module to hold all user-configurable parameters, and the code to load them from the user's
+//! config file.
+//!
+//! The config file is a JSON object, which is read from `$XDG_CONFIG_HOME/sapling/config.json`
+//! (or the path given by `--config`).  Every field is optional, and any field which isn't given
+//! keeps its default value:
+//! ```json
+//! {
+//!     "keymap": {
+//!         "dd": "delete",
+//!         "<C-r>": "redo",
+//!         "x": null
+//!     },
+//!     "color-scheme": {
+//!         "literal": "light-yellow",
+//!         "comment": "#7f8c8d",
+//!         "ident": 208
+//!     },
+//!     "undofile": true,
+//!     "undolevels": 500,
+//!     "internnodes": true
+//! }
+//! ```
+//! The keys of `keymap` are sequences of keystrokes (see [`parse_keystrokes`]) and the values are
+//! [`CmdType` names](CmdType::name), or `null` to remove a default binding.  The keys of
+//! `color-scheme` are [`SyntaxCategory`]s and the values are either colour names, `#rrggbb` hex
+//! codes or 256-colour terminal palette indices.  `undofile` turns on
+//! [undo files](crate::editor::undo_file), `undolevels` is the maximum number of changes kept
+//! in the undo tree, and `internnodes` makes the arena
+//! [merge identical nodes](crate::arena::Arena::with_interning).
 
 use crate::ast::display_token::{syntax_category::*, SyntaxCategory};
-use crate::core::Direction;
+use crate::core::{parse_keystrokes, Direction, Side};
 use crate::editor::normal_mode::CmdType;
+use crate::editor::window::{ScreenDirection, SplitDirection};
 
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
 use tuikit::prelude::{Color, Key};
 
 /* DEBUG FLAGS */
@@ -20,6 +52,56 @@ pub const DEBUG_HIGHLIGHTING: bool = false;
 /// A mapping from syntax highlighting categories to terminal [`Color`]s
 pub type ColorScheme = std::collections::HashMap<SyntaxCategory, Color>;
 
+/// Every [`SyntaxCategory`] which can be given a colour in the config file
+const SYNTAX_CATEGORIES: &[SyntaxCategory] = &[
+    DEFAULT, CONST, LITERAL, COMMENT, IDENT, KEYWORD, PRE_PROC, TYPE, SPECIAL, UNDERLINED, ERROR,
+];
+
+/// The names of the 16 standard terminal [`Color`]s, as they are written in the config file
+const COLOR_NAMES: &[(&str, Color)] = &[
+    ("black", Color::BLACK),
+    ("red", Color::RED),
+    ("green", Color::GREEN),
+    ("yellow", Color::YELLOW),
+    ("blue", Color::BLUE),
+    ("magenta", Color::MAGENTA),
+    ("cyan", Color::CYAN),
+    ("white", Color::WHITE),
+    ("light-black", Color::LIGHT_BLACK),
+    ("light-red", Color::LIGHT_RED),
+    ("light-green", Color::LIGHT_GREEN),
+    ("light-yellow", Color::LIGHT_YELLOW),
+    ("light-blue", Color::LIGHT_BLUE),
+    ("light-magenta", Color::LIGHT_MAGENTA),
+    ("light-cyan", Color::LIGHT_CYAN),
+    ("light-white", Color::LIGHT_WHITE),
+];
+
+/// Parses a [`Color`] from the config file.  This is either one of the [`COLOR_NAMES`], a
+/// `#rrggbb` hex code or a number between 0 and 255 (an index into the terminal's palette).
+fn parse_color(value: &Value) -> Option<Color> {
+    match value {
+        Value::Number(n) => n
+            .as_u64()
+            .filter(|&n| n <= u64::from(u8::MAX))
+            .map(|n| Color::AnsiValue(n as u8)),
+        Value::String(s) => {
+            if let Some(hex) = s.strip_prefix('#') {
+                if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return None;
+                }
+                let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+                return Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+            }
+            COLOR_NAMES
+                .iter()
+                .find(|(name, _)| name == s)
+                .map(|(_, color)| *color)
+        }
+        _ => None,
+    }
+}
+
 /// Return the default [`ColorScheme`] of Sapling
 pub fn default_color_scheme() -> ColorScheme {
     hmap::hmap! {
@@ -39,29 +121,95 @@ pub fn default_color_scheme() -> ColorScheme {
 
 /* KEY BINDINGS */
 
-/// Mapping of keys to keystrokes.
+/// Mapping of sequences of keystrokes to the commands that they trigger.
 /// Shortcut definition, also allows us to change the type if needed.
-pub type KeyMap = std::collections::HashMap<Key, CmdType>;
+pub type KeyMap = std::collections::HashMap<Vec<Key>, CmdType>;
 
 /// Generates a 'canonical' [`KeyMap`].  These keybindings will be very similar to those of Vim.
 pub fn default_keymap() -> KeyMap {
-    hmap::hmap! {
+    let single_keys = hmap::hmap! {
         Key::Char('q') => CmdType::Quit,
         Key::Char('w') => CmdType::Write,
         Key::Char('i') => CmdType::InsertBefore,
         Key::Char('a') => CmdType::InsertAfter,
         Key::Char('o') => CmdType::InsertChild,
         Key::Char('r') => CmdType::Replace,
+        Key::Char('W') => CmdType::Wrap,
+        Key::Char('J') => CmdType::MoveNode(Side::Next),
+        Key::Char('K') => CmdType::MoveNode(Side::Prev),
+        Key::Char('>') => CmdType::Slurp(Side::Prev),
+        Key::Char('<') => CmdType::Barf(Side::Next),
         Key::Char('x') => CmdType::Delete,
+        Key::Char('y') => CmdType::Yank,
+        Key::Char('p') => CmdType::PutAfter,
+        Key::Char('P') => CmdType::PutBefore,
+        Key::Char('e') => CmdType::EditText,
+        Key::Char(':') => CmdType::CommandMode,
+        Key::Char('/') => CmdType::Search(Side::Next),
+        Key::Char('?') => CmdType::Search(Side::Prev),
+        Key::Char('n') => CmdType::RepeatSearch(Side::Next),
+        Key::Char('N') => CmdType::RepeatSearch(Side::Prev),
+        Key::Char('v') => CmdType::VisualMode,
         Key::Char('c') => CmdType::MoveCursor(Direction::Down),
-        Key::Char('p') => CmdType::MoveCursor(Direction::Up),
+        Key::Char('-') => CmdType::MoveCursor(Direction::Up),
         Key::Char('h') => CmdType::MoveCursor(Direction::Prev),
         Key::Char('j') => CmdType::MoveCursor(Direction::Next),
         Key::Char('k') => CmdType::MoveCursor(Direction::Prev),
         Key::Char('l') => CmdType::MoveCursor(Direction::Next),
+        Key::Char('L') => CmdType::MoveCursor(Direction::LastChild),
+        Key::Char('^') => CmdType::MoveCursor(Direction::FirstSibling),
+        Key::Char('$') => CmdType::MoveCursor(Direction::LastSibling),
+        Key::Char(']') => CmdType::MoveCursor(Direction::PreorderNext),
+        Key::Char('[') => CmdType::MoveCursor(Direction::PreorderPrev),
+        Key::Char('}') => CmdType::MoveCursor(Direction::NextLeaf),
+        Key::Char('{') => CmdType::MoveCursor(Direction::PrevLeaf),
         Key::Char('u') => CmdType::Undo,
-        Key::Char('R') => CmdType::Redo
+        Key::Char('R') => CmdType::Redo,
+        Key::Char('U') => CmdType::UndoTree,
+        Key::Ctrl('a') => CmdType::Increment,
+        Key::Ctrl('x') => CmdType::Decrement,
+        Key::PageUp => CmdType::PageUp,
+        Key::PageDown => CmdType::PageDown
+    };
+    let mut keymap: KeyMap = single_keys
+        .into_iter()
+        .map(|(key, cmd_type)| (vec![key], cmd_type))
+        .collect();
+    // Bindings which take more than one keystroke
+    keymap.insert(vec![Key::Char('z'), Key::Char('z')], CmdType::CenterCursor);
+    keymap.insert(vec![Key::Char('g'), Key::Char('p')], CmdType::PutChild);
+    keymap.insert(vec![Key::Char('g'), Key::Char('-')], CmdType::OlderState);
+    keymap.insert(vec![Key::Char('g'), Key::Char('+')], CmdType::NewerState);
+    keymap.insert(vec![Key::Char('g'), Key::Char('s')], CmdType::SelectSimilar);
+    keymap.insert(vec![Key::Char('g'), Key::Char('r')], CmdType::Raise);
+    keymap.insert(
+        vec![Key::Char('g'), Key::Char('g')],
+        CmdType::MoveCursor(Direction::Root),
+    );
+    keymap.insert(
+        vec![Key::Char('g'), Key::Char('>')],
+        CmdType::Slurp(Side::Next),
+    );
+    keymap.insert(
+        vec![Key::Char('g'), Key::Char('<')],
+        CmdType::Barf(Side::Prev),
+    );
+    // Window commands start with `<C-w>`, like in Vim
+    for (key, cmd_type) in &[
+        ('w', CmdType::NextWindow),
+        ('W', CmdType::PrevWindow),
+        ('s', CmdType::SplitWindow(SplitDirection::Horizontal)),
+        ('v', CmdType::SplitWindow(SplitDirection::Vertical)),
+        ('c', CmdType::CloseWindow),
+        ('o', CmdType::OnlyWindow),
+        ('h', CmdType::WindowInDirection(ScreenDirection::Left)),
+        ('j', CmdType::WindowInDirection(ScreenDirection::Down)),
+        ('k', CmdType::WindowInDirection(ScreenDirection::Up)),
+        ('l', CmdType::WindowInDirection(ScreenDirection::Right)),
+    ] {
+        keymap.insert(vec![Key::Ctrl('w'), Key::Char(*key)], *cmd_type);
     }
+    keymap
 }
 
 /* COMPLETE CONFIG */
@@ -69,17 +217,310 @@ pub fn default_keymap() -> KeyMap {
 /// A struct to hold the entire run-time configuration of Sapling
 #[derive(Debug, Clone)]
 pub struct Config {
-    /// A mapping between [`char`]s and [`CmdType`]s
+    /// A mapping between sequences of [`Key`]s and [`CmdType`]s
     pub keymap: KeyMap,
     /// The current [`ColorScheme`] of Sapling
     pub color_scheme: ColorScheme,
+    /// Whether or not the undo history of each file is stored in an undo file, so that it can be
+    /// restored when the file is next opened
+    pub undo_file: bool,
+    /// The maximum number of changes kept in the undo tree.  Older changes are dropped, so that
+    /// their nodes can be freed.
+    pub undo_levels: usize,
+    /// Whether or not structurally equal nodes are merged into one node in the arena, which saves
+    /// memory when the tree contains lots of repeated values
+    pub intern_nodes: bool,
 }
 
+/// The default value of [`Config::undo_levels`]
+pub const DEFAULT_UNDO_LEVELS: usize = 1000;
+
 impl Default for Config {
     fn default() -> Self {
         Config {
             keymap: default_keymap(),
             color_scheme: default_color_scheme(),
+            undo_file: false,
+            undo_levels: DEFAULT_UNDO_LEVELS,
+            intern_nodes: false,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the user's `Config`.  If `path` is given, then that file must exist and is used as
+    /// the config file.  Otherwise, the config is read from [`default_config_path`] if that file
+    /// exists, and the default `Config` is used if it doesn't.
+    pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+        let path = match path {
+            Some(p) => p.to_owned(),
+            None => match default_config_path() {
+                Some(p) if p.exists() => p,
+                _ => {
+                    log::info!("No config file found, using the default config");
+                    return Ok(Config::default());
+                }
+            },
+        };
+        log::info!("Loading config from {:?}", path);
+        let text = std::fs::read_to_string(&path).map_err(|e| ConfigError::Io(path.clone(), e))?;
+        Config::from_json(&text)
+    }
+
+    /// Builds a `Config` from the text of a JSON config file, where every field that isn't
+    /// specified keeps its default value.
+    pub fn from_json(text: &str) -> Result<Config, ConfigError> {
+        let value: Value = serde_json::from_str(text).map_err(ConfigError::Json)?;
+        let mut config = Config::default();
+        for (field, value) in as_object(&value, "the config file to be an object")? {
+            match field.as_str() {
+                "keymap" => {
+                    for (keys, cmd_name) in as_object(value, "'keymap' to be an object")? {
+                        let keys = parse_keystrokes(keys)
+                            .filter(|keys| !keys.is_empty())
+                            .ok_or_else(|| ConfigError::InvalidKeys(keys.clone()))?;
+                        match cmd_name {
+                            // `null` removes a binding
+                            Value::Null => {
+                                config.keymap.remove(&keys);
+                            }
+                            Value::String(name) => {
+                                let cmd_type = CmdType::from_name(name)
+                                    .ok_or_else(|| ConfigError::UnknownCommand(name.clone()))?;
+                                config.keymap.insert(keys, cmd_type);
+                            }
+                            _ => {
+                                return Err(ConfigError::WrongType(
+                                    "keymap entries to be command names or null",
+                                ))
+                            }
+                        }
+                    }
+                }
+                "color-scheme" => {
+                    for (category_name, color) in
+                        as_object(value, "'color-scheme' to be an object")?
+                    {
+                        let category = SYNTAX_CATEGORIES
+                            .iter()
+                            .find(|c| *c == category_name)
+                            .ok_or_else(|| ConfigError::UnknownCategory(category_name.clone()))?;
+                        let color = parse_color(color)
+                            .ok_or_else(|| ConfigError::InvalidColor(color.to_string()))?;
+                        config.color_scheme.insert(category, color);
+                    }
+                }
+                "undofile" => {
+                    config.undo_file = value
+                        .as_bool()
+                        .ok_or(ConfigError::WrongType("'undofile' to be true or false"))?;
+                }
+                "undolevels" => {
+                    config.undo_levels = value.as_u64().ok_or(ConfigError::WrongType(
+                        "'undolevels' to be a non-negative integer",
+                    ))? as usize;
+                }
+                "internnodes" => {
+                    config.intern_nodes = value
+                        .as_bool()
+                        .ok_or(ConfigError::WrongType("'internnodes' to be true or false"))?;
+                }
+                _ => return Err(ConfigError::UnknownField(field.clone())),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Returns the path of the config file that Sapling reads if no path is given on the command
+/// line: `$XDG_CONFIG_HOME/sapling/config.json`, falling back on `~/.config/sapling/config.json`
+/// if `$XDG_CONFIG_HOME` isn't set.
+pub fn default_config_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("sapling").join("config.json"))
+}
+
+/// Returns the entries of a JSON object, or a [`ConfigError::WrongType`] (with a given expectation)
+/// if the [`Value`] isn't an object
+fn as_object<'v>(
+    value: &'v Value,
+    expected: &'static str,
+) -> Result<&'v serde_json::Map<String, Value>, ConfigError> {
+    value.as_object().ok_or(ConfigError::WrongType(expected))
+}
+
+/// The possible ways that loading a [`Config`] could fail
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file couldn't be read
+    Io(PathBuf, std::io::Error),
+    /// The config file isn't valid JSON
+    Json(serde_json::Error),
+    /// A JSON value has the wrong type.  The string describes what was expected.
+    WrongType(&'static str),
+    /// The config file has a top-level field that Sapling doesn't know about
+    UnknownField(String),
+    /// A keymap entry isn't a valid sequence of keystrokes
+    InvalidKeys(String),
+    /// A keymap entry is bound to a command name which doesn't exist
+    UnknownCommand(String),
+    /// The colour scheme refers to a [`SyntaxCategory`] which doesn't exist
+    UnknownCategory(String),
+    /// The colour scheme contains a value which isn't a colour
+    InvalidColor(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "Can't read config file {:?}: {}", path, e),
+            ConfigError::Json(e) => write!(f, "Config file isn't valid JSON: {}", e),
+            ConfigError::WrongType(expected) => write!(f, "Expected {}", expected),
+            ConfigError::UnknownField(field) => write!(
+                f,
+                "Unknown config field '{}' (expected 'keymap', 'color-scheme', 'undofile', 'undolevels' or 'internnodes')",
+                field
+            ),
+            ConfigError::InvalidKeys(keys) => write!(f, "Invalid keystrokes '{}'", keys),
+            ConfigError::UnknownCommand(name) => write!(
+                f,
+                "Unknown command '{}' (expected one of {})",
+                name,
+                CmdType::ALL
+                    .iter()
+                    .map(|c| format!("'{}'", c.name()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            ConfigError::UnknownCategory(name) => write!(
+                f,
+                "Unknown syntax category '{}' (expected one of {})",
+                name,
+                SYNTAX_CATEGORIES
+                    .iter()
+                    .map(|c| format!("'{}'", c))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            ConfigError::InvalidColor(color) => write!(
+                f,
+                "Invalid colour {} (expected a colour name, '#rrggbb' or a number 0-255)",
+                color
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{default_keymap, Config, ConfigError};
+    use crate::ast::display_token::syntax_category::*;
+    use crate::core::Direction;
+    use crate::editor::normal_mode::CmdType;
+    use tuikit::prelude::{Color, Key};
+
+    #[test]
+    fn empty_config_is_default() {
+        let config = Config::from_json("{}").unwrap();
+        assert_eq!(config.keymap, default_keymap());
+        assert_eq!(config.color_scheme, super::default_color_scheme());
+    }
+
+    #[test]
+    fn load_config() {
+        let config = Config::from_json(
+            r##"{
+                "keymap": {
+                    "gp": "move-up",
+                    "<C-r>": "redo",
+                    "<PageDown>": "move-next",
+                    "R": null
+                },
+                "color-scheme": {
+                    "literal": "light-blue",
+                    "comment": "#7f8C8d",
+                    "ident": 208
+                },
+                "undofile": true,
+                "undolevels": 20,
+                "internnodes": true
+            }"##,
+        )
+        .unwrap();
+        let keymap = &config.keymap;
+        assert_eq!(
+            keymap.get(&vec![Key::Char('g'), Key::Char('p')]),
+            Some(&CmdType::MoveCursor(Direction::Up))
+        );
+        assert_eq!(keymap.get(&vec![Key::Ctrl('r')]), Some(&CmdType::Redo));
+        assert_eq!(
+            keymap.get(&vec![Key::PageDown]),
+            Some(&CmdType::MoveCursor(Direction::Next))
+        );
+        assert_eq!(keymap.get(&vec![Key::Char('R')]), None);
+        // Bindings that weren't mentioned are left alone
+        assert_eq!(keymap.get(&vec![Key::Char('u')]), Some(&CmdType::Undo));
+
+        let colors = &config.color_scheme;
+        assert_eq!(colors.get(LITERAL), Some(&Color::LIGHT_BLUE));
+        assert_eq!(colors.get(COMMENT), Some(&Color::Rgb(0x7f, 0x8c, 0x8d)));
+        assert_eq!(colors.get(IDENT), Some(&Color::AnsiValue(208)));
+        assert_eq!(colors.get(CONST), Some(&Color::RED));
+        assert!(config.undo_file);
+        assert_eq!(config.undo_levels, 20);
+        assert!(config.intern_nodes);
+    }
+
+    #[test]
+    fn config_errors() {
+        let err = |text: &str| Config::from_json(text).unwrap_err();
+        assert!(matches!(err("{"), ConfigError::Json(_)));
+        assert!(matches!(err("[]"), ConfigError::WrongType(_)));
+        assert!(matches!(err(r#"{"keymap": 3}"#), ConfigError::WrongType(_)));
+        assert!(matches!(
+            err(r#"{"undofile": 1}"#),
+            ConfigError::WrongType(_)
+        ));
+        assert!(matches!(
+            err(r#"{"undolevels": -1}"#),
+            ConfigError::WrongType(_)
+        ));
+        assert!(matches!(
+            err(r#"{"internnodes": "yes"}"#),
+            ConfigError::WrongType(_)
+        ));
+        assert!(matches!(err(r#"{"keys": {}}"#), ConfigError::UnknownField(f) if f == "keys"));
+        assert!(matches!(
+            err(r#"{"keymap": {"<Foo>": "undo"}}"#),
+            ConfigError::InvalidKeys(k) if k == "<Foo>"
+        ));
+        assert!(matches!(
+            err(r#"{"keymap": {"": "undo"}}"#),
+            ConfigError::InvalidKeys(_)
+        ));
+        assert!(matches!(
+            err(r#"{"keymap": {"U": "undo-everything"}}"#),
+            ConfigError::UnknownCommand(c) if c == "undo-everything"
+        ));
+        assert!(matches!(
+            err(r#"{"color-scheme": {"strings": "red"}}"#),
+            ConfigError::UnknownCategory(c) if c == "strings"
+        ));
+        for color in &[
+            r#""purple""#,
+            r##""#12345""##,
+            r##""#gg0000""##,
+            "256",
+            "-1",
+            "true",
+        ] {
+            let text = format!(r#"{{"color-scheme": {{"type": {}}}}}"#, color);
+            assert!(matches!(err(&text), ConfigError::InvalidColor(_)));
         }
     }
 }
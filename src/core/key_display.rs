@@ -48,3 +48,119 @@ pub fn keystrokes_to_string(keystroke_buffer: &[Key]) -> String {
         .map(|x| x.compact_string())
         .collect()
 }
+
+/// Parses a sequence of keystrokes, written in the same notation as [`keystrokes_to_string`], back
+/// into a `Vec` of [`Key`]s.  Printable characters stand for themselves, whereas any other key is
+/// written as a name in angle brackets (e.g. `<Esc>`, `<PageDown>`, `<F5>` or `<C-a>` for Ctrl-a).
+/// `<lt>` and `<Space>` can be used for `<` and ` ` respectively.  Returns `None` if the string
+/// contains an unknown or unterminated key name.
+pub fn parse_keystrokes(string: &str) -> Option<Vec<Key>> {
+    let mut keys = Vec::new();
+    let mut chars = string.chars();
+    while let Some(c) = chars.next() {
+        if c != '<' {
+            keys.push(Key::Char(c));
+            continue;
+        }
+        // Read the key name up to the closing '>'
+        let mut name = String::new();
+        loop {
+            match chars.next()? {
+                '>' => break,
+                c => name.push(c),
+            }
+        }
+        keys.push(parse_key_name(&name)?);
+    }
+    Some(keys)
+}
+
+/// Parses the name of a single [`Key`] (i.e. the contents of `<...>` in [`parse_keystrokes`]).
+/// Names are case-insensitive, except for the character in `<C-*>`.
+fn parse_key_name(name: &str) -> Option<Key> {
+    // Ctrl-modified keys have to be handled first, because their character is case-sensitive
+    if let Some(rest) = name.strip_prefix("C-").or_else(|| name.strip_prefix("c-")) {
+        let mut chars = rest.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(Key::Ctrl(c)),
+            _ => None,
+        };
+    }
+    let lower_name = name.to_ascii_lowercase();
+    Some(match lower_name.as_str() {
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+
+        "esc" => Key::ESC,
+        "bs" => Key::Backspace,
+        "del" => Key::Delete,
+        "tab" => Key::Tab,
+        "cr" | "enter" => Key::Enter,
+
+        "insert" => Key::Insert,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+
+        "lt" => Key::Char('<'),
+        "space" => Key::Char(' '),
+
+        _ => Key::F(lower_name.strip_prefix('f')?.parse().ok()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{keystrokes_to_string, parse_keystrokes};
+    use tuikit::prelude::Key;
+
+    #[test]
+    fn parse_valid() {
+        for (string, expected_keys) in &[
+            ("", vec![]),
+            ("q", vec![Key::Char('q')]),
+            ("gg", vec![Key::Char('g'), Key::Char('g')]),
+            ("<C-a>", vec![Key::Ctrl('a')]),
+            ("<c-X>", vec![Key::Ctrl('X')]),
+            ("<Esc>", vec![Key::ESC]),
+            ("<esc>", vec![Key::ESC]),
+            ("<PageDown>", vec![Key::PageDown]),
+            ("<F12>", vec![Key::F(12)]),
+            ("g<Up>", vec![Key::Char('g'), Key::Up]),
+            (
+                "<lt><Space>>",
+                vec![Key::Char('<'), Key::Char(' '), Key::Char('>')],
+            ),
+        ] {
+            println!("Testing {:?}", string);
+            assert_eq!(parse_keystrokes(string).as_ref(), Some(expected_keys));
+        }
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for string in &[
+            "<", "<Esc", "<Foo>", "<C->", "<C-ab>", "<F>", "<Fx>", "<F256>",
+        ] {
+            println!("Testing {:?}", string);
+            assert_eq!(parse_keystrokes(string), None);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for keys in &[
+            vec![Key::Char('x')],
+            vec![Key::ESC, Key::Enter, Key::Tab, Key::Backspace, Key::Delete],
+            vec![Key::Home, Key::End, Key::PageUp, Key::Insert, Key::F(3)],
+        ] {
+            assert_eq!(
+                parse_keystrokes(&keystrokes_to_string(keys)).as_ref(),
+                Some(keys)
+            );
+        }
+    }
+}
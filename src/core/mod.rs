@@ -2,22 +2,45 @@
 
 mod key_display;
 mod path;
+mod path_query;
 
-// Re-export `core::path::Path` and `core::key_display::KeyDisplay` as `core::{Path, KeyDisplay}`
-pub use key_display::{keystrokes_to_string, KeyDisplay};
+// Re-export `core::path::Path`, `core::path_query::PathQuery` and `core::key_display::KeyDisplay`
+// as `core::{Path, PathQuery, KeyDisplay}`
+pub use key_display::{keystrokes_to_string, parse_keystrokes, KeyDisplay};
 pub use path::Path;
+pub use path_query::{PathQuery, PathQueryErr};
 
 /// The possible ways you can move the cursor
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum Direction {
     /// The [`Direction`] from a node to its parent/ancestors
     Up,
-    /// The [`Direction`] from a node to its children/descendants
+    /// The [`Direction`] from a node to its children/descendants.  This goes to the child that
+    /// the cursor was last in, or to the first child.
     Down,
     /// The [`Direction`] from a node to its lower-indexed siblings
     Prev,
     /// the [`Direction`] from a node to its higher-indexed siblings
     Next,
+    /// The [`Direction`] from a node to its last children/descendants
+    LastChild,
+    /// The [`Direction`] from a node to its first sibling
+    FirstSibling,
+    /// The [`Direction`] from a node to its last sibling
+    LastSibling,
+    /// The [`Direction`] from a node to the root of the tree
+    Root,
+    /// The [`Direction`] from a node to the nodes after it in pre-order (i.e. the order that the
+    /// nodes appear in the text)
+    PreorderNext,
+    /// The [`Direction`] from a node to the nodes before it in pre-order
+    PreorderPrev,
+    /// The [`Direction`] from a node to the nodes without children which come after it in
+    /// pre-order
+    NextLeaf,
+    /// The [`Direction`] from a node to the nodes without children which come before it in
+    /// pre-order
+    PrevLeaf,
 }
 
 /// An enum to represent the two sides of a node
@@ -166,7 +189,7 @@ mod tests {
             let mut total_size_add_assign = Size::ZERO;
             let mut full_string = String::new();
             for s in *strings {
-                total_size_add = total_size_add + Size::from(*s);
+                total_size_add += Size::from(*s);
                 total_size_add_assign += Size::from(*s);
                 full_string.push_str(s);
             }
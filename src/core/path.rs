@@ -3,7 +3,10 @@
 use crate::ast::Ast;
 
 /// A tree-independent struct for representing the locations of nodes within trees.
-#[derive(Debug, Clone, Eq, PartialEq)]
+///
+/// `Path`s are ordered in the same order as their nodes appear in the tree (i.e. a node comes
+/// before its descendants, which come before its next sibling).
+#[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub struct Path {
     child_indices: Vec<usize>,
 }
@@ -96,6 +99,98 @@ impl Path {
         self.child_indices.iter()
     }
 
+    /// Returns `true` if this path points to a node that exists in the tree with a given root.  Any
+    /// [`Path`] which hasn't come from the tree itself (e.g. one read from a file) should be checked
+    /// with this before it is used.
+    pub fn is_valid_in<'arena, Node: Ast<'arena>>(&self, root: &'arena Node) -> bool {
+        let mut node = root;
+        for &index in self.iter() {
+            match node.children().get(index) {
+                Some(child) => node = child,
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Changes this path as little as possible so that it points to a node that exists in the tree
+    /// with a given root.  Indices past the last child are moved to the last child, and the path
+    /// is cut short at any node with no children.  This is used to restore a cursor which was
+    /// saved before the tree was edited.
+    pub fn clamp_to<'arena, Node: Ast<'arena>>(&mut self, root: &'arena Node) {
+        let mut node = root;
+        for depth in 0..self.child_indices.len() {
+            let children = node.children();
+            if children.is_empty() {
+                self.child_indices.truncate(depth);
+                return;
+            }
+            let index = &mut self.child_indices[depth];
+            *index = (*index).min(children.len() - 1);
+            node = children[*index];
+        }
+    }
+
+    /// Updates this path after the number of children of the node at `parent` has changed by
+    /// `delta` because of an edit to its `index`th child, so that it still points to the same node.
+    /// Paths which don't go through a later child of `parent` are unchanged.  Returns `false` if
+    /// this path's node was removed by the edit (i.e. it was one of the `-delta` children after
+    /// `index` which were removed).
+    pub fn shift_for_edit(&mut self, parent: &Path, index: usize, delta: isize) -> bool {
+        let depth = parent.depth();
+        if self.depth() <= depth || !self.child_indices.starts_with(&parent.child_indices) {
+            return true;
+        }
+        let child_index = &mut self.child_indices[depth];
+        if *child_index <= index {
+            return true;
+        }
+        let shifted_index = *child_index as isize + delta;
+        if shifted_index < index as isize {
+            return false;
+        }
+        *child_index = shifted_index as usize;
+        true
+    }
+
+    /// Moves this path to the node after it in pre-order (i.e. the order that the nodes appear in
+    /// the text), which is its first child if it has one.  Returns `false` and leaves the path
+    /// unchanged if this is the last node in the tree.
+    pub fn preorder_next<'arena, Node: Ast<'arena>>(&mut self, root: &'arena Node) -> bool {
+        if !self.cursor(root).children().is_empty() {
+            self.push(0);
+            return true;
+        }
+        // Otherwise, go to the next sibling of the closest ancestor which has one
+        let mut ancestor = self.clone();
+        while let Some(index) = ancestor.pop() {
+            if index + 1 < ancestor.cursor(root).children().len() {
+                ancestor.push(index + 1);
+                *self = ancestor;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Moves this path to the node before it in pre-order (i.e. the order that the nodes appear
+    /// in the text), which is either its parent or the last descendant of its previous sibling.
+    /// Returns `false` and leaves the path unchanged if this is the root.
+    pub fn preorder_prev<'arena, Node: Ast<'arena>>(&mut self, root: &'arena Node) -> bool {
+        match self.pop() {
+            None => return false,
+            Some(0) => return true,
+            Some(index) => self.push(index - 1),
+        }
+        loop {
+            let child_count = self.cursor(root).children().len();
+            if child_count == 0 {
+                return true;
+            }
+            self.push(child_count - 1);
+        }
+    }
+
     /// Returns an iterator over the AST `Node`s generated when this path is traversed starting
     /// with a given root.
     #[inline]
@@ -103,7 +198,7 @@ impl Path {
     where
         Node: Ast<'arena>,
     {
-        NodeIter::new(root, &self)
+        NodeIter::new(root, self)
     }
 }
 
@@ -247,6 +342,34 @@ mod tests {
         assert_eq!(path.depth(), 0);
     }
 
+    #[test]
+    fn preorder() {
+        let arena = Arena::new();
+        let root = add_value_to_arena(json!([[true, []], {"a": null}]), &arena);
+        let expected_order: Vec<Vec<usize>> = vec![
+            vec![],
+            vec![0],
+            vec![0, 0],
+            vec![0, 1],
+            vec![1],
+            vec![1, 0],
+            vec![1, 0, 0],
+            vec![1, 0, 1],
+        ];
+        let mut path = Path::root();
+        for indices in &expected_order[1..] {
+            assert!(path.preorder_next(root));
+            assert_eq!(path, Path::from_vec(indices.clone()));
+        }
+        assert!(!path.preorder_next(root));
+        for indices in expected_order[..expected_order.len() - 1].iter().rev() {
+            assert!(path.preorder_prev(root));
+            assert_eq!(path, Path::from_vec(indices.clone()));
+        }
+        assert!(!path.preorder_prev(root));
+        assert_eq!(path, Path::root());
+    }
+
     #[test]
     fn node_iter() {
         // Create some test Json and add it to an arena
@@ -345,4 +468,70 @@ mod tests {
         assert_eq!(c.display_name(), "true");
         assert_eq!(p.unwrap().display_name(), "field");
     }
+
+    #[test]
+    fn is_valid_in() {
+        let arena = Arena::new();
+        let root = add_value_to_arena(json!([true, { "value": [] }]), &arena);
+        for (indices, is_valid) in &[
+            (vec![], true),
+            (vec![0], true),
+            (vec![1, 0, 1], true),
+            (vec![2], false),
+            (vec![0, 0], false),
+            (vec![1, 0, 1, 0], false),
+        ] {
+            let path = Path::from_vec(indices.clone());
+            assert_eq!(path.is_valid_in(root), *is_valid, "Testing {:?}", path);
+        }
+    }
+
+    #[test]
+    fn clamp_to() {
+        let arena = Arena::new();
+        let root = add_value_to_arena(json!([true, { "value": [] }]), &arena);
+        for (indices, expected_indices) in &[
+            (vec![], vec![]),
+            (vec![1, 0, 1], vec![1, 0, 1]),
+            (vec![2], vec![1]),
+            (vec![0, 0], vec![0]),
+            (vec![5, 3, 1, 0], vec![1, 0, 1]),
+        ] {
+            let mut path = Path::from_vec(indices.clone());
+            path.clamp_to(root);
+            assert_eq!(path, Path::from_vec(expected_indices.clone()));
+            assert!(path.is_valid_in(root));
+        }
+    }
+
+    #[test]
+    fn shift_for_edit() {
+        let parent = Path::from_vec(vec![1]);
+        for (indices, delta, expected) in &[
+            // Paths which don't go through a later child of the parent don't move
+            (vec![0, 5], 2, Some(vec![0, 5])),
+            (vec![1], 2, Some(vec![1])),
+            (vec![1, 3, 0], 2, Some(vec![1, 3, 0])),
+            (vec![2, 4], -1, Some(vec![2, 4])),
+            // Later children of the parent are shifted along with their descendants
+            (vec![1, 4], 2, Some(vec![1, 6])),
+            (vec![1, 6, 0], -2, Some(vec![1, 4, 0])),
+            (vec![1, 5], -2, Some(vec![1, 3])),
+            // Children which were removed are reported
+            (vec![1, 4], -2, None),
+        ] {
+            let mut path = Path::from_vec(indices.clone());
+            let kept = path.shift_for_edit(&parent, 3, *delta);
+            match expected {
+                Some(expected_indices) => {
+                    assert!(kept, "Testing {:?}", indices);
+                    assert_eq!(path, Path::from_vec(expected_indices.clone()));
+                }
+                None => assert!(!kept, "Testing {:?}", indices),
+            }
+        }
+        // Paths are ordered like the nodes in the tree
+        assert!(Path::from_vec(vec![1]) < Path::from_vec(vec![1, 0]));
+        assert!(Path::from_vec(vec![1, 5]) < Path::from_vec(vec![2]));
+    }
 }
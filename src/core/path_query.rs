@@ -0,0 +1,641 @@
+//! A JSONPath-like query language, which selects a set of nodes from any [`Ast`].
+//!
+//! A query starts at the root (`$`) or at the cursor (`@`), followed by segments which each select
+//! some of the members of the nodes selected so far.  The members of a node are its children,
+//! except that a child without a [`class`](Ast::class) (such as a JSON field) stands for its last
+//! child (the field's value), identified by the child's [`key`](Ast::key).  The segments are:
+//! - `.name` or `['name']`: The member with a given key
+//! - `.*` or `[*]`: Every member
+//! - `[n]`: The `n`th member, counting from the end if `n` is negative
+//! - `[start:end]`: The members from `start` up to (but not including) `end`
+//! - `[?(<query>)]`: The members for which `<query>` (starting from `@`, the member) selects
+//!   something.  `[?(<query> == <value>)]` and `[?(<query> != <value>)]` compare the selected
+//!   nodes to a value, which is a quoted string (compared with the node's [`text`](Ast::text)),
+//!   or a number or a word like `true` (both compared with the node's
+//!   [`display_name`](Ast::display_name))
+//! - `..` followed by any of the above: Applies the segment to the node and all its descendants
+//!
+//! Several selectors can be given in one pair of brackets, separated by commas (e.g. `[0,'a']`).
+
+use super::Path;
+use crate::ast::Ast;
+
+/// A parsed query, which can be [evaluated](PathQuery::evaluate) on a tree to find the paths of
+/// every node that it selects
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct PathQuery {
+    /// `true` if the query starts at the cursor (`@`) rather than the root (`$`)
+    is_relative: bool,
+    segments: Vec<Segment>,
+}
+
+/// One step of a [`PathQuery`], which selects nodes from each of the nodes selected so far
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+enum Segment {
+    /// Selects the members of each node which match any of the [`Selector`]s
+    Child(Vec<Selector>),
+    /// Selects the members of each node and of all its descendants which match any of the
+    /// [`Selector`]s
+    Descendant(Vec<Selector>),
+}
+
+/// Picks some of a node's members
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+enum Selector {
+    /// `*`: Every member
+    Wildcard,
+    /// `name` or `'name'`: The members with a given key
+    Key(String),
+    /// `n`: The `n`th member, counting back from the end if negative
+    Index(isize),
+    /// `start:end`: A range of members, either end of which can be missing or negative
+    Slice(Option<isize>, Option<isize>),
+    /// `?<query>`: The members which satisfy a [`Filter`]
+    Filter(Box<Filter>),
+}
+
+/// A condition on a node, which is satisfied if a [`PathQuery`] starting at that node selects
+/// something (or something which satisfies the comparison)
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+struct Filter {
+    query: PathQuery,
+    comparison: Option<(Comparison, Literal)>,
+}
+
+/// The ways a [`Filter`] can compare nodes to a [`Literal`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+enum Comparison {
+    /// `==`: Satisfied if any selected node equals the literal
+    Equal,
+    /// `!=`: Satisfied if no selected node equals the literal
+    NotEqual,
+}
+
+/// A value which nodes are compared to in a [`Filter`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+enum Literal {
+    /// A quoted string, which equals nodes with the same [`text`](Ast::text)
+    Text(String),
+    /// A number, which equals nodes whose [`display_name`](Ast::display_name) is the same number
+    Number(String),
+    /// A bare word, which equals nodes with the same [`display_name`](Ast::display_name)
+    Word(String),
+}
+
+/// The possible ways that parsing a [`PathQuery`] could fail
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum PathQueryErr {
+    /// The query doesn't start with `$` or `@`
+    MissingStart,
+    /// The query ended part of the way through a segment
+    UnexpectedEnd,
+    /// A character appeared where it isn't allowed, at a given index (in [`char`]s)
+    UnexpectedChar(char, usize),
+    /// A quoted string was never closed
+    UnclosedString,
+    /// A number in the query couldn't be parsed
+    InvalidNumber(String),
+}
+
+impl std::fmt::Display for PathQueryErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathQueryErr::MissingStart => write!(f, "Queries start with '$' or '@'"),
+            PathQueryErr::UnexpectedEnd => write!(f, "Unexpected end of query"),
+            PathQueryErr::UnexpectedChar(c, index) => {
+                write!(f, "Unexpected '{}' at position {} of query", c, index)
+            }
+            PathQueryErr::UnclosedString => write!(f, "Unclosed string in query"),
+            PathQueryErr::InvalidNumber(text) => write!(f, "Invalid number '{}' in query", text),
+        }
+    }
+}
+
+impl std::error::Error for PathQueryErr {}
+
+impl PathQuery {
+    /// Parses a query, which must start with `$` or `@`
+    pub fn parse(query: &str) -> Result<Self, PathQueryErr> {
+        let mut parser = Parser {
+            chars: query.trim().chars().collect(),
+            index: 0,
+        };
+        let query = parser.query()?;
+        match parser.peek() {
+            Some(c) => Err(PathQueryErr::UnexpectedChar(c, parser.index)),
+            None => Ok(query),
+        }
+    }
+
+    /// Returns `true` if this query starts at the cursor (`@`) rather than the root (`$`)
+    pub fn is_relative(&self) -> bool {
+        self.is_relative
+    }
+
+    /// Returns the paths of every node in the tree under `root` which this query selects, in the
+    /// order that they appear in the tree.  `cursor` is the path that `@` refers to.
+    pub fn evaluate<'arena, Node: Ast<'arena>>(
+        &self,
+        root: &'arena Node,
+        cursor: &Path,
+    ) -> Vec<Path> {
+        let mut paths: Vec<Path> = self
+            .select(root, (cursor.clone(), cursor.cursor(root)))
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    /// Returns every node selected by this query (possibly with duplicates), where `current` is
+    /// the node that `@` refers to
+    fn select<'arena, Node: Ast<'arena>>(
+        &self,
+        root: &'arena Node,
+        current: (Path, &'arena Node),
+    ) -> Vec<(Path, &'arena Node)> {
+        let mut nodes = if self.is_relative {
+            vec![current]
+        } else {
+            vec![(Path::root(), root)]
+        };
+        for segment in &self.segments {
+            nodes = nodes
+                .into_iter()
+                .flat_map(|node| segment.select(root, node))
+                .collect();
+        }
+        nodes
+    }
+}
+
+impl Segment {
+    /// Returns the nodes selected by this segment from a single node
+    fn select<'arena, Node: Ast<'arena>>(
+        &self,
+        root: &'arena Node,
+        node: (Path, &'arena Node),
+    ) -> Vec<(Path, &'arena Node)> {
+        let (selectors, nodes) = match self {
+            Segment::Child(selectors) => (selectors, vec![node]),
+            Segment::Descendant(selectors) => {
+                let mut nodes = Vec::new();
+                add_descendants(node, &mut nodes);
+                (selectors, nodes)
+            }
+        };
+        let mut selected = Vec::new();
+        for (path, node) in nodes {
+            let members = members(&path, node);
+            for selector in selectors {
+                selected.extend(selector.select(root, &members));
+            }
+        }
+        selected
+    }
+}
+
+impl Selector {
+    /// Returns the `members` of a node which this `Selector` picks
+    fn select<'arena, Node: Ast<'arena>>(
+        &self,
+        root: &'arena Node,
+        members: &[Member<'arena, Node>],
+    ) -> Vec<(Path, &'arena Node)> {
+        let len = members.len() as isize;
+        // Negative indices count back from the end
+        let bound = |index: isize| -> usize {
+            if index < 0 {
+                (len + index).max(0) as usize
+            } else {
+                index.min(len) as usize
+            }
+        };
+        let picked: Vec<&Member<'arena, Node>> = match self {
+            Selector::Wildcard => members.iter().collect(),
+            Selector::Key(name) => members
+                .iter()
+                .filter(|member| member.key == Some(name.as_str()))
+                .collect(),
+            Selector::Index(index) if -len <= *index && *index < len => {
+                vec![&members[bound(*index)]]
+            }
+            Selector::Index(_) => Vec::new(),
+            Selector::Slice(start, end) => {
+                let start = start.map_or(0, bound);
+                let end = end.map_or(members.len(), bound);
+                members[start..end.max(start)].iter().collect()
+            }
+            Selector::Filter(filter) => members
+                .iter()
+                .filter(|member| filter.is_satisfied(root, (member.path.clone(), member.node)))
+                .collect(),
+        };
+        picked
+            .into_iter()
+            .map(|member| (member.path.clone(), member.node))
+            .collect()
+    }
+}
+
+impl Filter {
+    /// Returns `true` if a given node satisfies this `Filter`
+    fn is_satisfied<'arena, Node: Ast<'arena>>(
+        &self,
+        root: &'arena Node,
+        node: (Path, &'arena Node),
+    ) -> bool {
+        let selected = self.query.select(root, node);
+        match &self.comparison {
+            None => !selected.is_empty(),
+            Some((comparison, literal)) => {
+                let is_any_equal = selected.iter().any(|(_, node)| literal.equals(*node));
+                is_any_equal == (*comparison == Comparison::Equal)
+            }
+        }
+    }
+}
+
+impl Literal {
+    /// Returns `true` if a given node equals this `Literal`
+    fn equals<'arena, Node: Ast<'arena>>(&self, node: &'arena Node) -> bool {
+        match self {
+            Literal::Text(text) => node.text() == Some(text.as_str()),
+            // The display names of strings are quoted, so they don't equal numbers
+            Literal::Number(number) => match node.display_name().parse::<f64>() {
+                Ok(value) => number.parse() == Ok(value),
+                Err(_) => false,
+            },
+            Literal::Word(word) => node.display_name() == *word,
+        }
+    }
+}
+
+/// One of the members of a node, which [`Selector`]s pick from
+struct Member<'arena, Node> {
+    path: Path,
+    node: &'arena Node,
+    key: Option<&'arena str>,
+}
+
+/// Returns the members of the node at `path`.  A child without a class (e.g. a JSON field) can't
+/// exist on its own, so it stands for its last child (e.g. the field's value).
+fn members<'arena, Node: Ast<'arena>>(
+    path: &Path,
+    node: &'arena Node,
+) -> Vec<Member<'arena, Node>> {
+    node.children()
+        .iter()
+        .enumerate()
+        .map(|(index, child)| {
+            let mut member_path = path.clone();
+            member_path.push(index);
+            let mut member = *child;
+            if let (None, Some(value)) = (child.class(), child.children().last()) {
+                member_path.push(child.children().len() - 1);
+                member = *value;
+            }
+            Member {
+                path: member_path,
+                node: member,
+                key: child.key(),
+            }
+        })
+        .collect()
+}
+
+/// Adds a node and all the members of its members (recursively) to `nodes`, in pre-order
+fn add_descendants<'arena, Node: Ast<'arena>>(
+    (path, node): (Path, &'arena Node),
+    nodes: &mut Vec<(Path, &'arena Node)>,
+) {
+    let members = members(&path, node);
+    nodes.push((path, node));
+    for member in members {
+        add_descendants((member.path, member.node), nodes);
+    }
+}
+
+/// A recursive descent parser for [`PathQuery`]s
+struct Parser {
+    chars: Vec<char>,
+    /// The index of the next [`char`] to be parsed
+    index: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    /// Consumes the next [`char`] if it is `c`, returning `true` if it was
+    fn eat(&mut self, c: char) -> bool {
+        let is_match = self.peek() == Some(c);
+        if is_match {
+            self.index += 1;
+        }
+        is_match
+    }
+
+    /// Consumes the next [`char`], failing if it isn't `c`
+    fn expect(&mut self, c: char) -> Result<(), PathQueryErr> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    /// Returns the error for finding the next [`char`] where it isn't allowed
+    fn unexpected(&self) -> PathQueryErr {
+        match self.peek() {
+            Some(c) => PathQueryErr::UnexpectedChar(c, self.index),
+            None => PathQueryErr::UnexpectedEnd,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.index += 1;
+        }
+    }
+
+    /// Consumes [`char`]s for as long as they satisfy `predicate`, returning them as a `String`
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let start = self.index;
+        while self.peek().is_some_and(&predicate) {
+            self.index += 1;
+        }
+        self.chars[start..self.index].iter().collect()
+    }
+
+    /// Parses a whole [`PathQuery`], stopping at the first [`char`] which can't continue it
+    fn query(&mut self) -> Result<PathQuery, PathQueryErr> {
+        let is_relative = match self.peek() {
+            Some('$') => false,
+            Some('@') => true,
+            _ => return Err(PathQueryErr::MissingStart),
+        };
+        self.index += 1;
+        let mut segments = Vec::new();
+        loop {
+            let segment = if self.eat('.') {
+                if self.eat('.') {
+                    let selectors = if self.eat('[') {
+                        self.bracketed_selectors()?
+                    } else {
+                        vec![self.dotted_selector()?]
+                    };
+                    Segment::Descendant(selectors)
+                } else {
+                    Segment::Child(vec![self.dotted_selector()?])
+                }
+            } else if self.eat('[') {
+                Segment::Child(self.bracketed_selectors()?)
+            } else {
+                break;
+            };
+            segments.push(segment);
+        }
+        Ok(PathQuery {
+            is_relative,
+            segments,
+        })
+    }
+
+    /// Parses the [`Selector`] after a `.`, which is a name or `*`
+    fn dotted_selector(&mut self) -> Result<Selector, PathQueryErr> {
+        if self.eat('*') {
+            return Ok(Selector::Wildcard);
+        }
+        let name = self.name();
+        if name.is_empty() {
+            Err(self.unexpected())
+        } else {
+            Ok(Selector::Key(name))
+        }
+    }
+
+    fn name(&mut self) -> String {
+        self.take_while(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    }
+
+    /// Parses the comma-separated [`Selector`]s after a `[`, up to and including the `]`
+    fn bracketed_selectors(&mut self) -> Result<Vec<Selector>, PathQueryErr> {
+        let mut selectors = Vec::new();
+        loop {
+            self.skip_whitespace();
+            selectors.push(self.selector()?);
+            self.skip_whitespace();
+            if self.eat(']') {
+                return Ok(selectors);
+            }
+            self.expect(',')?;
+        }
+    }
+
+    /// Parses a [`Selector`] inside brackets
+    fn selector(&mut self) -> Result<Selector, PathQueryErr> {
+        match self.peek() {
+            Some('*') => {
+                self.index += 1;
+                Ok(Selector::Wildcard)
+            }
+            Some('\'') | Some('"') => Ok(Selector::Key(self.string()?)),
+            Some('?') => {
+                self.index += 1;
+                Ok(Selector::Filter(Box::new(self.filter()?)))
+            }
+            _ => {
+                let start = self.integer()?;
+                if self.eat(':') {
+                    Ok(Selector::Slice(start, self.integer()?))
+                } else {
+                    start.map(Selector::Index).ok_or_else(|| self.unexpected())
+                }
+            }
+        }
+    }
+
+    /// Parses an optional (possibly negative) integer
+    fn integer(&mut self) -> Result<Option<isize>, PathQueryErr> {
+        self.skip_whitespace();
+        let start = self.index;
+        self.eat('-');
+        self.take_while(|c| c.is_ascii_digit());
+        let text: String = self.chars[start..self.index].iter().collect();
+        self.skip_whitespace();
+        if text.is_empty() {
+            return Ok(None);
+        }
+        text.parse()
+            .map(Some)
+            .map_err(|_| PathQueryErr::InvalidNumber(text))
+    }
+
+    /// Parses a string quoted by `'` or `"`, where `\` escapes the next [`char`]
+    fn string(&mut self) -> Result<String, PathQueryErr> {
+        let quote = self.peek();
+        self.index += 1;
+        let mut string = String::new();
+        loop {
+            let c = self.peek().ok_or(PathQueryErr::UnclosedString)?;
+            self.index += 1;
+            if Some(c) == quote {
+                return Ok(string);
+            }
+            if c == '\\' {
+                string.push(self.peek().ok_or(PathQueryErr::UnclosedString)?);
+                self.index += 1;
+            } else {
+                string.push(c);
+            }
+        }
+    }
+
+    /// Parses the [`Filter`] after a `?`, which can be wrapped in parentheses
+    fn filter(&mut self) -> Result<Filter, PathQueryErr> {
+        self.skip_whitespace();
+        let has_parens = self.eat('(');
+        self.skip_whitespace();
+        let query = self.query()?;
+        self.skip_whitespace();
+        let comparison = match (self.peek(), self.chars.get(self.index + 1)) {
+            (Some('='), Some('=')) => Some(Comparison::Equal),
+            (Some('!'), Some('=')) => Some(Comparison::NotEqual),
+            _ => None,
+        };
+        let comparison = match comparison {
+            Some(comparison) => {
+                self.index += 2;
+                self.skip_whitespace();
+                Some((comparison, self.literal()?))
+            }
+            None => None,
+        };
+        self.skip_whitespace();
+        if has_parens {
+            self.expect(')')?;
+        }
+        Ok(Filter { query, comparison })
+    }
+
+    /// Parses the [`Literal`] on the right of a comparison
+    fn literal(&mut self) -> Result<Literal, PathQueryErr> {
+        match self.peek() {
+            Some('\'') | Some('"') => Ok(Literal::Text(self.string()?)),
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let number = self.take_while(|c| c.is_ascii_digit() || "+-.eE".contains(c));
+                match number.parse::<f64>() {
+                    Ok(_) => Ok(Literal::Number(number)),
+                    Err(_) => Err(PathQueryErr::InvalidNumber(number)),
+                }
+            }
+            _ => {
+                let word = self.name();
+                if word.is_empty() {
+                    Err(self.unexpected())
+                } else {
+                    Ok(Literal::Word(word))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PathQuery, PathQueryErr};
+    use crate::arena::Arena;
+    use crate::ast::json::add_value_to_arena;
+    use crate::core::Path;
+
+    use serde_json::json;
+
+    fn paths(indices: &[&[usize]]) -> Vec<Path> {
+        indices.iter().map(|i| Path::from_vec(i.to_vec())).collect()
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (query, expected_err) in &[
+            ("services", PathQueryErr::MissingStart),
+            ("", PathQueryErr::MissingStart),
+            ("$.", PathQueryErr::UnexpectedEnd),
+            ("$[0", PathQueryErr::UnexpectedEnd),
+            ("$[0]x", PathQueryErr::UnexpectedChar('x', 4)),
+            ("$.a b", PathQueryErr::UnexpectedChar(' ', 3)),
+            ("$['a]", PathQueryErr::UnclosedString),
+            (
+                "$[?(@.a == 1.2.3)]",
+                PathQueryErr::InvalidNumber("1.2.3".to_owned()),
+            ),
+            ("$[?(@.a]", PathQueryErr::UnexpectedChar(']', 7)),
+        ] {
+            println!("Testing {:?}", query);
+            assert_eq!(PathQuery::parse(query).as_ref(), Err(expected_err));
+        }
+    }
+
+    #[test]
+    fn evaluate() {
+        let arena = Arena::new();
+        let root = add_value_to_arena(
+            json!({
+                "services": [
+                    {"name": "web", "timeout": 30, "enabled": true},
+                    {"name": "db", "enabled": false},
+                    {"name": "cache", "timeout": 5.0}
+                ],
+                "timeout": 10
+            }),
+            &arena,
+        );
+        let evaluate = |query: &str, cursor: &[usize]| {
+            PathQuery::parse(query)
+                .unwrap()
+                .evaluate(root, &Path::from_vec(cursor.to_vec()))
+        };
+        for (query, expected_paths) in &[
+            ("$", paths(&[&[]])),
+            ("$.services", paths(&[&[0, 1]])),
+            (
+                "$['services'][*]",
+                paths(&[&[0, 1, 0], &[0, 1, 1], &[0, 1, 2]]),
+            ),
+            (
+                "$.services[*].timeout",
+                paths(&[&[0, 1, 0, 1, 1], &[0, 1, 2, 1, 1]]),
+            ),
+            (
+                "$..timeout",
+                paths(&[&[0, 1, 0, 1, 1], &[0, 1, 2, 1, 1], &[1, 1]]),
+            ),
+            ("$.services[-1].name", paths(&[&[0, 1, 2, 0, 1]])),
+            ("$.services[5]", paths(&[])),
+            ("$.services[:2]", paths(&[&[0, 1, 0], &[0, 1, 1]])),
+            ("$.services[1:]", paths(&[&[0, 1, 1], &[0, 1, 2]])),
+            ("$.services[2, 0]", paths(&[&[0, 1, 0], &[0, 1, 2]])),
+            (
+                "$.services[?(@.timeout)].name",
+                paths(&[&[0, 1, 0, 0, 1], &[0, 1, 2, 0, 1]]),
+            ),
+            ("$.services[?@.enabled == true]", paths(&[&[0, 1, 0]])),
+            (
+                "$.services[?(@.enabled != true)]",
+                paths(&[&[0, 1, 1], &[0, 1, 2]]),
+            ),
+            ("$.services[?(@.name == 'db')]", paths(&[&[0, 1, 1]])),
+            ("$.services[?(@.timeout == 5)]", paths(&[&[0, 1, 2]])),
+            ("$..[?(@ == 10)]", paths(&[&[1, 1]])),
+            // Queries can start from the cursor
+            ("@.name", paths(&[&[0, 1, 1, 0, 1]])),
+            ("@", paths(&[&[0, 1, 1]])),
+        ] {
+            println!("Testing {:?}", query);
+            assert_eq!(&evaluate(query, &[0, 1, 1]), expected_paths);
+        }
+    }
+}
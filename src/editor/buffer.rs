@@ -0,0 +1,158 @@
+//! Buffers, each of which holds one tree that is open in the editor (usually the contents of a
+//! file).
+
+use super::dag::{Dag, DetachedDag, Detacher};
+use super::{load_dag, undo_file, write_atomically, FileError};
+use crate::arena::Arena;
+use crate::ast::Ast;
+use crate::config::Config;
+
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+/// A tree which is open in the editor.  Each `Buffer` has its own history and cursor (both stored
+/// in its [`Dag`]) and file.  A `Buffer` is shown on the screen by one or more
+/// [`Window`](super::window::Window)s, which each have their own format style and scroll position.
+pub struct Buffer<'arena, Node: Ast<'arena>> {
+    /// The `Dag` that is storing the tree and history of this `Buffer`
+    pub tree: Dag<'arena, Node>,
+    /// The path of the file being edited, or `None` if the tree hasn't been given a file yet
+    pub file_path: Option<PathBuf>,
+}
+
+impl<'arena, Node: Ast<'arena>> Buffer<'arena, Node> {
+    /// Creates a new `Buffer` which is editing a given tree
+    pub fn new(tree: Dag<'arena, Node>, file_path: Option<PathBuf>) -> Self {
+        Buffer { tree, file_path }
+    }
+
+    /// Returns the name of this `Buffer` as shown to the user, which is the path of its file (or
+    /// `[No Name]` if it doesn't have one)
+    pub fn name(&self) -> Cow<'_, str> {
+        match &self.file_path {
+            Some(path) => path.to_string_lossy(),
+            None => Cow::from("[No Name]"),
+        }
+    }
+
+    /* ===== FILE I/O ===== */
+
+    /// Writes the tree (printed in `format_style`) to `path`, or to the file being edited if `path`
+    /// is `None`, returning the path that was written to.  If the buffer doesn't have a file yet,
+    /// then `path` becomes the file being edited.  If `use_undo_file` is `true`, the
+    /// [undo file](undo_file) is also written.
+    pub fn write(
+        &mut self,
+        path: Option<PathBuf>,
+        format_style: &Node::FormatStyle,
+        use_undo_file: bool,
+    ) -> Result<PathBuf, FileError> {
+        let path = path
+            .or_else(|| self.file_path.clone())
+            .ok_or(FileError::NoFilePath)?;
+        let mut content = self.tree.to_text(format_style);
+        // Force the file to finish with a newline
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+        write_atomically(&path, &content).map_err(|e| FileError::Io(path.clone(), e))?;
+        if self.file_path.is_none() {
+            self.file_path = Some(path.clone());
+        }
+        // Writing a copy of the tree to some other file doesn't save the file being edited
+        if self.file_path.as_ref() == Some(&path) {
+            self.tree.mark_saved();
+            // The file has already been written, so failing to write the undo file shouldn't
+            // cause the whole write to fail
+            if use_undo_file {
+                if let Err(e) = undo_file::write(&self.tree, &path) {
+                    log::warn!("{}", e);
+                }
+            }
+        }
+        Ok(path)
+    }
+
+    /// Replaces the tree with the contents of the file at `path`, which becomes the file being
+    /// edited.  The undo history of the old tree is discarded (and replaced by the new file's undo
+    /// file, if enabled).  If no file exists at `path`, then the tree is replaced with an empty tree
+    /// which will be written to `path` on the next write.
+    pub fn open(&mut self, path: PathBuf, config: &Config) -> Result<(), FileError> {
+        self.tree = load_dag(&path, self.tree.arena(), config.undo_file)?;
+        self.tree.set_undo_levels(Some(config.undo_levels));
+        self.file_path = Some(path);
+        Ok(())
+    }
+
+    /* ===== COMPACTION ===== */
+
+    /// Copies the history of this `Buffer` out of its arena (see [`Detacher`])
+    pub fn detach(self, detacher: &mut Detacher<'arena, Node>) -> DetachedBuffer {
+        DetachedBuffer {
+            tree: detacher.detach_dag(&self.tree),
+            file_path: self.file_path,
+        }
+    }
+}
+
+/// The parts of a [`Buffer`] which are kept when the arena is compacted
+pub struct DetachedBuffer {
+    tree: DetachedDag,
+    file_path: Option<PathBuf>,
+}
+
+impl DetachedBuffer {
+    /// Re-creates the [`Buffer`] in a new arena, using the `nodes` returned by
+    /// [`DetachedNodes::attach`](super::dag::DetachedNodes::attach)
+    pub fn attach<'arena, Node: Ast<'arena>>(
+        self,
+        arena: &'arena Arena<Node>,
+        nodes: &[&'arena Node],
+    ) -> Buffer<'arena, Node> {
+        Buffer::new(self.tree.attach(arena, nodes), self.file_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Buffer;
+    use crate::arena::Arena;
+    use crate::ast::json::{add_value_to_arena, Json, JsonFormat};
+    use crate::config::Config;
+    use crate::core::Path;
+    use crate::editor::dag::{Dag, Insertable};
+
+    #[test]
+    fn write_and_open() {
+        let dir = std::env::temp_dir().join(format!("sapling-buffer-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(serde_json::json!([true]), &arena);
+        let tree = Dag::new(&arena, root, Path::from_vec(vec![0]));
+        let mut buffer = Buffer::new(tree, None);
+        assert_eq!(buffer.name(), "[No Name]");
+        // Writing a buffer without a file gives it one
+        buffer
+            .tree
+            .replace_cursor(1, Insertable::CountedNode(1, 'n'))
+            .unwrap();
+        let path = dir.join("a.json");
+        assert_eq!(
+            buffer
+                .write(Some(path.clone()), &JsonFormat::Compact, false)
+                .unwrap(),
+            path
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[null]\n");
+        assert_eq!(buffer.name(), path.to_string_lossy());
+        assert!(!buffer.tree.is_modified());
+        // Opening a file replaces the tree and the history
+        let other_path = dir.join("b.json");
+        std::fs::write(&other_path, "{}").unwrap();
+        buffer.open(other_path.clone(), &Config::default()).unwrap();
+        assert_eq!(*buffer.tree.root(), serde_json::json!({}));
+        assert_eq!(buffer.file_path, Some(other_path));
+        assert_eq!(buffer.tree.history().len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
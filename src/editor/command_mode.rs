@@ -0,0 +1,638 @@
+//! The code for 'command-mode', similar to Vim's command-line mode (entered by typing `:`)
+
+use super::window::SplitDirection;
+use super::{
+    dag::Dag, keystroke_log::Category, multi_cursor, normal_mode, search, state, undo_tree, Editor,
+};
+use crate::ast::Ast;
+use crate::core::{PathQuery, PathQueryErr, Side};
+
+use std::borrow::Cow;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use tuikit::prelude::Key;
+
+/// The [`State`](state::State) which Sapling is in whilst the user is typing a command into the
+/// command line.
+#[derive(Debug, Clone, Default)]
+pub struct State {
+    /// The command typed so far (not including the leading `:`)
+    command: String,
+}
+
+impl<'arena, Node: Ast<'arena>> state::State<'arena, Node> for State {
+    fn transition(
+        mut self: Box<Self>,
+        key: Key,
+        editor: &mut Editor<'arena, Node>,
+    ) -> (
+        Box<dyn state::State<'arena, Node>>,
+        Option<(String, Category)>,
+    ) {
+        match key {
+            Key::Char(c) => {
+                self.command.push(c);
+                (self, None)
+            }
+            // Deleting from an empty command line leaves command mode, like in Vim
+            Key::Backspace if self.command.is_empty() => (
+                Box::new(normal_mode::State::default()),
+                Some(("leave command mode".to_owned(), Category::Undefined)),
+            ),
+            Key::Backspace => {
+                self.command.pop();
+                (self, None)
+            }
+            Key::ESC => (
+                Box::new(normal_mode::State::default()),
+                Some(("leave command mode".to_owned(), Category::Undefined)),
+            ),
+            Key::Enter => {
+                let (new_state, log_entry) = match parse_command(&self.command) {
+                    Ok(command) => execute_command(command, editor),
+                    Err(e) => (
+                        Box::new(normal_mode::State::default()) as Box<dyn state::State<_>>,
+                        (e.to_string(), Category::Undefined),
+                    ),
+                };
+                (new_state, Some(log_entry))
+            }
+            _ => (self, None),
+        }
+    }
+
+    fn keystroke_buffer(&self) -> Cow<'_, str> {
+        Cow::from("-- COMMAND --")
+    }
+
+    fn command_line(&self) -> Option<(char, &str)> {
+        Some((':', &self.command))
+    }
+}
+
+/// Run a [`Command`] on the [`Editor`], returning the [`State`](state::State) that Sapling should
+/// move into and a log entry describing what happened.
+fn execute_command<'arena, Node: Ast<'arena>>(
+    command: Command,
+    editor: &mut Editor<'arena, Node>,
+) -> (Box<dyn state::State<'arena, Node>>, (String, Category)) {
+    let normal_mode = Box::new(normal_mode::State::default());
+    match command {
+        Command::Write(path) => match editor.write(path) {
+            Ok(path) => (normal_mode, (format!("write to {:?}", path), Category::IO)),
+            Err(e) => (normal_mode, (e.to_string(), Category::IO)),
+        },
+        // Like in Vim, quitting only closes the current window if there are several.  The window's
+        // buffer stays open, so this can't lose any changes.
+        Command::Quit { force } => {
+            if editor.close_window() {
+                (normal_mode, ("close window".to_owned(), Category::Quit))
+            } else {
+                quit(editor, force)
+            }
+        }
+        Command::QuitAll { force } => quit(editor, force),
+        Command::WriteQuit => match editor.write(None) {
+            Ok(_) => {
+                if editor.close_window() {
+                    (
+                        normal_mode,
+                        ("write and close window".to_owned(), Category::Quit),
+                    )
+                } else {
+                    // Other buffers might still have unsaved changes
+                    match editor.unsaved_changes_message() {
+                        Some(message) => (normal_mode, (message, Category::Quit)),
+                        None => (
+                            Box::new(state::Quit),
+                            ("write and quit".to_owned(), Category::Quit),
+                        ),
+                    }
+                }
+            }
+            // If the write failed, then we shouldn't quit because doing so would lose the user's
+            // changes
+            Err(e) => (normal_mode, (e.to_string(), Category::IO)),
+        },
+        Command::Edit { force: false, .. } if editor.buffer().tree.is_modified() => (
+            normal_mode,
+            (
+                "unsaved changes (use ':e!' to discard them)".to_owned(),
+                Category::IO,
+            ),
+        ),
+        Command::Edit { path, .. } => match editor.open(path.clone()) {
+            Ok(()) => (normal_mode, (format!("open {:?}", path), Category::IO)),
+            Err(e) => (normal_mode, (e.to_string(), Category::IO)),
+        },
+        Command::Set { option, value } => match editor.set_option(&option, &value) {
+            Ok(()) => (
+                normal_mode,
+                (
+                    format!("set {} to '{}'", option, value),
+                    Category::Undefined,
+                ),
+            ),
+            Err(e) => (normal_mode, (e, Category::Undefined)),
+        },
+        Command::UndoList => (
+            normal_mode,
+            (
+                undo_list(&editor.buffer().tree, SystemTime::now()),
+                Category::History,
+            ),
+        ),
+        Command::Undo(index) => {
+            let message = match editor.buffer_mut().tree.jump_to_snapshot(index) {
+                Ok(_) => format!("go to snapshot {}", index),
+                Err(_) => format!("there is no snapshot {}", index),
+            };
+            (normal_mode, (message, Category::History))
+        }
+        Command::Compact => {
+            // The arena can only be compacted between keystrokes, so this just asks the editor to
+            // do it once the command has finished
+            editor.compaction_requested = true;
+            (
+                normal_mode,
+                ("compact arena".to_owned(), Category::Undefined),
+            )
+        }
+        Command::ListBuffers => (normal_mode, (editor.buffer_list(), Category::IO)),
+        Command::NextBuffer | Command::PrevBuffer => {
+            editor.cycle_buffer(if command == Command::NextBuffer {
+                1
+            } else {
+                -1
+            });
+            (normal_mode, (switch_message(editor), Category::IO))
+        }
+        Command::Buffer(number) => {
+            let message = if editor.switch_to_buffer(number) {
+                switch_message(editor)
+            } else {
+                format!("there is no buffer {}", number)
+            };
+            (normal_mode, (message, Category::IO))
+        }
+        Command::Split(direction) => {
+            let message = if editor.split_window(direction) {
+                match direction {
+                    SplitDirection::Horizontal => "split window",
+                    SplitDirection::Vertical => "split window vertically",
+                }
+            } else {
+                "not enough room to split the window"
+            };
+            (normal_mode, (message.to_owned(), Category::Move))
+        }
+        Command::Close => {
+            let message = if editor.close_window() {
+                "close window"
+            } else {
+                "can't close the last window"
+            };
+            (normal_mode, (message.to_owned(), Category::Move))
+        }
+        Command::Only => {
+            editor.close_other_windows();
+            (
+                normal_mode,
+                ("close other windows".to_owned(), Category::Move),
+            )
+        }
+        // Every selected node gets a cursor, so that edits are made at all of them
+        Command::Select(query) => {
+            let tree = &mut editor.buffer_mut().tree;
+            let mut paths = query.evaluate(tree.root(), tree.cursor_path());
+            if paths.is_empty() {
+                return (
+                    normal_mode,
+                    ("no nodes match the query".to_owned(), Category::Undefined),
+                );
+            }
+            let message = match paths.len() {
+                1 => "select 1 node".to_owned(),
+                count => format!("select {} nodes", count),
+            };
+            tree.set_cursor_path(paths.remove(0));
+            if paths.is_empty() {
+                (normal_mode, (message, Category::Move))
+            } else {
+                (
+                    Box::new(multi_cursor::State::new(paths)),
+                    (message, Category::Move),
+                )
+            }
+        }
+        Command::Find(pattern) => (normal_mode, search::search(editor, pattern, Side::Next)),
+        Command::AddBuffer(path) => match editor.add_buffer(path.clone()) {
+            Ok(number) => (
+                normal_mode,
+                (format!("add {:?} as buffer {}", path, number), Category::IO),
+            ),
+            Err(e) => (normal_mode, (e.to_string(), Category::IO)),
+        },
+    }
+}
+
+/// Quits Sapling, unless a [`Buffer`](super::buffer::Buffer) has unsaved changes and the quit
+/// isn't forced
+fn quit<'arena, Node: Ast<'arena>>(
+    editor: &Editor<'arena, Node>,
+    force: bool,
+) -> (Box<dyn state::State<'arena, Node>>, (String, Category)) {
+    match editor.unsaved_changes_message() {
+        Some(message) if !force => (
+            Box::new(normal_mode::State::default()),
+            (message, Category::Quit),
+        ),
+        _ => (
+            Box::new(state::Quit),
+            ("quit Sapling".to_owned(), Category::Quit),
+        ),
+    }
+}
+
+/// Describes the [`Buffer`](super::buffer::Buffer) that the editor has just switched to
+fn switch_message<'arena, Node: Ast<'arena>>(editor: &Editor<'arena, Node>) -> String {
+    format!(
+        "go to buffer {} ({})",
+        editor.current_buffer() + 1,
+        editor.buffer().name()
+    )
+}
+
+/// Describes the tips of every branch of a [`Dag`]'s undo tree (i.e. the snapshots that haven't
+/// been edited).  Each tip is given as its snapshot number (as used by `:undo <n>`), the number
+/// of changes made since the original tree, and how long before `now` it was made.
+fn undo_list<'arena, Node: Ast<'arena>>(dag: &Dag<'arena, Node>, now: SystemTime) -> String {
+    let history = dag.history();
+    let branches = dag
+        .branch_tips()
+        .into_iter()
+        .map(|index| {
+            let mut changes = 0;
+            let mut snapshot = &history[index];
+            while let Some(parent) = snapshot.parent {
+                changes += 1;
+                snapshot = &history[parent];
+            }
+            format!(
+                "{} ({} {}, {} ago)",
+                index,
+                changes,
+                if changes == 1 { "change" } else { "changes" },
+                undo_tree::format_age(history[index].time, now)
+            )
+        })
+        .collect::<Vec<_>>();
+    format!(
+        "branches: {}; at snapshot {}",
+        branches.join(", "),
+        dag.history_index()
+    )
+}
+
+/// A single command that can be run from command mode
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Command {
+    /// `:w [path]`: Write the tree to a given path, or to the current file if no path is given
+    Write(Option<PathBuf>),
+    /// `:q` or `:q!`: Close the current window, or quit Sapling if there is only one window
+    Quit {
+        /// `true` if the quit was forced (i.e. `:q!`)
+        force: bool,
+    },
+    /// `:qa` or `:qa!`: Quit Sapling, however many windows are open
+    QuitAll {
+        /// `true` if the quit was forced (i.e. `:qa!`)
+        force: bool,
+    },
+    /// `:wq` or `:x`: Write the tree to the current file, then close the current window (quitting
+    /// Sapling if it's the only window)
+    WriteQuit,
+    /// `:e <path>` or `:e! <path>`: Open the file at a given path, replacing the current tree
+    Edit {
+        /// The path of the file to open
+        path: PathBuf,
+        /// `true` if unsaved changes to the current tree should be discarded (i.e. `:e!`)
+        force: bool,
+    },
+    /// `:set <option>=<value>`: Set a user-configurable option
+    Set {
+        /// The name of the option being set
+        option: String,
+        /// The new value of that option
+        value: String,
+    },
+    /// `:undolist`: List the branches of the undo tree
+    UndoList,
+    /// `:undo <n>`: Go to the snapshot with a given number in the undo tree
+    Undo(usize),
+    /// `:compact`: Free the memory used by nodes which are no longer part of the undo tree or any
+    /// register
+    Compact,
+    /// `:ls`: List the open buffers
+    ListBuffers,
+    /// `:bn`: Go to the next buffer
+    NextBuffer,
+    /// `:bp`: Go to the previous buffer
+    PrevBuffer,
+    /// `:b <n>`: Go to the buffer with a given number (as shown by `:ls`)
+    Buffer(usize),
+    /// `:badd <path>`: Open the file at a given path in a new buffer, without switching to it
+    AddBuffer(PathBuf),
+    /// `:split` or `:vsplit`: Split the current window in two
+    Split(SplitDirection),
+    /// `:close`: Close the current window
+    Close,
+    /// `:only`: Close every window except the current one
+    Only,
+    /// `:select <query>`: Put a cursor on every node selected by a [`PathQuery`]
+    Select(PathQuery),
+    /// `:find <pattern>`: Search forwards for a pattern, like typing `/<pattern>`
+    Find(String),
+}
+
+/// The possible ways that parsing a [`Command`] could fail
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum CommandErr {
+    /// The command line was empty
+    Empty,
+    /// The command name isn't a known command
+    UnknownCommand(String),
+    /// The command requires an argument, but none was given
+    MissingArgument(&'static str),
+    /// The command was given an argument that it doesn't take
+    UnexpectedArgument(String),
+    /// The argument to `:set` isn't of the form `<option>=<value>`
+    InvalidSetArgument(String),
+    /// The command expected a number as its argument
+    InvalidNumber(String),
+    /// The command expected a [`PathQuery`] as its argument
+    InvalidQuery(PathQueryErr),
+}
+
+impl std::fmt::Display for CommandErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandErr::Empty => write!(f, "No command given"),
+            CommandErr::UnknownCommand(name) => write!(f, "Not a command: '{}'", name),
+            CommandErr::MissingArgument(name) => write!(f, "Expected an argument for '{}'", name),
+            CommandErr::UnexpectedArgument(arg) => write!(f, "Unexpected argument '{}'", arg),
+            CommandErr::InvalidSetArgument(arg) => {
+                write!(f, "Expected '<option>=<value>', got '{}'", arg)
+            }
+            CommandErr::InvalidNumber(arg) => write!(f, "Expected a number, got '{}'", arg),
+            CommandErr::InvalidQuery(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for CommandErr {}
+
+/// Parse a command line (without the leading `:`) into a [`Command`]
+pub fn parse_command(command_line: &str) -> Result<Command, CommandErr> {
+    let command_line = command_line.trim();
+    // Split the command line into the command name and the (optional) argument
+    let (name, argument) = match command_line.find(char::is_whitespace) {
+        Some(index) => (
+            &command_line[..index],
+            Some(command_line[index..].trim_start()),
+        ),
+        None => (command_line, None),
+    };
+    // Helper closure that fails if the command was given an argument
+    let no_argument = |command: Command| match argument {
+        Some(arg) => Err(CommandErr::UnexpectedArgument(arg.to_owned())),
+        None => Ok(command),
+    };
+
+    match name {
+        "" => Err(CommandErr::Empty),
+        "w" | "write" => Ok(Command::Write(argument.map(PathBuf::from))),
+        "q" | "quit" => no_argument(Command::Quit { force: false }),
+        "q!" | "quit!" => no_argument(Command::Quit { force: true }),
+        "qa" | "qall" => no_argument(Command::QuitAll { force: false }),
+        "qa!" | "qall!" => no_argument(Command::QuitAll { force: true }),
+        "wq" | "x" => no_argument(Command::WriteQuit),
+        "e" | "edit" | "e!" | "edit!" => argument
+            .map(|path| Command::Edit {
+                path: PathBuf::from(path),
+                force: name.ends_with('!'),
+            })
+            .ok_or(CommandErr::MissingArgument("e")),
+        "set" => {
+            let argument = argument.ok_or(CommandErr::MissingArgument("set"))?;
+            let equals_index = argument
+                .find('=')
+                .ok_or_else(|| CommandErr::InvalidSetArgument(argument.to_owned()))?;
+            Ok(Command::Set {
+                option: argument[..equals_index].trim().to_owned(),
+                value: argument[equals_index + 1..].trim().to_owned(),
+            })
+        }
+        "undol" | "undolist" => no_argument(Command::UndoList),
+        "compact" => no_argument(Command::Compact),
+        "ls" | "buffers" | "files" => no_argument(Command::ListBuffers),
+        "bn" | "bnext" => no_argument(Command::NextBuffer),
+        "bp" | "bprevious" | "bN" | "bNext" => no_argument(Command::PrevBuffer),
+        "b" | "buffer" => {
+            let argument = argument.ok_or(CommandErr::MissingArgument("buffer"))?;
+            argument
+                .parse()
+                .map(Command::Buffer)
+                .map_err(|_| CommandErr::InvalidNumber(argument.to_owned()))
+        }
+        "badd" => argument
+            .map(|path| Command::AddBuffer(PathBuf::from(path)))
+            .ok_or(CommandErr::MissingArgument("badd")),
+        "sp" | "split" => no_argument(Command::Split(SplitDirection::Horizontal)),
+        "vs" | "vsplit" => no_argument(Command::Split(SplitDirection::Vertical)),
+        "clo" | "close" => no_argument(Command::Close),
+        "on" | "only" => no_argument(Command::Only),
+        "sel" | "select" => {
+            let argument = argument.ok_or(CommandErr::MissingArgument("select"))?;
+            PathQuery::parse(argument)
+                .map(Command::Select)
+                .map_err(CommandErr::InvalidQuery)
+        }
+        "find" => argument
+            .map(|pattern| Command::Find(pattern.to_owned()))
+            .ok_or(CommandErr::MissingArgument("find")),
+        "u" | "undo" => {
+            let argument = argument.ok_or(CommandErr::MissingArgument("undo"))?;
+            argument
+                .parse()
+                .map(Command::Undo)
+                .map_err(|_| CommandErr::InvalidNumber(argument.to_owned()))
+        }
+        _ => Err(CommandErr::UnknownCommand(name.to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_command, undo_list, Command, CommandErr};
+    use crate::arena::Arena;
+    use crate::ast::json::{add_value_to_arena, Json};
+    use crate::core::{Path, PathQuery, PathQueryErr, Side};
+    use crate::editor::dag::{Dag, Insertable};
+    use crate::editor::window::SplitDirection;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn parse_valid() {
+        for (command_line, expected_command) in &[
+            ("w", Command::Write(None)),
+            ("write", Command::Write(None)),
+            ("  w   ", Command::Write(None)),
+            (
+                "w out.json",
+                Command::Write(Some(PathBuf::from("out.json"))),
+            ),
+            (
+                "w dir/my file.json",
+                Command::Write(Some(PathBuf::from("dir/my file.json"))),
+            ),
+            ("q", Command::Quit { force: false }),
+            ("quit", Command::Quit { force: false }),
+            ("q!", Command::Quit { force: true }),
+            ("qa", Command::QuitAll { force: false }),
+            ("qall!", Command::QuitAll { force: true }),
+            ("wq", Command::WriteQuit),
+            ("x", Command::WriteQuit),
+            (
+                "e other.json",
+                Command::Edit {
+                    path: PathBuf::from("other.json"),
+                    force: false,
+                },
+            ),
+            (
+                "e! other.json",
+                Command::Edit {
+                    path: PathBuf::from("other.json"),
+                    force: true,
+                },
+            ),
+            (
+                "set format=compact",
+                Command::Set {
+                    option: "format".to_owned(),
+                    value: "compact".to_owned(),
+                },
+            ),
+            (
+                "set format = pretty",
+                Command::Set {
+                    option: "format".to_owned(),
+                    value: "pretty".to_owned(),
+                },
+            ),
+            ("undolist", Command::UndoList),
+            ("undo 12", Command::Undo(12)),
+            ("u 0", Command::Undo(0)),
+            ("compact", Command::Compact),
+            ("ls", Command::ListBuffers),
+            ("buffers", Command::ListBuffers),
+            ("bn", Command::NextBuffer),
+            ("bprevious", Command::PrevBuffer),
+            ("b 2", Command::Buffer(2)),
+            ("buffer 10", Command::Buffer(10)),
+            (
+                "badd other file.json",
+                Command::AddBuffer(PathBuf::from("other file.json")),
+            ),
+            ("sp", Command::Split(SplitDirection::Horizontal)),
+            ("vsplit", Command::Split(SplitDirection::Vertical)),
+            ("clo", Command::Close),
+            ("only", Command::Only),
+            (
+                "select $.services[*].timeout",
+                Command::Select(PathQuery::parse("$.services[*].timeout").unwrap()),
+            ),
+            (
+                "sel @..[?(@ == null)]",
+                Command::Select(PathQuery::parse("@..[?(@ == null)]").unwrap()),
+            ),
+            ("find $..name", Command::Find("$..name".to_owned())),
+            ("find :null", Command::Find(":null".to_owned())),
+        ] {
+            println!("Testing {:?}", command_line);
+            assert_eq!(parse_command(command_line).as_ref(), Ok(expected_command));
+        }
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for (command_line, expected_err) in &[
+            ("", CommandErr::Empty),
+            ("   ", CommandErr::Empty),
+            ("foo", CommandErr::UnknownCommand("foo".to_owned())),
+            ("Q", CommandErr::UnknownCommand("Q".to_owned())),
+            ("q now", CommandErr::UnexpectedArgument("now".to_owned())),
+            (
+                "wq file.json",
+                CommandErr::UnexpectedArgument("file.json".to_owned()),
+            ),
+            ("e", CommandErr::MissingArgument("e")),
+            ("e!", CommandErr::MissingArgument("e")),
+            ("set", CommandErr::MissingArgument("set")),
+            (
+                "set format",
+                CommandErr::InvalidSetArgument("format".to_owned()),
+            ),
+            ("undo", CommandErr::MissingArgument("undo")),
+            ("undo -1", CommandErr::InvalidNumber("-1".to_owned())),
+            ("undolist 2", CommandErr::UnexpectedArgument("2".to_owned())),
+            (
+                "compact now",
+                CommandErr::UnexpectedArgument("now".to_owned()),
+            ),
+            ("b", CommandErr::MissingArgument("buffer")),
+            ("b next", CommandErr::InvalidNumber("next".to_owned())),
+            ("badd", CommandErr::MissingArgument("badd")),
+            ("ls -a", CommandErr::UnexpectedArgument("-a".to_owned())),
+            (
+                "vs a.json",
+                CommandErr::UnexpectedArgument("a.json".to_owned()),
+            ),
+            ("select", CommandErr::MissingArgument("select")),
+            (
+                "select services",
+                CommandErr::InvalidQuery(PathQueryErr::MissingStart),
+            ),
+            ("find", CommandErr::MissingArgument("find")),
+        ] {
+            println!("Testing {:?}", command_line);
+            assert_eq!(parse_command(command_line).as_ref(), Err(expected_err));
+        }
+    }
+
+    #[test]
+    fn undo_branches() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(serde_json::json!([]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::root());
+        dag.insert_child(1, Insertable::CountedNode(1, 't'))
+            .unwrap();
+        dag.undo(1).unwrap();
+        dag.insert_child(1, Insertable::CountedNode(1, 'f'))
+            .unwrap();
+        dag.insert_next_to_cursor(1, Insertable::CountedNode(1, 'n'), Side::Next)
+            .unwrap();
+        let now = dag.history()[3].time + Duration::from_secs(90);
+        assert_eq!(
+            undo_list(&dag, now),
+            "branches: 1 (1 change, 1m ago), 3 (2 changes, 1m ago); at snapshot 3"
+        );
+        assert_eq!(
+            undo_list(&dag, SystemTime::UNIX_EPOCH),
+            "branches: 1 (1 change, 0s ago), 3 (2 changes, 0s ago); at snapshot 3"
+        );
+    }
+}
@@ -5,22 +5,36 @@ use crate::ast;
 use crate::ast::{Ast, AstClass};
 
 use crate::core::{Direction, Path, Side};
+use std::marker::PhantomData;
+use std::time::SystemTime;
 use std::{collections::HashMap, hash::Hash};
 
-/// The two possible locations where an edit could cause nodes to be replaced
+/// The possible locations where an edit could cause nodes to be replaced
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 enum EditLocation {
     /// The edit caused the cursor to be replaced
-    Cursor = 0,
+    Cursor,
     /// The edit caused the parent to be replaced
-    Parent = 1,
+    Parent,
+    /// The edit caused the parent's parent to be replaced
+    Grandparent,
+    /// The edit caused the parent's parent's parent to be replaced
+    GreatGrandparent,
+    /// The edit caused the ancestor which is some number of steps above the cursor to be replaced
+    Ancestor(usize),
 }
 
 impl EditLocation {
     /// How many steps above the cursor was the edit made
     #[inline]
     fn steps_above_cursor(self) -> usize {
-        self as usize
+        match self {
+            EditLocation::Cursor => 0,
+            EditLocation::Parent => 1,
+            EditLocation::Grandparent => 2,
+            EditLocation::GreatGrandparent => 3,
+            EditLocation::Ancestor(steps) => steps,
+        }
     }
 }
 
@@ -30,19 +44,83 @@ impl EditLocation {
 pub enum EditSuccess<C: AstClass> {
     Undo,
     Redo,
+    JumpToSnapshot(usize),
     Move(usize, Direction),
     Replace(C),
     InsertChild(C),
     InsertNextToCursor { side: Side, class: C },
     Delete { name: String },
+    PutNextToCursor { side: Side, count: usize },
+    PutChild { count: usize },
+    Wrap { class: C, count: usize },
+    Raise { class: C, count: usize },
+    MoveNode { side: Side, count: usize },
+    Slurp(Side),
+    Barf(Side),
+    Increment(i64),
+    SetText { name: String },
 }
 
 impl<C: AstClass> EditSuccess<C> {
+    /// Returns a short lower-case description of this edit, which is shown next to each
+    /// [`Snapshot`] in the undo tree
+    pub fn description(&self) -> String {
+        match self {
+            EditSuccess::Undo => "undo".to_owned(),
+            EditSuccess::Redo => "redo".to_owned(),
+            EditSuccess::JumpToSnapshot(index) => format!("jump to snapshot {}", index),
+            EditSuccess::Move(n, direction) => {
+                let direction = match direction {
+                    Direction::Up => "up",
+                    Direction::Down => "down",
+                    Direction::Prev => "back",
+                    Direction::Next => "forward",
+                    Direction::LastChild => "down to last child",
+                    Direction::FirstSibling => "to first sibling",
+                    Direction::LastSibling => "to last sibling",
+                    Direction::Root => "to root",
+                    Direction::PreorderNext => "to next node",
+                    Direction::PreorderPrev => "to previous node",
+                    Direction::NextLeaf => "to next leaf",
+                    Direction::PrevLeaf => "to previous leaf",
+                };
+                format!("move {} {}", direction, n)
+            }
+            EditSuccess::Replace(class) => format!("replace with {}", class.name()),
+            EditSuccess::InsertChild(class) => format!("insert child {}", class.name()),
+            EditSuccess::InsertNextToCursor { side, class } => {
+                format!("insert {} {}", class.name(), side.relational_word())
+            }
+            EditSuccess::Delete { name } => format!("delete {}", name),
+            EditSuccess::PutNextToCursor { side, count } => {
+                format!("put {} {}", count, side.relational_word())
+            }
+            EditSuccess::PutChild { count } => format!("put {} as children", count),
+            EditSuccess::Wrap { class, count } => format!("wrap {} in {}", count, class.name()),
+            EditSuccess::Raise { class, count: 1 } => format!("raise {}", class.name()),
+            EditSuccess::Raise { class, count } => {
+                format!("raise {} by {} levels", class.name(), count)
+            }
+            EditSuccess::MoveNode { side, count } => {
+                let direction = match side {
+                    Side::Prev => "back",
+                    Side::Next => "forward",
+                };
+                format!("move node {} {}", direction, count)
+            }
+            EditSuccess::Slurp(side) => format!("move into {} sibling", side_adjective(*side)),
+            EditSuccess::Barf(side) => format!("move out {} parent", side.relational_word()),
+            EditSuccess::Increment(delta) => format!("add {}", delta),
+            EditSuccess::SetText { name } => format!("set text to {}", name),
+        }
+    }
+
     /// Writes an info message of a successful action using `info!`
     fn log_message(self) {
         match self {
             EditSuccess::Undo => log::info!("Undoing one change"),
             EditSuccess::Redo => log::info!("Redoing one change"),
+            EditSuccess::JumpToSnapshot(index) => log::info!("Jumping to snapshot {}", index),
             EditSuccess::Move(n, Direction::Up) => log::info!("Moving {} levels up the tree", n),
             EditSuccess::Move(n, Direction::Down) => {
                 log::info!("Moving {} levels down the tree", n)
@@ -51,6 +129,28 @@ impl<C: AstClass> EditSuccess<C> {
                 log::info!("Moving to {}th previous sibling", n)
             }
             EditSuccess::Move(n, Direction::Next) => log::info!("Moving to {}th next sibling", n),
+            EditSuccess::Move(n, Direction::LastChild) => {
+                log::info!("Moving {} levels down the tree through last children", n)
+            }
+            EditSuccess::Move(n, Direction::FirstSibling) => {
+                log::info!("Moving {} siblings to a sibling counted from the first", n)
+            }
+            EditSuccess::Move(n, Direction::LastSibling) => {
+                log::info!("Moving {} siblings to a sibling counted from the last", n)
+            }
+            EditSuccess::Move(n, Direction::Root) => {
+                log::info!("Moving {} levels up the tree towards the root", n)
+            }
+            EditSuccess::Move(n, Direction::PreorderNext) => {
+                log::info!("Moving to {}th next node in the text", n)
+            }
+            EditSuccess::Move(n, Direction::PreorderPrev) => {
+                log::info!("Moving to {}th previous node in the text", n)
+            }
+            EditSuccess::Move(n, Direction::NextLeaf) => log::info!("Moving to {}th next leaf", n),
+            EditSuccess::Move(n, Direction::PrevLeaf) => {
+                log::info!("Moving to {}th previous leaf", n)
+            }
             EditSuccess::Replace(class) => {
                 log::info!("Replacing with '{}'/{}", class.to_char(), class.name())
             }
@@ -68,6 +168,43 @@ impl<C: AstClass> EditSuccess<C> {
                 side.relational_word()
             ),
             EditSuccess::Delete { name } => log::info!("Deleting {}", name),
+            EditSuccess::PutNextToCursor { side, count } => {
+                log::info!(
+                    "Putting {} nodes {} the cursor",
+                    count,
+                    side.relational_word()
+                )
+            }
+            EditSuccess::PutChild { count } => {
+                log::info!("Putting {} nodes as children of the cursor", count)
+            }
+            EditSuccess::Wrap { class, count } => log::info!(
+                "Wrapping {} nodes in '{}'/{}",
+                count,
+                class.to_char(),
+                class.name()
+            ),
+            EditSuccess::Raise { class, count } => log::info!(
+                "Raising '{}'/{} to replace its ancestor {} levels up",
+                class.to_char(),
+                class.name(),
+                count
+            ),
+            EditSuccess::MoveNode { side, count } => log::info!(
+                "Moving the cursor {} places {} its siblings",
+                count,
+                side.relational_word()
+            ),
+            EditSuccess::Slurp(side) => log::info!(
+                "Moving the cursor into its {} sibling",
+                side_adjective(side)
+            ),
+            EditSuccess::Barf(side) => log::info!(
+                "Moving the cursor out of its parent, to {} it",
+                side.relational_word()
+            ),
+            EditSuccess::Increment(delta) => log::info!("Adding {} to the cursor", delta),
+            EditSuccess::SetText { name } => log::info!("Setting the cursor's text to {}", name),
         }
     }
 }
@@ -84,6 +221,8 @@ pub enum EditErr<C: AstClass> {
     NoChangesToUndo,
     /// Trying to redo the latest change
     NoChangesToRedo,
+    /// Trying to jump to a snapshot which doesn't exist
+    NoSuchSnapshot(usize),
     /// The user typed a char that doesn't correspond to any node
     CharNotANode(char),
     /// Trying to insert a node that cannot be root node
@@ -101,10 +240,30 @@ pub enum EditErr<C: AstClass> {
     InsertError(ast::InsertError),
     /// An error was generated by the Ast code when trying to delete a node
     DeleteError(ast::DeleteError),
+    /// An error was generated by the Ast code when trying to set the text of a node
+    TextError(ast::TextError),
     /// Trying to add a sibling to the root
     AddSiblingToRoot,
     /// Trying to delete the root
     DeletingRoot,
+    /// Trying to raise the root
+    RaisingRoot,
+    /// Trying to raise a node which can't exist on its own (i.e. it has no class)
+    CannotRaise {
+        /// The [`display_name`](Ast::display_name) of the node that couldn't be raised
+        name: String,
+    },
+    /// Trying to move the root
+    MovingRoot,
+    /// Trying to move a node out of the root
+    MovingOutOfRoot,
+    /// Trying to move the cursor past or into a sibling on a side where it has no siblings
+    NoSibling(Side),
+    /// Trying to increment a node that isn't a number
+    CannotIncrement {
+        /// The [`display_name`](Ast::display_name) of the node that couldn't be incremented
+        name: String,
+    },
 }
 
 impl<C: AstClass> EditErr<C> {
@@ -115,9 +274,11 @@ impl<C: AstClass> EditErr<C> {
             EditErr::MoveToSiblingOfRoot => log::warn!("Can't move to a sibling of the root."),
             EditErr::NoChangesToUndo => log::warn!("No changes to undo."),
             EditErr::NoChangesToRedo => log::warn!("No changes to redo."),
+            EditErr::NoSuchSnapshot(index) => log::warn!("There is no snapshot {}.", index),
             EditErr::NoNodesToInsert => log::warn!("No nodes to insert."),
             EditErr::InsertError(e) => log::warn!("{}", e),
             EditErr::DeleteError(e) => log::warn!("{}", e),
+            EditErr::TextError(e) => log::warn!("{}", e),
             EditErr::CharNotANode(c) => log::warn!("'{}' doesn't correspond to any node type.", c),
             EditErr::CannotBeRoot(c) => {
                 log::warn!("'{}' cannot be root", c.name())
@@ -127,6 +288,14 @@ impl<C: AstClass> EditErr<C> {
             }
             EditErr::AddSiblingToRoot => log::warn!("Can't add siblings to the root."),
             EditErr::DeletingRoot => log::warn!("Can't delete the root."),
+            EditErr::RaisingRoot => log::warn!("Can't raise the root."),
+            EditErr::CannotRaise { name } => log::warn!("Can't raise {} out of its parent.", name),
+            EditErr::MovingRoot => log::warn!("Can't move the root."),
+            EditErr::MovingOutOfRoot => log::warn!("Can't move a node out of the root."),
+            EditErr::NoSibling(side) => {
+                log::warn!("There is no node {} the cursor.", side.relational_word())
+            }
+            EditErr::CannotIncrement { name } => log::warn!("Can't increment {}.", name),
         }
     }
 }
@@ -143,6 +312,12 @@ impl<C: AstClass> From<ast::DeleteError> for EditErr<C> {
     }
 }
 
+impl<C: AstClass> From<ast::TextError> for EditErr<C> {
+    fn from(e: ast::TextError) -> EditErr<C> {
+        EditErr::TextError(e)
+    }
+}
+
 /// An alias for [`Result`] that is the return type of all of [`Dag`]'s edit methods.
 pub type EditResult<C> = Result<EditSuccess<C>, EditErr<C>>;
 
@@ -178,19 +353,51 @@ impl std::fmt::Display for Insertable {
     }
 }
 
-/// A representation of a single edit, along with the cursor locations around it
-struct Snapshot<'arena, Node: Ast<'arena>> {
-    cursor_before: Path,
-    root: &'arena Node,
-    cursor_after: Path,
+/// The description of the first [`Snapshot`] in the undo tree, which wasn't created by an edit
+pub const ORIGINAL_DESCRIPTION: &str = "original tree";
+
+/// A representation of a single edit, along with the cursor locations around it.  The snapshots
+/// of a [`Dag`] form a tree, where each snapshot's parent is the snapshot that was edited to make
+/// it.
+#[derive(Debug, Clone)]
+pub struct Snapshot<'arena, Node: Ast<'arena>> {
+    /// The location of the cursor just before the edit was made
+    pub cursor_before: Path,
+    /// The root of the tree after the edit
+    pub root: &'arena Node,
+    /// The location of the cursor just after the edit was made
+    pub cursor_after: Path,
+    /// The index of the snapshot that was edited to make this one, or `None` if this is the
+    /// original tree
+    pub parent: Option<usize>,
+    /// The time when this snapshot was created
+    pub time: SystemTime,
+    /// A description of the edit that created this snapshot (see [`EditSuccess::description`])
+    pub description: String,
+    /// The index of the child snapshot that redoing should move to: the child which was most
+    /// recently created or undone from
+    redo_child: Option<usize>,
 }
 
 impl<'arena, Node: Ast<'arena>> Snapshot<'arena, Node> {
-    fn new(cursor_before: Path, root: &'arena Node, cursor_after: Path) -> Self {
+    /// Creates a new `Snapshot` from its root, surrounding cursor locations, parent, time of
+    /// creation and a description of the edit that created it
+    pub fn new(
+        cursor_before: Path,
+        root: &'arena Node,
+        cursor_after: Path,
+        parent: Option<usize>,
+        time: SystemTime,
+        description: String,
+    ) -> Self {
         Snapshot {
             cursor_before,
             root,
             cursor_after,
+            parent,
+            time,
+            description,
+            redo_child: None,
         }
     }
 }
@@ -205,16 +412,31 @@ impl<'arena, Node: Ast<'arena>> Snapshot<'arena, Node> {
 ///
 /// Therefore, moving back through the history is as simple as reading a different root node from
 /// the `roots` vector, and following its descendants through the Dag of nodes.
+///
+/// The history is a tree rather than a list: making an edit after undoing creates a new branch
+/// rather than throwing away the changes that were undone (like Vim's undo tree).
 pub struct Dag<'arena, Node: Ast<'arena>> {
     /// The arena in which all the `Node`s will be stored
     arena: &'arena Arena<Node>,
-    /// A [`Vec`] containing a reference to the root node at every edit in the undo history.  This
-    /// is required to always have length at least one.
+    /// Every [`Snapshot`] in the undo tree, in the order that they were created.  Each snapshot's
+    /// parent comes before it.  This is required to always have length at least one.
     root_history: Vec<Snapshot<'arena, Node>>,
     /// An index into [`root_history`](Dag::root_history) of the current edit.  This is required to
     /// be in `0..root_history.len()`.
     history_index: usize,
+    /// The index into [`root_history`](Dag::root_history) of the snapshot which was last written
+    /// to disk, or `None` if that snapshot has since been removed from the history.
+    saved_history_index: Option<usize>,
     current_cursor_path: Path,
+    /// The index of the child that the cursor was last in, for every node that the cursor has
+    /// been below.  Moving down from one of these nodes goes back to that child.  Nodes are keyed
+    /// by their paths rather than their addresses, because an
+    /// [interning](crate::arena::Arena::with_interning) arena shares identical subtrees (which
+    /// would then share an entry).  Every edit [remaps](Dag::remap_visited_children) the paths so
+    /// that they follow their nodes, and removes the paths which no longer exist.
+    visited_children: HashMap<Path, usize>,
+    /// The maximum number of changes kept in the undo tree, or `None` if there is no limit
+    undo_levels: Option<usize>,
 }
 
 impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
@@ -226,12 +448,76 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
                 cursor_path.clone(),
                 root,
                 cursor_path.clone(),
+                None,
+                SystemTime::now(),
+                ORIGINAL_DESCRIPTION.to_owned(),
             )],
             history_index: 0,
+            saved_history_index: Some(0),
             current_cursor_path: cursor_path,
+            visited_children: HashMap::new(),
+            undo_levels: None,
+        }
+    }
+
+    /// Builds a `Dag` with an existing undo tree (e.g. one read from an undo file), where the
+    /// `history_index`th [`Snapshot`] is the current tree and is treated as being saved.  Redoing
+    /// from any snapshot moves to its most recently created child.
+    ///
+    /// # Panics
+    /// Panics if `history_index` isn't a valid index into `history`, or if any snapshot's parent
+    /// doesn't come before it.
+    pub fn from_history(
+        arena: &'arena Arena<Node>,
+        mut history: Vec<Snapshot<'arena, Node>>,
+        history_index: usize,
+    ) -> Self {
+        for index in 0..history.len() {
+            if let Some(parent) = history[index].parent {
+                assert!(parent < index, "Snapshots must come after their parents");
+                history[parent].redo_child = Some(index);
+            }
+        }
+        let current_cursor_path = history[history_index].cursor_after.clone();
+        Dag {
+            arena,
+            root_history: history,
+            history_index,
+            saved_history_index: Some(history_index),
+            current_cursor_path,
+            visited_children: HashMap::new(),
+            undo_levels: None,
         }
     }
 
+    /// Returns every [`Snapshot`] in the undo tree, from oldest to newest
+    pub fn history(&self) -> &[Snapshot<'arena, Node>] {
+        &self.root_history
+    }
+
+    /// Returns the index into [`history`](Self::history) of the current [`Snapshot`]
+    pub fn history_index(&self) -> usize {
+        self.history_index
+    }
+
+    /// Returns the arena in which this `Dag` stores its nodes
+    pub fn arena(&self) -> &'arena Arena<Node> {
+        self.arena
+    }
+
+    /* SAVE TRACKING */
+
+    /// Records that the current tree has just been written to disk
+    pub fn mark_saved(&mut self) {
+        self.saved_history_index = Some(self.history_index);
+    }
+
+    /// Returns `true` if the current tree differs from the one that was last written to disk.  If
+    /// the user undoes or redoes back to the saved tree, then the `Dag` is no longer modified.
+    pub fn is_modified(&self) -> bool {
+        self.saved_history_index != Some(self.history_index)
+    }
+
     /* NAVIGATION METHODS */
 
     /// Returns a reference to the node that is currently the root of the AST.
@@ -251,46 +537,109 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
         self.current_cursor_path.cursor(self.root())
     }
 
+    /// Returns the [`Path`] from the root to the node under the cursor
+    pub fn cursor_path(&self) -> &Path {
+        &self.current_cursor_path
+    }
+
+    /// Moves the cursor to a given [`Path`] (such as the cursor of a window which is being
+    /// returned to), [clamping](Path::clamp_to) it if it doesn't exist in the current tree
+    pub fn set_cursor_path(&mut self, mut path: Path) {
+        path.clamp_to(self.root());
+        self.current_cursor_path = path;
+    }
+
+    /// Returns the node under the cursor, followed by up to `count - 1` of its next siblings.
+    /// These are the nodes that would be removed by calling [`delete_cursor`](Self::delete_cursor)
+    /// with the same `count`.
+    pub fn cursor_and_next_siblings(&self, count: usize) -> Vec<&'arena Node> {
+        self.node_and_next_siblings(&self.current_cursor_path, count)
+    }
+
+    /// Returns the node at a given [`Path`], followed by up to `count - 1` of its next siblings
+    pub fn node_and_next_siblings(&self, path: &Path, count: usize) -> Vec<&'arena Node> {
+        match path.cursor_and_parent(self.root()) {
+            (node, None) => vec![node],
+            // Unwrapping is safe because the node has a parent, so the path can't be empty
+            (_, Some(parent)) => {
+                let cursor_index = path.last().unwrap();
+                parent.children()[cursor_index..]
+                    .iter()
+                    .copied()
+                    .take(count)
+                    .collect()
+            }
+        }
+    }
+
+    /// Records the cursor's child index in every one of its ancestors, so that moving down from
+    /// any of them returns towards the cursor
+    fn remember_cursor_path(&mut self) {
+        let mut path = Path::root();
+        for &index in self.current_cursor_path.iter() {
+            self.visited_children.insert(path.clone(), index);
+            path.push(index);
+        }
+    }
+
     /// Move the cursor a given `distance` in a given [`Direction`] across the tree.
     pub fn move_cursor(
         &mut self,
         distance: usize,
         direction: Direction,
     ) -> EditResult<Node::Class> {
-        let (mut current_cursor, cursor_parent) = self.cursor_and_parent();
+        self.remember_cursor_path();
+        let mut path = std::mem::replace(&mut self.current_cursor_path, Path::root());
+        let result = self.move_path(&mut path, distance, direction);
+        self.current_cursor_path = path;
+        result
+    }
+
+    /// Moves a [`Path`] (such as one of the other cursors in multi-cursor mode) as
+    /// [`move_cursor`](Self::move_cursor) would move the cursor, but without remembering the
+    /// children that it leaves
+    pub fn move_path(
+        &self,
+        path: &mut Path,
+        distance: usize,
+        direction: Direction,
+    ) -> EditResult<Node::Class> {
+        let (mut current_cursor, cursor_parent) = path.cursor_and_parent(self.root());
         let successful_distance = match direction {
+            // Moving down goes to the child that the cursor was last in, or the first child if
+            // the cursor hasn't been in any of them
             Direction::Down => {
                 let mut successful_distance = 0usize;
                 while !current_cursor.children().is_empty() && successful_distance < distance {
-                    self.current_cursor_path.push(0);
-                    current_cursor = current_cursor.children()[0];
+                    let index = self
+                        .visited_children
+                        .get(path)
+                        .copied()
+                        .filter(|index| *index < current_cursor.children().len())
+                        .unwrap_or(0);
+                    path.push(index);
+                    current_cursor = current_cursor.children()[index];
                     successful_distance += 1;
                 }
                 successful_distance
             }
             Direction::Up => {
                 let mut successful_distance = 0usize;
-                while !self.current_cursor_path.is_root() && successful_distance < distance {
-                    self.current_cursor_path.pop();
+                while !path.is_root() && successful_distance < distance {
+                    path.pop();
                     successful_distance += 1;
                 }
                 successful_distance
             }
             Direction::Prev => {
-                let index = self
-                    .current_cursor_path
-                    .last_mut()
-                    .ok_or(EditErr::MoveToSiblingOfRoot)?;
+                let index = path.last_mut().ok_or(EditErr::MoveToSiblingOfRoot)?;
                 let last_index = *index;
                 *index = last_index.saturating_sub(distance);
                 // Return the distance that we actually moved
                 last_index - *index
             }
             Direction::Next => {
-                let index = self
-                    .current_cursor_path
-                    .last_mut()
-                    .ok_or(EditErr::MoveToSiblingOfRoot)?;
+                let index = path.last_mut().ok_or(EditErr::MoveToSiblingOfRoot)?;
                 let last_index = *index;
                 // We can unwrap here, because the only way for a node to not have a parent is
                 // if it's the root.  And if the cursor is at the root, then the ? in the last line
@@ -301,38 +650,111 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
                 // Return the distance we travelled
                 *index - last_index
             }
+            Direction::LastChild => {
+                let mut successful_distance = 0usize;
+                while !current_cursor.children().is_empty() && successful_distance < distance {
+                    let last_index = current_cursor.children().len() - 1;
+                    path.push(last_index);
+                    current_cursor = current_cursor.children()[last_index];
+                    successful_distance += 1;
+                }
+                successful_distance
+            }
+            // Like Vim's `3gg`, the count says where to jump to: the `distance`th sibling counted
+            // from the first or last sibling, or the cursor's ancestor `distance - 1` levels below
+            // the root.  Counts which are too large go as far as they can.  The distance returned
+            // is how many nodes were skipped over.
+            Direction::FirstSibling | Direction::LastSibling => {
+                let index = path.last_mut().ok_or(EditErr::MoveToSiblingOfRoot)?;
+                // We can unwrap for the same reasons as when moving to the next sibling
+                let max_index = cursor_parent.unwrap().children().len() - 1;
+                let offset = distance.saturating_sub(1).min(max_index);
+                let new_index = match direction {
+                    Direction::FirstSibling => offset,
+                    _ => max_index - offset,
+                };
+                let last_index = std::mem::replace(index, new_index);
+                (new_index as isize - last_index as isize).unsigned_abs()
+            }
+            Direction::Root => {
+                let depth = path.depth();
+                let new_depth = distance.saturating_sub(1).min(depth);
+                for _ in new_depth..depth {
+                    path.pop();
+                }
+                depth - new_depth
+            }
+            Direction::PreorderNext
+            | Direction::PreorderPrev
+            | Direction::NextLeaf
+            | Direction::PrevLeaf => {
+                let root = self.root();
+                let is_forward = matches!(direction, Direction::PreorderNext | Direction::NextLeaf);
+                let leaves_only = matches!(direction, Direction::NextLeaf | Direction::PrevLeaf);
+                let mut next_path = path.clone();
+                let mut successful_distance = 0usize;
+                while successful_distance < distance {
+                    let moved = if is_forward {
+                        next_path.preorder_next(root)
+                    } else {
+                        next_path.preorder_prev(root)
+                    };
+                    if !moved {
+                        break;
+                    }
+                    // Only stop on a leaf if we're moving between leaves, so that we never stop
+                    // on a node with children after running out of leaves
+                    if !leaves_only || next_path.cursor(root).children().is_empty() {
+                        *path = next_path.clone();
+                        successful_distance += 1;
+                    }
+                }
+                successful_distance
+            }
         };
         Ok(EditSuccess::Move(successful_distance, direction))
     }
 
     /* HISTORY METHODS */
 
-    /// Move one step back in the tree history
+    /// Move up to `steps` steps back up the undo tree, towards the original tree
     pub fn undo(&mut self, steps: usize) -> EditResult<Node::Class> {
         log::trace!("Performing undo.");
         // Early return if there are no changes to undo
-        if self.history_index == 0 {
+        if self.root_history[self.history_index].parent.is_none() {
             return Err(EditErr::NoChangesToUndo);
         }
-        // Move the history index back by one to perform the undo
-        self.history_index = self.history_index.saturating_sub(steps);
-        // Follow the behaviour of other text editors and update the location of the cursor
-        // with its location in the snapshot we are going forward to
-        self.current_cursor_path
-            .clone_from(&self.root_history[self.history_index + 1].cursor_before);
+        for _ in 0..steps {
+            let parent = match self.root_history[self.history_index].parent {
+                Some(parent) => parent,
+                None => break,
+            };
+            // Follow the behaviour of other text editors and update the location of the cursor
+            // with its location before the change that's being undone
+            self.current_cursor_path
+                .clone_from(&self.root_history[self.history_index].cursor_before);
+            // Redoing should return to the branch that we're undoing
+            self.root_history[parent].redo_child = Some(self.history_index);
+            self.history_index = parent;
+        }
         log::debug!("Setting cursor path to {:?}", self.current_cursor_path);
         Ok(EditSuccess::Undo)
     }
 
-    /// Move one step forward in the tree history
+    /// Move up to `steps` steps forward down the undo tree, following the most recently used
+    /// branches
     pub fn redo(&mut self, steps: usize) -> EditResult<Node::Class> {
         log::trace!("Performing redo.");
         // Early return if there are no changes to redo
-        if self.history_index >= self.root_history.len() - 1 {
+        if self.root_history[self.history_index].redo_child.is_none() {
             return Err(EditErr::NoChangesToRedo);
         }
-        // Move the history index forward by one to perform the redo
-        self.history_index = (self.history_index + steps).min(self.root_history.len() - 1);
+        for _ in 0..steps {
+            match self.root_history[self.history_index].redo_child {
+                Some(child) => self.history_index = child,
+                None => break,
+            }
+        }
         // Follow the behaviour of other text editors and update the location of the cursor
         // with its location in the snapshot we are going back to
         self.current_cursor_path
@@ -341,6 +763,153 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
         Ok(EditSuccess::Redo)
     }
 
+    /// Moves `steps` snapshots back in time, regardless of which branches of the undo tree they
+    /// are on (like Vim's `g-`)
+    pub fn undo_chronologically(&mut self, steps: usize) -> EditResult<Node::Class> {
+        if self.history_index == 0 {
+            return Err(EditErr::NoChangesToUndo);
+        }
+        self.jump_to_snapshot(self.history_index.saturating_sub(steps))
+    }
+
+    /// Moves `steps` snapshots forward in time, regardless of which branches of the undo tree they
+    /// are on (like Vim's `g+`)
+    pub fn redo_chronologically(&mut self, steps: usize) -> EditResult<Node::Class> {
+        let last_index = self.root_history.len() - 1;
+        if self.history_index == last_index {
+            return Err(EditErr::NoChangesToRedo);
+        }
+        self.jump_to_snapshot((self.history_index + steps).min(last_index))
+    }
+
+    /// Makes the `index`th [`Snapshot`] the current tree.  Afterwards, redoing from any of its
+    /// ancestors leads back to it.
+    pub fn jump_to_snapshot(&mut self, index: usize) -> EditResult<Node::Class> {
+        if index >= self.root_history.len() {
+            return Err(EditErr::NoSuchSnapshot(index));
+        }
+        let mut child = index;
+        while let Some(parent) = self.root_history[child].parent {
+            self.root_history[parent].redo_child = Some(child);
+            child = parent;
+        }
+        self.history_index = index;
+        self.current_cursor_path
+            .clone_from(&self.root_history[index].cursor_after);
+        Ok(EditSuccess::JumpToSnapshot(index))
+    }
+
+    /// Returns the indices of the [`Snapshot`]s at the tips of each branch of the undo tree (i.e.
+    /// the snapshots which haven't been edited), from oldest to newest
+    pub fn branch_tips(&self) -> Vec<usize> {
+        let mut is_tip = vec![true; self.root_history.len()];
+        for snapshot in &self.root_history {
+            if let Some(parent) = snapshot.parent {
+                is_tip[parent] = false;
+            }
+        }
+        (0..self.root_history.len())
+            .filter(|i| is_tip[*i])
+            .collect()
+    }
+
+    /// Sets the maximum number of changes kept in the undo tree (or `None` for no limit), dropping
+    /// old snapshots straight away if there are already too many
+    pub fn set_undo_levels(&mut self, undo_levels: Option<usize>) {
+        self.undo_levels = undo_levels;
+        self.enforce_undo_levels();
+    }
+
+    /// Drops the oldest [`Snapshot`]s until the undo tree holds at most `undo_levels` changes (i.e.
+    /// `undo_levels + 1` snapshots).  Like in Vim, the oldest snapshot is dropped if the undo tree
+    /// only carries on from it in one direction.  Otherwise, the oldest branch leaving it which
+    /// doesn't lead to the current snapshot is dropped.  The nodes of the dropped snapshots stay in
+    /// the arena until it is compacted (see [`Detacher`]).
+    fn enforce_undo_levels(&mut self) {
+        let max_snapshots = match self.undo_levels {
+            Some(undo_levels) => undo_levels + 1,
+            None => return,
+        };
+        let len = self.root_history.len();
+        if len <= max_snapshots {
+            return;
+        }
+
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); len];
+        for (index, snapshot) in self.root_history.iter().enumerate() {
+            if let Some(parent) = snapshot.parent {
+                children[parent].push(index);
+            }
+        }
+        let mut leads_to_current = vec![false; len];
+        let mut ancestor = Some(self.history_index);
+        while let Some(index) = ancestor {
+            leads_to_current[index] = true;
+            ancestor = self.root_history[index].parent;
+        }
+
+        // Drop snapshots from the root of the undo tree.  Every kept snapshot is always a
+        // descendant of `root`, so the current snapshot is never dropped.
+        let mut is_kept = vec![true; len];
+        let mut kept_count = len;
+        let mut root = 0;
+        while kept_count > max_snapshots {
+            let kept_children: Vec<usize> = children[root]
+                .iter()
+                .copied()
+                .filter(|c| is_kept[*c])
+                .collect();
+            if kept_children.len() == 1 && root != self.history_index {
+                is_kept[root] = false;
+                kept_count -= 1;
+                root = kept_children[0];
+                continue;
+            }
+            // Unwrapping is safe, because at most one child of `root` leads to the current
+            // snapshot and `root` must have a kept child (since `kept_count > 1`).  If `root` is
+            // the current snapshot, then none of its children lead to it.
+            let branch = *kept_children
+                .iter()
+                .find(|c| !leads_to_current[**c])
+                .unwrap();
+            let mut to_drop = vec![branch];
+            while let Some(index) = to_drop.pop() {
+                is_kept[index] = false;
+                kept_count -= 1;
+                to_drop.extend_from_slice(&children[index]);
+            }
+        }
+
+        // Remove the dropped snapshots, updating all the indices to point to the kept snapshots
+        let mut new_indices = vec![None; len];
+        let mut next_index = 0;
+        for (index, kept) in is_kept.iter().enumerate() {
+            if *kept {
+                new_indices[index] = Some(next_index);
+                next_index += 1;
+            }
+        }
+        let old_history = std::mem::take(&mut self.root_history);
+        self.root_history = old_history
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| is_kept[*index])
+            .map(|(index, mut snapshot)| {
+                snapshot.parent = snapshot.parent.and_then(|p| new_indices[p]);
+                // If the redo branch has been dropped, then redo moves to the newest kept child
+                snapshot.redo_child = snapshot
+                    .redo_child
+                    .and_then(|c| new_indices[c])
+                    .or_else(|| children[index].iter().rev().find_map(|c| new_indices[*c]));
+                snapshot
+            })
+            .collect();
+        // The current snapshot is always kept, so this unwrap is safe
+        self.history_index = new_indices[self.history_index].unwrap();
+        self.saved_history_index = self.saved_history_index.and_then(|i| new_indices[i]);
+        log::debug!("Dropped {} snapshots from the undo tree", len - kept_count);
+    }
+
     /* EDITING METHODS */
 
     fn perform_edit(
@@ -384,6 +953,14 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
         // work our way up parent by parent until we reach the root of the tree.  At that point,
         // this node becomes the root of the new tree.
         let mut node = self.arena.alloc(new_node);
+        let edited_depth = old_cursor_path.depth() - steps_above_cursor;
+        let edited_path =
+            Path::from_vec(old_cursor_path.iter().take(edited_depth).copied().collect());
+        self.remap_visited_children(
+            &edited_path,
+            nodes_to_clone[edited_depth].children(),
+            node.children(),
+        );
         // Iterate backwards over the child indices and the nodes, whilst cloning the tree and
         // replacing the correct child reference to point to the newly created node.
         for (n, child_index) in nodes_to_clone
@@ -400,25 +977,187 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
 
         /* UPDATE THE HISTORY */
 
-        // Remove future trees from the history vector so that the currently 'checked-out' tree is
-        // the most recent tree in the history.
-        while self.history_index < self.root_history.len() - 1 {
-            // TODO: Deallocate the tree so that we don't get a 'memory leak'
-            self.root_history.pop();
-        }
-        // At this point, `node` contains a reference to the root of the new tree, so we just add
-        // this to the history, along with the cursor path.
-
+        // At this point, `node` contains a reference to the root of the new tree
         log::debug!("current_cursor_path {:?}", self.current_cursor_path);
+        self.push_snapshot(old_cursor_path, node, success.description());
+
+        /* RETURN SUCCESS */
+        Ok(success)
+    }
+
+    /// Updates the keys of [`visited_children`](Dag::visited_children) after an edit has changed
+    /// the children of the node at `path` from `old_children` to `new_children`, so that each
+    /// entry follows its node.  The children kept by the edit are matched up by address, in order,
+    /// and the entries of any children which were removed or replaced are forgotten.
+    fn remap_visited_children(
+        &mut self,
+        path: &Path,
+        old_children: &[&'arena Node],
+        new_children: &[&'arena Node],
+    ) {
+        let mut next_new_index = 0;
+        let new_indices: Vec<Option<usize>> = old_children
+            .iter()
+            .map(|old_child| {
+                let new_index = new_children[next_new_index..]
+                    .iter()
+                    .position(|new_child| std::ptr::eq(*old_child, *new_child))
+                    .map(|offset| next_new_index + offset)?;
+                next_new_index = new_index + 1;
+                Some(new_index)
+            })
+            .collect();
+        let depth = path.depth();
+        self.visited_children = self
+            .visited_children
+            .drain()
+            .filter_map(|(key, index)| {
+                if key == *path {
+                    // The remembered child is one of the children which were changed
+                    return Some((key, new_indices.get(index).copied().flatten()?));
+                }
+                if key.depth() <= depth || !key.iter().take(depth).eq(path.iter()) {
+                    // The node isn't below the edited node, so its path hasn't changed
+                    return Some((key, index));
+                }
+                let mut indices: Vec<usize> = key.iter().copied().collect();
+                indices[depth] = new_indices.get(indices[depth]).copied().flatten()?;
+                Some((Path::from_vec(indices), index))
+            })
+            .collect();
+    }
+
+    /// Adds a new tree (with the current cursor path) to the undo tree as a child of the current
+    /// snapshot, and moves to it.  Any changes which had been undone stay in the undo tree as a
+    /// separate branch.
+    fn push_snapshot(&mut self, old_cursor_path: Path, root: &'arena Node, description: String) {
         self.root_history.push(Snapshot::new(
-            old_cursor_path.clone(),
-            node,
+            old_cursor_path,
+            root,
             self.current_cursor_path.clone(),
+            Some(self.history_index),
+            SystemTime::now(),
+            description,
         ));
-        // Move the history index on by one so that we are pointing at the latest change
-        self.history_index = self.root_history.len() - 1;
+        let new_index = self.root_history.len() - 1;
+        self.root_history[self.history_index].redo_child = Some(new_index);
+        // Move the history index to the new snapshot, which is the latest change
+        self.history_index = new_index;
+        self.enforce_undo_levels();
+        // Forget the visited children of nodes which aren't in the new tree
+        let root = self.root();
+        self.visited_children
+            .retain(|path, _| path.is_valid_in(root));
+    }
 
-        /* RETURN SUCCESS */
+    /// Performs an `edit` (one of the other edit methods) at the cursor and at every path in
+    /// `other_cursors`, as a single snapshot in the history.  Each cursor is moved as it would be
+    /// by making the edit on its own.
+    ///
+    /// The edits are made from the last cursor in the tree to the first, so that each edit can only
+    /// move the nodes of cursors which have already been edited.  Those cursors are then shifted
+    /// to keep up with any siblings that were added or removed before them (and are dropped if
+    /// their node was removed).  If the edit fails at any cursor, then none of the edits are made.
+    pub fn edit_every_cursor(
+        &mut self,
+        other_cursors: &mut Vec<Path>,
+        mut edit: impl FnMut(&mut Self) -> EditResult<Node::Class>,
+    ) -> EditResult<Node::Class> {
+        let start_history_len = self.root_history.len();
+        let start_history_index = self.history_index;
+        let start_redo_child = self.root_history[start_history_index].redo_child;
+        let start_cursor_path = self.current_cursor_path.clone();
+        other_cursors.sort();
+        other_cursors.dedup();
+        other_cursors.retain(|path| *path != start_cursor_path);
+        let start_other_cursors = other_cursors.clone();
+        let start_visited_children = self.visited_children.clone();
+        // Stop snapshots being dropped (and the history being renumbered) part way through
+        let undo_levels = self.undo_levels.take();
+
+        // The primary cursor is always the first entry, and cursors which have been removed are
+        // replaced with `None`
+        let mut cursors: Vec<Option<Path>> = std::iter::once(start_cursor_path.clone())
+            .chain(other_cursors.drain(..))
+            .map(Some)
+            .collect();
+        let mut order: Vec<usize> = (0..cursors.len()).collect();
+        order.sort_by(|a, b| cursors[*b].cmp(&cursors[*a]));
+
+        let mut result = Err(EditErr::NoNodesToInsert);
+        for (edited_count, &i) in order.iter().enumerate() {
+            let path = match cursors[i].clone() {
+                Some(path) => path,
+                None => continue,
+            };
+            self.set_cursor_path(path.clone());
+            let mut parent_path = path.clone();
+            let parent_and_index = parent_path.pop().map(|index| {
+                let child_count = parent_path.cursor(self.root()).children().len();
+                (parent_path, index, child_count)
+            });
+
+            result = edit(self);
+            if result.is_err() {
+                break;
+            }
+            cursors[i] = Some(self.current_cursor_path.clone());
+
+            // Shift the cursors which were edited before this one
+            if let Some((parent_path, index, old_child_count)) = parent_and_index {
+                if !parent_path.is_valid_in(self.root()) {
+                    continue;
+                }
+                let new_child_count = parent_path.cursor(self.root()).children().len();
+                let delta = new_child_count as isize - old_child_count as isize;
+                for &j in &order[..edited_count] {
+                    if let Some(path) = &mut cursors[j] {
+                        if !path.shift_for_edit(&parent_path, index, delta) && j != 0 {
+                            cursors[j] = None;
+                        }
+                    }
+                }
+            }
+        }
+
+        let new_root = self.root();
+        self.root_history.truncate(start_history_len);
+        self.history_index = start_history_index;
+        self.undo_levels = undo_levels;
+        let success = match result {
+            Ok(success) => success,
+            Err(err) => {
+                self.root_history[start_history_index].redo_child = start_redo_child;
+                self.current_cursor_path = start_cursor_path;
+                *other_cursors = start_other_cursors;
+                self.visited_children = start_visited_children;
+                return Err(err);
+            }
+        };
+
+        // Every cursor must point to a node that still exists, and no two cursors can be on the
+        // same node.  We can unwrap because the primary cursor is never removed.
+        let mut cursors = cursors.into_iter();
+        let mut primary_cursor = cursors.next().unwrap().unwrap();
+        primary_cursor.clamp_to(new_root);
+        let mut new_other_cursors: Vec<Path> = cursors
+            .flatten()
+            .map(|mut path| {
+                path.clamp_to(new_root);
+                path
+            })
+            .filter(|path| *path != primary_cursor)
+            .collect();
+        new_other_cursors.sort();
+        new_other_cursors.dedup();
+        *other_cursors = new_other_cursors;
+
+        self.current_cursor_path = primary_cursor;
+        let description = match order.len() {
+            1 => success.description(),
+            n => format!("{} at {} cursors", success.description(), n),
+        };
+        self.push_snapshot(start_cursor_path, new_root, description);
         Ok(success)
     }
 
@@ -480,7 +1219,7 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
                                 } else {
                                     cloned_parent.insert_child(
                                         new_node,
-                                        &this.arena,
+                                        this.arena,
                                         insert_index,
                                     )?;
                                 }
@@ -690,46 +1429,442 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
         )
     }
 
-    /* DISPLAY METHODS */
-
-    /// Build the text representation of the current tree into the given [`String`]
-    pub fn write_text(&self, string: &mut String, format: &Node::FormatStyle) {
-        self.root().write_text(string, format);
-    }
-
-    /// Build and return a [`String`] of the current tree
-    pub fn to_text(&self, format: &Node::FormatStyle) -> String {
-        let mut s = String::new();
-        self.write_text(&mut s, format);
-        s
+    /// Inserts `count` copies of the sequence of existing `nodes` (e.g. the contents of a
+    /// register) before or after the cursor, and moves the cursor to the last inserted node.
+    /// Because nodes are immutable, the new copies share their subtrees with the originals.
+    pub fn put_next_to_cursor(
+        &mut self,
+        count: usize,
+        nodes: &[&'arena Node],
+        side: Side,
+    ) -> EditResult<Node::Class> {
+        let node_count = count * nodes.len();
+        if node_count == 0 {
+            return Err(EditErr::NoNodesToInsert);
+        }
+        self.perform_edit(
+            |this: &mut Self,
+             parent_and_index: Option<(&'arena Node, usize)>,
+             _cursor: &'arena Node| {
+                let (parent, cursor_index) = parent_and_index.ok_or(EditErr::AddSiblingToRoot)?;
+                let insert_start_index = cursor_index
+                    + match side {
+                        Side::Prev => 0,
+                        Side::Next => 1,
+                    };
+                let mut cloned_parent = parent.clone();
+                for (i, node) in nodes.iter().cycle().take(node_count).enumerate() {
+                    put_child_at(&mut cloned_parent, node, this.arena, insert_start_index + i)?;
+                }
+                // Move the cursor to the last inserted node.  We can unwrap here because putting
+                // siblings next to the root would have caused an error
+                *this.current_cursor_path.last_mut().unwrap() = insert_start_index + node_count - 1;
+                Ok((
+                    cloned_parent,
+                    EditLocation::Parent,
+                    EditSuccess::PutNextToCursor {
+                        side,
+                        count: node_count,
+                    },
+                ))
+            },
+        )
     }
 
-    ///  Generate `.dot` code of the internal state of the `Dag`
-    pub fn to_dot_code(&self) -> String {
-        /// Helper function that recursively builds dot code for the entire subtree of a given node
-        fn add_to_graph<'arena, Node: Ast<'arena>>(
-            node: &'arena Node,
-            digraph_edges: &mut String,
-            hmap_nodes: &mut HashMap<usize, String>,
-        ) {
-            let name = node as *const Node as usize;
-            if hmap_nodes.get(&name).is_some() {
-                return;
-            }
-            hmap_nodes.insert(
-                name,
-                format!("node{} [label={:?}]\n", name, node.debug_name()),
-            );
-
-            if node.children().len() > 0 {
-                for &child in node.children() {
-                    add_to_graph(child, digraph_edges, hmap_nodes);
+    /// Inserts `count` copies of the sequence of existing `nodes` as the last children of the
+    /// cursor, and moves the cursor to the last inserted node.
+    pub fn put_child(&mut self, count: usize, nodes: &[&'arena Node]) -> EditResult<Node::Class> {
+        let node_count = count * nodes.len();
+        if node_count == 0 {
+            return Err(EditErr::NoNodesToInsert);
+        }
+        self.perform_edit(
+            |this: &mut Self,
+             _parent_and_index: Option<(&'arena Node, usize)>,
+             cursor: &'arena Node| {
+                let mut cloned_cursor = cursor.clone();
+                for node in nodes.iter().cycle().take(node_count) {
+                    let insert_index = cloned_cursor.children().len();
+                    put_child_at(&mut cloned_cursor, node, this.arena, insert_index)?;
                 }
+                this.current_cursor_path
+                    .push(cloned_cursor.children().len() - 1);
+                Ok((
+                    cloned_cursor,
+                    EditLocation::Cursor,
+                    EditSuccess::PutChild { count: node_count },
+                ))
+            },
+        )
+    }
 
-                let edge = format!("node{} -> ", name);
-                digraph_edges.push_str(&edge);
-
-                for i in 0..node.children().len() {
+    /// Wraps the cursor and up to `prefix_count - 1` of its next siblings in a new node given by
+    /// `insertable` (e.g. a JSON array), and moves the cursor to the new node.  If the insertable's
+    /// count is more than 1, the nodes are wrapped that many times over.
+    pub fn wrap_cursor(
+        &mut self,
+        prefix_count: usize,
+        insertable: Insertable,
+    ) -> EditResult<Node::Class> {
+        let (depth, class) = match insertable {
+            Insertable::CountedNode(count, c) => (
+                count,
+                Node::Class::from_char(c).ok_or(EditErr::CharNotANode(c))?,
+            ),
+        };
+        if prefix_count == 0 || depth == 0 {
+            return Err(EditErr::NoNodesToInsert);
+        }
+        let wrapped_nodes = self.cursor_and_next_siblings(prefix_count);
+        self.perform_edit(
+            |this: &mut Self,
+             parent_and_index: Option<(&'arena Node, usize)>,
+             cursor: &'arena Node| {
+                // Build the new node from the inside out
+                let mut wrapper = Node::from_class(class);
+                for (i, node) in wrapped_nodes.iter().enumerate() {
+                    put_child_at(&mut wrapper, node, this.arena, i)?;
+                }
+                for _ in 1..depth {
+                    let mut outer_wrapper = Node::from_class(class);
+                    put_child_at(&mut outer_wrapper, this.arena.alloc(wrapper), this.arena, 0)?;
+                    wrapper = outer_wrapper;
+                }
+                let (parent, cursor_index) = match parent_and_index {
+                    Some(parent_and_index) => parent_and_index,
+                    // Wrapping the root makes a new root, which contains the old one
+                    None if cursor.is_valid_root(class) => {
+                        return Ok((
+                            wrapper,
+                            EditLocation::Cursor,
+                            EditSuccess::Wrap { class, count: 1 },
+                        ))
+                    }
+                    None => return Err(EditErr::CannotBeRoot(class)),
+                };
+                if !parent.is_valid_child(cursor_index, class) {
+                    return Err(EditErr::CannotBeChild {
+                        class,
+                        parent_name: parent.display_name(),
+                    });
+                }
+                let wrapper = this.arena.alloc(wrapper);
+                let mut cloned_parent = parent.clone();
+                // A single node with a class can be replaced directly, which works even if its
+                // parent can't have children removed (like a JSON field).  Otherwise (e.g. when
+                // wrapping the fields of a JSON object), the nodes are removed and the wrapper is
+                // inserted in their place, so that the parent can create any nodes it needs to
+                // hold the wrapper.
+                if wrapped_nodes.len() == 1 && cursor.class().is_some() {
+                    cloned_parent.replace_child(cursor_index, wrapper);
+                } else {
+                    for _ in 0..wrapped_nodes.len() {
+                        cloned_parent.delete_child(cursor_index)?;
+                    }
+                    cloned_parent.insert_child(wrapper, this.arena, cursor_index)?;
+                }
+                Ok((
+                    cloned_parent,
+                    EditLocation::Parent,
+                    EditSuccess::Wrap {
+                        class,
+                        count: wrapped_nodes.len(),
+                    },
+                ))
+            },
+        )
+    }
+
+    /// Replaces the cursor's parent with the cursor, and moves the cursor to where its parent was.
+    /// If the parent doesn't have a class (like a JSON field, which is part of its object), then
+    /// the parent's parent is replaced instead (so raising the value of a JSON field replaces the
+    /// object).  With a `count`, the cursor is raised that many times over as a single edit,
+    /// stopping at the root.
+    pub fn raise_cursor(&mut self, count: usize) -> EditResult<Node::Class> {
+        self.perform_edit(
+            |this: &mut Self,
+             parent_and_index: Option<(&'arena Node, usize)>,
+             cursor: &'arena Node| {
+                parent_and_index.ok_or(EditErr::RaisingRoot)?;
+                let class = cursor.class().ok_or_else(|| EditErr::CannotRaise {
+                    name: cursor.display_name(),
+                })?;
+                // Find the node that will be replaced, by going up a level for every raise (and an
+                // extra level past every ancestor which doesn't have a class)
+                let ancestors: Vec<&'arena Node> =
+                    this.current_cursor_path.node_iter(this.root()).collect();
+                let mut replaced_depth = this.current_cursor_path.depth();
+                let mut levels_raised = 0;
+                while levels_raised < count.max(1) && replaced_depth > 0 {
+                    replaced_depth -= 1;
+                    if ancestors[replaced_depth].class().is_none() && replaced_depth > 0 {
+                        replaced_depth -= 1;
+                    }
+                    levels_raised += 1;
+                }
+                let steps_above_cursor = this.current_cursor_path.depth() - replaced_depth;
+                // Check that the cursor can go where the replaced node was
+                let mut replaced_path = this.current_cursor_path.clone();
+                for _ in 0..steps_above_cursor {
+                    replaced_path.pop();
+                }
+                let replaced_index = replaced_path.last();
+                match replaced_path.cursor_and_parent(this.root()) {
+                    (root, None) => {
+                        if !root.is_valid_root(class) {
+                            return Err(EditErr::CannotBeRoot(class));
+                        }
+                    }
+                    (_, Some(replaced_parent)) => {
+                        // Unwrapping is safe, because a node with a parent has a non-empty path
+                        if !replaced_parent.is_valid_child(replaced_index.unwrap(), class) {
+                            return Err(EditErr::CannotBeChild {
+                                class,
+                                parent_name: replaced_parent.display_name(),
+                            });
+                        }
+                    }
+                }
+                this.current_cursor_path = replaced_path;
+                Ok((
+                    cursor.clone(),
+                    EditLocation::Ancestor(steps_above_cursor),
+                    EditSuccess::Raise {
+                        class,
+                        count: levels_raised,
+                    },
+                ))
+            },
+        )
+    }
+
+    /// Moves the cursor `count` places through its siblings, towards the given `side` (so moving 1
+    /// place swaps the cursor with one of its neighbours).  The cursor stays on the moved node, which
+    /// stops at the first or last child if there aren't enough siblings to move past.
+    pub fn move_node(&mut self, count: usize, side: Side) -> EditResult<Node::Class> {
+        self.perform_edit(
+            |this: &mut Self,
+             parent_and_index: Option<(&'arena Node, usize)>,
+             cursor: &'arena Node| {
+                let (parent, cursor_index) = parent_and_index.ok_or(EditErr::MovingRoot)?;
+                let new_index = match side {
+                    Side::Prev => cursor_index.saturating_sub(count),
+                    Side::Next => (cursor_index + count).min(parent.children().len() - 1),
+                };
+                if new_index == cursor_index {
+                    return Err(EditErr::NoSibling(side));
+                }
+                let mut cloned_parent = parent.clone();
+                cloned_parent.delete_child(cursor_index)?;
+                put_child_at(&mut cloned_parent, cursor, this.arena, new_index)?;
+                // We can unwrap here because the cursor isn't the root
+                *this.current_cursor_path.last_mut().unwrap() = new_index;
+                Ok((
+                    cloned_parent,
+                    EditLocation::Parent,
+                    EditSuccess::MoveNode {
+                        side,
+                        count: (new_index as isize - cursor_index as isize).unsigned_abs(),
+                    },
+                ))
+            },
+        )
+    }
+
+    /// Moves the cursor into its sibling on the given `side`, as that sibling's last child if it
+    /// came before the cursor or as its first child if it came after (so the sibling 'slurps' the
+    /// cursor, like in Paredit).  If the sibling doesn't have a class (like a JSON field), then the
+    /// cursor is moved into the sibling's last child (i.e. the field's value) instead.  The cursor
+    /// stays on the moved node.
+    pub fn slurp_cursor(&mut self, side: Side) -> EditResult<Node::Class> {
+        self.perform_edit(
+            |this: &mut Self,
+             parent_and_index: Option<(&'arena Node, usize)>,
+             cursor: &'arena Node| {
+                let (parent, cursor_index) = parent_and_index.ok_or(EditErr::MovingRoot)?;
+                let sibling_index = match side {
+                    Side::Prev => cursor_index.checked_sub(1),
+                    Side::Next => Some(cursor_index + 1),
+                }
+                .filter(|index| *index < parent.children().len())
+                .ok_or(EditErr::NoSibling(side))?;
+                let sibling = parent.children()[sibling_index];
+                let value_index = match (sibling.class(), sibling.children().len()) {
+                    (None, len) if len > 0 => Some(len - 1),
+                    _ => None,
+                };
+                let container = match value_index {
+                    Some(index) => sibling.children()[index],
+                    None => sibling,
+                };
+
+                let insert_index = match side {
+                    Side::Prev => container.children().len(),
+                    Side::Next => 0,
+                };
+                let mut new_node = container.clone();
+                put_child_at(&mut new_node, cursor, this.arena, insert_index)?;
+                if let Some(index) = value_index {
+                    let mut cloned_sibling = sibling.clone();
+                    cloned_sibling.replace_child(index, this.arena.alloc(new_node));
+                    new_node = cloned_sibling;
+                }
+                let mut cloned_parent = parent.clone();
+                cloned_parent.replace_child(sibling_index, this.arena.alloc(new_node));
+                cloned_parent.delete_child(cursor_index)?;
+
+                // Removing the cursor moves a sibling after it back by one place.  We can unwrap
+                // because the cursor isn't the root.
+                *this.current_cursor_path.last_mut().unwrap() = sibling_index.min(cursor_index);
+                if let Some(index) = value_index {
+                    this.current_cursor_path.push(index);
+                }
+                this.current_cursor_path.push(insert_index);
+                Ok((
+                    cloned_parent,
+                    EditLocation::Parent,
+                    EditSuccess::Slurp(side),
+                ))
+            },
+        )
+    }
+
+    /// Moves the cursor out of its parent, so that it becomes the sibling before or after its old
+    /// parent (so the parent 'barfs' the cursor, like in Paredit).  If the parent's parent doesn't
+    /// have a class (like a JSON field), then the cursor is put next to that node instead (so a
+    /// field moved out of the value of another field is put next to that field).  The cursor stays
+    /// on the moved node.
+    pub fn barf_cursor(&mut self, side: Side) -> EditResult<Node::Class> {
+        self.perform_edit(
+            |this: &mut Self,
+             parent_and_index: Option<(&'arena Node, usize)>,
+             cursor: &'arena Node| {
+                let (parent, cursor_index) = parent_and_index.ok_or(EditErr::MovingRoot)?;
+                let mut parent_path = this.current_cursor_path.clone();
+                parent_path.pop();
+                let (_, grandparent) = parent_path.cursor_and_parent(this.root());
+                let grandparent = grandparent.ok_or(EditErr::MovingOutOfRoot)?;
+
+                let mut new_node = parent.clone();
+                new_node.delete_child(cursor_index)?;
+                // Find the node that the cursor will be put next to, and the node containing it,
+                // replacing the nodes between the two with their edited copies
+                let mut next_to_path = parent_path.clone();
+                let mut container = grandparent;
+                let mut edit_location = EditLocation::Grandparent;
+                if grandparent.class().is_none() {
+                    let mut grandparent_path = parent_path.clone();
+                    grandparent_path.pop();
+                    if let (_, Some(great_grandparent)) =
+                        grandparent_path.cursor_and_parent(this.root())
+                    {
+                        let mut cloned_grandparent = grandparent.clone();
+                        // We can unwrap because the parent isn't the root
+                        cloned_grandparent
+                            .replace_child(parent_path.last().unwrap(), this.arena.alloc(new_node));
+                        new_node = cloned_grandparent;
+                        next_to_path = grandparent_path;
+                        container = great_grandparent;
+                        edit_location = EditLocation::GreatGrandparent;
+                    }
+                }
+
+                // We can unwrap because the node that the cursor is put next to isn't the root
+                let next_to_index = next_to_path.last().unwrap();
+                let mut cloned_container = container.clone();
+                cloned_container.replace_child(next_to_index, this.arena.alloc(new_node));
+                let insert_index = match side {
+                    Side::Prev => next_to_index,
+                    Side::Next => next_to_index + 1,
+                };
+                put_child_at(&mut cloned_container, cursor, this.arena, insert_index)?;
+
+                this.current_cursor_path = next_to_path;
+                *this.current_cursor_path.last_mut().unwrap() = insert_index;
+                Ok((cloned_container, edit_location, EditSuccess::Barf(side)))
+            },
+        )
+    }
+
+    /// Adds `delta` to the numeric value of the node under the cursor
+    pub fn increment_cursor(&mut self, delta: i64) -> EditResult<Node::Class> {
+        self.perform_edit(
+            |_this: &mut Self,
+             _parent_and_index: Option<(&'arena Node, usize)>,
+             cursor: &'arena Node| {
+                let new_node = cursor
+                    .increment(delta)
+                    .ok_or_else(|| EditErr::CannotIncrement {
+                        name: cursor.display_name(),
+                    })?;
+                Ok((
+                    new_node,
+                    EditLocation::Cursor,
+                    EditSuccess::Increment(delta),
+                ))
+            },
+        )
+    }
+
+    /// Replaces the text contents of the node under the cursor (e.g. the contents of a JSON
+    /// string) with `text`.
+    pub fn set_cursor_text(&mut self, text: String) -> EditResult<Node::Class> {
+        self.perform_edit(
+            |_this: &mut Self,
+             _parent_and_index: Option<(&'arena Node, usize)>,
+             cursor: &'arena Node| {
+                let mut cloned_cursor = cursor.clone();
+                cloned_cursor.set_text(text.clone())?;
+                let name = cloned_cursor.display_name();
+                Ok((
+                    cloned_cursor,
+                    EditLocation::Cursor,
+                    EditSuccess::SetText { name },
+                ))
+            },
+        )
+    }
+
+    /* DISPLAY METHODS */
+
+    /// Build the text representation of the current tree into the given [`String`]
+    pub fn write_text(&self, string: &mut String, format: &Node::FormatStyle) {
+        self.root().write_text(string, format);
+    }
+
+    /// Build and return a [`String`] of the current tree
+    pub fn to_text(&self, format: &Node::FormatStyle) -> String {
+        let mut s = String::new();
+        self.write_text(&mut s, format);
+        s
+    }
+
+    ///  Generate `.dot` code of the internal state of the `Dag`
+    pub fn to_dot_code(&self) -> String {
+        /// Helper function that recursively builds dot code for the entire subtree of a given node
+        fn add_to_graph<'arena, Node: Ast<'arena>>(
+            node: &'arena Node,
+            digraph_edges: &mut String,
+            hmap_nodes: &mut HashMap<usize, String>,
+        ) {
+            let name = node as *const Node as usize;
+            if hmap_nodes.get(&name).is_some() {
+                return;
+            }
+            hmap_nodes.insert(
+                name,
+                format!("node{} [label={:?}]\n", name, node.debug_name()),
+            );
+
+            if !node.children().is_empty() {
+                for &child in node.children() {
+                    add_to_graph(child, digraph_edges, hmap_nodes);
+                }
+
+                let edge = format!("node{} -> ", name);
+                digraph_edges.push_str(&edge);
+
+                for i in 0..node.children().len() {
                     let child_name = node.children()[i] as *const Node as usize;
                     if i != node.children().len() - 1 {
                         digraph_edges.push_str(&format!("node{}, ", child_name));
@@ -737,7 +1872,7 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
                         digraph_edges.push_str(&format!("node{}", child_name));
                     }
                 }
-                digraph_edges.push_str("\n");
+                digraph_edges.push('\n');
             }
         }
 
@@ -750,23 +1885,220 @@ impl<'arena, Node: Ast<'arena>> Dag<'arena, Node> {
             add_to_graph(snapshot.root, &mut digraph_edges, &mut hmap_nodes);
         }
         for value in hmap_nodes.values() {
-            dot_buffer.push_str(&value);
+            dot_buffer.push_str(value);
         }
-        dot_buffer.push_str("\n");
+        dot_buffer.push('\n');
 
         dot_buffer.push_str(&digraph_edges);
-        dot_buffer.push_str(&digraph_tail);
+        dot_buffer.push_str(digraph_tail);
 
         dot_buffer
     }
 }
 
+/* COMPACTION */
+
+/// A copy of a [`Dag`] which doesn't borrow any arena, made by a [`Detacher`].  Because the
+/// [`Arena`] never frees individual nodes, the only way to free the nodes which are no longer
+/// reachable (e.g. after snapshots are dropped from the undo tree) is to detach everything that is
+/// still reachable, drop the old arena, then [`attach`](DetachedDag::attach) everything to a new
+/// arena.
+#[derive(Debug, Clone)]
+pub struct DetachedDag {
+    /// The [`Snapshot`]s of the `Dag`, with their roots replaced by indices into the
+    /// [`DetachedNodes`]
+    snapshots: Vec<DetachedSnapshot>,
+    history_index: usize,
+    saved_history_index: Option<usize>,
+    current_cursor_path: Path,
+    /// The [visited children](Dag::visited_children) of the `Dag`'s nodes
+    visited_children: HashMap<Path, usize>,
+    undo_levels: Option<usize>,
+}
+
+/// A [`Snapshot`] whose root is an index into the [`DetachedNodes`]
+#[derive(Debug, Clone)]
+struct DetachedSnapshot {
+    cursor_before: Path,
+    root: usize,
+    cursor_after: Path,
+    parent: Option<usize>,
+    time: SystemTime,
+    description: String,
+    redo_child: Option<usize>,
+}
+
+/// Every node copied out of an arena by a [`Detacher`], stored as its
+/// [undo data](Ast::to_undo_data) and the indices of its children (which always come before it)
+#[derive(Debug, Clone)]
+pub struct DetachedNodes {
+    nodes: Vec<(serde_json::Value, Vec<usize>)>,
+}
+
+/// Copies nodes out of an arena to make [`DetachedDag`]s.  Nodes which are shared (between
+/// snapshots, between `Dag`s or with anything else detached by the same `Detacher`) are only
+/// copied once, so they are still shared once they are attached to a new arena.
+#[derive(Debug)]
+pub struct Detacher<'arena, Node: Ast<'arena>> {
+    nodes: Vec<(serde_json::Value, Vec<usize>)>,
+    indices: HashMap<*const Node, usize>,
+    _arena: PhantomData<&'arena Node>,
+}
+
+impl<'arena, Node: Ast<'arena>> Detacher<'arena, Node> {
+    /// Creates a `Detacher` which hasn't copied any nodes
+    pub fn new() -> Self {
+        Detacher {
+            nodes: Vec::new(),
+            indices: HashMap::new(),
+            _arena: PhantomData,
+        }
+    }
+
+    /// Copies a node and its descendants (unless they have already been copied), returning the
+    /// node's index in the [`DetachedDag`].  This is used to detach nodes which aren't part of the
+    /// undo tree, such as the contents of registers.
+    pub fn detach_node(&mut self, node: &'arena Node) -> usize {
+        if let Some(index) = self.indices.get(&(node as *const Node)) {
+            return *index;
+        }
+        let child_indices: Vec<usize> = node
+            .children()
+            .iter()
+            .map(|child| self.detach_node(*child))
+            .collect();
+        self.nodes.push((node.to_undo_data(), child_indices));
+        self.indices
+            .insert(node as *const Node, self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    /// Copies every [`Snapshot`] in a [`Dag`] (along with the nodes they use)
+    pub fn detach_dag(&mut self, dag: &Dag<'arena, Node>) -> DetachedDag {
+        let snapshots = dag
+            .root_history
+            .iter()
+            .map(|snapshot| DetachedSnapshot {
+                cursor_before: snapshot.cursor_before.clone(),
+                root: self.detach_node(snapshot.root),
+                cursor_after: snapshot.cursor_after.clone(),
+                parent: snapshot.parent,
+                time: snapshot.time,
+                description: snapshot.description.clone(),
+                redo_child: snapshot.redo_child,
+            })
+            .collect();
+        DetachedDag {
+            snapshots,
+            history_index: dag.history_index,
+            saved_history_index: dag.saved_history_index,
+            current_cursor_path: dag.current_cursor_path.clone(),
+            visited_children: dag.visited_children.clone(),
+            undo_levels: dag.undo_levels,
+        }
+    }
+
+    /// Returns every node that has been copied, which have to be
+    /// [attached](DetachedNodes::attach) before any [`DetachedDag`]
+    pub fn finish(self) -> DetachedNodes {
+        DetachedNodes { nodes: self.nodes }
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> Default for Detacher<'arena, Node> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DetachedNodes {
+    /// Copies every node into `arena`, returning the attached nodes indexed by the indices
+    /// returned by [`Detacher::detach_node`].
+    ///
+    /// # Panics
+    /// Panics if the nodes were detached from a different [`Ast`] to `Node`.
+    pub fn attach<'arena, Node: Ast<'arena>>(
+        self,
+        arena: &'arena Arena<Node>,
+    ) -> Vec<&'arena Node> {
+        let mut nodes: Vec<&'arena Node> = Vec::with_capacity(self.nodes.len());
+        for (data, child_indices) in &self.nodes {
+            let children = child_indices.iter().map(|i| nodes[*i]).collect();
+            let node = Node::from_undo_data(data, children)
+                .expect("Detached nodes should always be valid");
+            nodes.push(arena.alloc(node));
+        }
+        nodes
+    }
+}
+
+impl DetachedDag {
+    /// Rebuilds the [`Dag`] exactly as it was detached, using the `nodes` returned by
+    /// [`DetachedNodes::attach`]
+    pub fn attach<'arena, Node: Ast<'arena>>(
+        self,
+        arena: &'arena Arena<Node>,
+        nodes: &[&'arena Node],
+    ) -> Dag<'arena, Node> {
+        let root_history = self
+            .snapshots
+            .into_iter()
+            .map(|snapshot| Snapshot {
+                cursor_before: snapshot.cursor_before,
+                root: nodes[snapshot.root],
+                cursor_after: snapshot.cursor_after,
+                parent: snapshot.parent,
+                time: snapshot.time,
+                description: snapshot.description,
+                redo_child: snapshot.redo_child,
+            })
+            .collect();
+        Dag {
+            arena,
+            root_history,
+            history_index: self.history_index,
+            saved_history_index: self.saved_history_index,
+            current_cursor_path: self.current_cursor_path,
+            visited_children: self.visited_children,
+            undo_levels: self.undo_levels,
+        }
+    }
+}
+
+/// Converts a [`Side`] into either `"previous"` or `"next"`
+fn side_adjective(side: Side) -> &'static str {
+    match side {
+        Side::Prev => "previous",
+        Side::Next => "next",
+    }
+}
+
+/// Inserts an existing `node` as the `index`th child of `parent`, checking that it is allowed to
+/// go there.  Nodes without a [`class`](Ast::class) are validated by [`Ast::insert_child`] instead.
+fn put_child_at<'arena, Node: Ast<'arena>>(
+    parent: &mut Node,
+    node: &'arena Node,
+    arena: &'arena Arena<Node>,
+    index: usize,
+) -> Result<(), EditErr<Node::Class>> {
+    if let Some(class) = node.class() {
+        if !parent.is_valid_child(index, class) {
+            return Err(EditErr::CannotBeChild {
+                class,
+                parent_name: parent.display_name(),
+            });
+        }
+    }
+    parent.insert_child(node, arena, index)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Dag, EditErr, EditResult, EditSuccess, Insertable};
+    use super::{Dag, Detacher, EditErr, EditResult, EditSuccess, Insertable};
     use crate::arena::Arena;
     use crate::ast::json::{add_value_to_arena, Class, Json, JsonFormat};
-    use crate::ast::Ast;
+    use crate::ast::{Ast, InsertError, TextError};
     use crate::core::{Direction, Path, Side};
     use crate::editor::normal_mode::Action;
 
@@ -787,13 +2119,43 @@ mod tests {
             match action {
                 Action::Undo => self.undo(count),
                 Action::Redo => self.redo(count),
+                Action::OlderState => self.undo_chronologically(count),
+                Action::NewerState => self.redo_chronologically(count),
                 Action::MoveCursor(direction) => self.move_cursor(count, direction),
                 Action::Replace(c) => self.replace_cursor(count, c),
                 Action::InsertChild(c) => self.insert_child(count, c),
                 Action::InsertBefore(c) => self.insert_next_to_cursor(count, c, Side::Prev),
                 Action::InsertAfter(c) => self.insert_next_to_cursor(count, c, Side::Next),
-                Action::Delete => self.delete_cursor(count),
-                Action::Quit | Action::Write => unreachable!(),
+                Action::Delete(_) => self.delete_cursor(count),
+                Action::Wrap(c) => self.wrap_cursor(count, c),
+                Action::Raise => self.raise_cursor(count),
+                Action::MoveNode(side) => self.move_node(count, side),
+                Action::Slurp(side) => self.slurp_cursor(side),
+                Action::Barf(side) => self.barf_cursor(side),
+                Action::Increment => self.increment_cursor(count as i64),
+                Action::Decrement => self.increment_cursor(-(count as i64)),
+                Action::Quit
+                | Action::Write
+                | Action::EditText
+                | Action::CommandMode
+                | Action::Search(_)
+                | Action::RepeatSearch(_)
+                | Action::VisualMode
+                | Action::SelectSimilar
+                | Action::UndoTree
+                | Action::PageUp
+                | Action::PageDown
+                | Action::CenterCursor
+                | Action::NextWindow
+                | Action::PrevWindow
+                | Action::SplitWindow(_)
+                | Action::CloseWindow
+                | Action::OnlyWindow
+                | Action::WindowInDirection(_)
+                | Action::Yank(_)
+                | Action::PutBefore(_)
+                | Action::PutAfter(_)
+                | Action::PutChild(_) => unreachable!(),
             }
         }
     }
@@ -1113,7 +2475,7 @@ mod tests {
         run_test_err(
             json!([]),
             Path::root(),
-            Action::Delete,
+            Action::Delete('"'),
             EditErr::DeletingRoot,
         );
 
@@ -1121,7 +2483,7 @@ mod tests {
         run_test_err(
             json!([[], true]),
             Path::root(),
-            Action::Delete,
+            Action::Delete('"'),
             EditErr::DeletingRoot,
         );
     }
@@ -1132,7 +2494,7 @@ mod tests {
         run_test_ok(
             json!([true, []]),
             Path::from_vec(vec![0]),
-            Action::Delete,
+            Action::Delete('"'),
             EditSuccess::Delete {
                 name: "true".to_string(),
             },
@@ -1143,7 +2505,7 @@ mod tests {
         run_test_ok(
             json!([true, []]),
             Path::from_vec(vec![1]),
-            Action::Delete,
+            Action::Delete('"'),
             EditSuccess::Delete {
                 name: "array".to_string(),
             },
@@ -1154,7 +2516,7 @@ mod tests {
         run_test_ok(
             json!([true]),
             Path::from_vec(vec![0]),
-            Action::Delete,
+            Action::Delete('"'),
             EditSuccess::Delete {
                 name: "true".to_string(),
             },
@@ -1170,7 +2532,7 @@ mod tests {
             json!([true, []]),
             Path::from_vec(vec![0]),
             2,
-            Action::Delete,
+            Action::Delete('"'),
             EditSuccess::Delete {
                 name: "true".to_string(),
             },
@@ -1182,7 +2544,7 @@ mod tests {
             json!([true, null, false, []]),
             Path::from_vec(vec![1]),
             2,
-            Action::Delete,
+            Action::Delete('"'),
             EditSuccess::Delete {
                 name: "null".to_string(),
             },
@@ -1196,7 +2558,7 @@ mod tests {
             json!([true, null, false, []]),
             Path::from_vec(vec![2]),
             4,
-            Action::Delete,
+            Action::Delete('"'),
             EditSuccess::Delete {
                 name: "false".to_string(),
             },
@@ -1328,46 +2690,291 @@ mod tests {
         );
     }
 
-    /* UNDO/REDO */
+    #[test]
+    fn move_down_to_visited_child() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(json!([[true, false, null], [0, 1]]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![0, 2]));
+        // Moving up and back down returns to the same child, even through several levels
+        dag.move_cursor(2, Direction::Up).unwrap();
+        dag.move_cursor(2, Direction::Down).unwrap();
+        assert_eq!(dag.cursor_path(), &Path::from_vec(vec![0, 2]));
+        // The child is remembered separately for each parent, and moving into a node for the
+        // first time goes to its first child
+        dag.move_cursor(1, Direction::Up).unwrap();
+        dag.move_cursor(1, Direction::Next).unwrap();
+        dag.move_cursor(1, Direction::Down).unwrap();
+        assert_eq!(dag.cursor_path(), &Path::from_vec(vec![1, 0]));
+        // Editing the second array doesn't change the first, so it keeps its visited child
+        dag.move_cursor(1, Direction::Next).unwrap();
+        dag.delete_cursor(1).unwrap();
+        dag.move_cursor(1, Direction::Up).unwrap();
+        dag.move_cursor(1, Direction::Prev).unwrap();
+        dag.move_cursor(1, Direction::Down).unwrap();
+        assert_eq!(dag.cursor_path(), &Path::from_vec(vec![0, 2]));
+        // Nodes which no longer exist are forgotten
+        assert!(dag.visited_children.contains_key(&Path::from_vec(vec![1])));
+        dag.move_cursor(1, Direction::Up).unwrap();
+        dag.delete_cursor(1).unwrap();
+        assert!(!dag.visited_children.contains_key(&Path::from_vec(vec![1])));
+    }
 
     #[test]
-    fn undo_root() {
-        let start_tree = json!([]);
-        let start_cursor_location = Path::root();
-        let end_tree = json!(false);
-        let end_cursor_location = Path::root();
+    fn move_down_to_visited_child_after_edit() {
+        // The visited child is remembered for the node, even when an edit moves that node
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(json!([[true, false, null]]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![0, 2]));
+        dag.move_cursor(1, Direction::Up).unwrap();
+        dag.insert_next_to_cursor(1, Insertable::CountedNode(1, 'n'), Side::Prev)
+            .unwrap();
+        assert_eq!(dag.cursor_path(), &Path::from_vec(vec![0]));
+        dag.move_cursor(1, Direction::Next).unwrap();
+        dag.move_cursor(1, Direction::Down).unwrap();
+        assert_eq!(dag.cursor_path(), &Path::from_vec(vec![1, 2]));
+        // The remembered child index also follows its node
+        dag.move_cursor(1, Direction::Up).unwrap();
+        dag.move_cursor(1, Direction::Prev).unwrap();
+        dag.delete_cursor(1).unwrap();
+        let expected: std::collections::HashMap<Path, usize> =
+            vec![(Path::root(), 0), (Path::from_vec(vec![0]), 2)]
+                .into_iter()
+                .collect();
+        assert_eq!(dag.visited_children, expected);
+    }
 
-        // Initialise the Dag with the `start_tree`
+    #[test]
+    fn move_path_without_remembering() {
         let arena: Arena<Json> = Arena::new();
-        let root = add_value_to_arena(start_tree.clone(), &arena);
-        let mut dag = Dag::new(&arena, root, start_cursor_location.clone());
+        let root = add_value_to_arena(json!([[true, false, null]]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![0, 2]));
+        dag.move_cursor(1, Direction::Up).unwrap();
+        // Moving another path uses the remembered children, but doesn't change them
+        let mut path = Path::from_vec(vec![0, 1]);
+        dag.move_path(&mut path, 1, Direction::Up).unwrap();
+        dag.move_path(&mut path, 1, Direction::Down).unwrap();
+        assert_eq!(path, Path::from_vec(vec![0, 2]));
+        dag.move_path(&mut path, 2, Direction::Prev).unwrap();
+        dag.move_path(&mut path, 1, Direction::Up).unwrap();
+        assert_eq!(dag.cursor_path(), &Path::from_vec(vec![0]));
+        dag.move_cursor(1, Direction::Down).unwrap();
+        assert_eq!(dag.cursor_path(), &Path::from_vec(vec![0, 2]));
+    }
 
-        // We start with an empty Json array (`[]`), and we replace it with `false`
-        assert_eq!(
-            Ok(EditSuccess::Replace(Class::False)),
-            dag.replace_cursor(1, Insertable::CountedNode(1, 'f')),
-            "Not equal in action result"
-        );
-        assert_eq!(*dag.root(), end_tree, "Not equal in tree.");
-        assert_eq!(
-            end_cursor_location, dag.current_cursor_path,
-            "Not equal in cursor location."
-        );
+    #[test]
+    fn move_down_to_visited_child_of_interned_node() {
+        // Identical subtrees are the same node in an interning arena, but each is remembered
+        // separately
+        let arena: Arena<Json> = Arena::with_interning();
+        let root = add_value_to_arena(json!([[true, false], [true, false]]), &arena);
+        assert!(std::ptr::eq(root.children()[0], root.children()[1]));
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![0, 1]));
+        dag.move_cursor(1, Direction::Up).unwrap();
+        dag.move_cursor(1, Direction::Next).unwrap();
+        dag.move_cursor(1, Direction::Down).unwrap();
+        assert_eq!(dag.cursor_path(), &Path::from_vec(vec![1, 0]));
+    }
 
-        // Undo the change
-        assert_eq!(
-            Ok(EditSuccess::Undo),
-            dag.undo(1),
-            "Not equal in action result"
+    #[test]
+    fn move_to_last_child() {
+        test_movement(
+            json!([true, [false, null]]),
+            Path::root(),
+            2,
+            Direction::LastChild,
+            Path::from_vec(vec![1, 1]),
         );
-        assert_eq!(*dag.root(), start_tree, "Not equal in tree.");
-        assert_eq!(
-            start_cursor_location, dag.current_cursor_path,
-            "Not equal in cursor location."
+        test_capped_movement(
+            json!([true, [false, null]]),
+            Path::root(),
+            3,
+            Direction::LastChild,
+            2,
+            Path::from_vec(vec![1, 1]),
         );
+    }
 
-        // Dag level == 1
-        let start_tree = json!([null, true, false]);
+    #[test]
+    fn move_to_ends() {
+        // Jumping to the first or last sibling (or the root) without a count goes all the way
+        test_capped_movement(
+            json!([true, false, null, 0]),
+            Path::from_vec(vec![1]),
+            1,
+            Direction::LastSibling,
+            2,
+            Path::from_vec(vec![3]),
+        );
+        test_capped_movement(
+            json!([true, false, null, 0]),
+            Path::from_vec(vec![2]),
+            1,
+            Direction::FirstSibling,
+            2,
+            Path::from_vec(vec![0]),
+        );
+        test_capped_movement(
+            json!([true, [false, null]]),
+            Path::from_vec(vec![1, 0]),
+            1,
+            Direction::Root,
+            2,
+            Path::root(),
+        );
+        // A count picks the sibling counted from that end, which can be in either direction ...
+        test_capped_movement(
+            json!([true, false, null, 0]),
+            Path::from_vec(vec![0]),
+            3,
+            Direction::FirstSibling,
+            2,
+            Path::from_vec(vec![2]),
+        );
+        test_capped_movement(
+            json!([true, false, null, 0]),
+            Path::from_vec(vec![3]),
+            2,
+            Direction::LastSibling,
+            1,
+            Path::from_vec(vec![2]),
+        );
+        test_capped_movement(
+            json!([true, false, null, 0]),
+            Path::from_vec(vec![0]),
+            5,
+            Direction::FirstSibling,
+            3,
+            Path::from_vec(vec![3]),
+        );
+        // ... or the cursor's ancestor at that depth, stopping at the cursor
+        test_capped_movement(
+            json!([[[true]], null]),
+            Path::from_vec(vec![0, 0, 0]),
+            2,
+            Direction::Root,
+            2,
+            Path::from_vec(vec![0]),
+        );
+        test_capped_movement(
+            json!([[[true]], null]),
+            Path::from_vec(vec![0, 0]),
+            4,
+            Direction::Root,
+            0,
+            Path::from_vec(vec![0, 0]),
+        );
+        run_test_err(
+            json!([]),
+            Path::root(),
+            Action::MoveCursor(Direction::LastSibling),
+            EditErr::MoveToSiblingOfRoot,
+        );
+    }
+
+    #[test]
+    fn move_preorder() {
+        // Moving forward goes into the children, then out to the next node
+        test_movement(
+            json!([[true, []], {"a": null}, 0]),
+            Path::from_vec(vec![0, 1]),
+            2,
+            Direction::PreorderNext,
+            Path::from_vec(vec![1, 0]),
+        );
+        test_capped_movement(
+            json!([[true, []], {"a": null}, 0]),
+            Path::from_vec(vec![1, 0, 1]),
+            3,
+            Direction::PreorderNext,
+            1,
+            Path::from_vec(vec![2]),
+        );
+        // Moving back goes to the last descendant of the previous sibling
+        test_movement(
+            json!([[true, []], {"a": null}, 0]),
+            Path::from_vec(vec![1]),
+            1,
+            Direction::PreorderPrev,
+            Path::from_vec(vec![0, 1]),
+        );
+        test_capped_movement(
+            json!([[true, []], {"a": null}, 0]),
+            Path::from_vec(vec![0, 0]),
+            3,
+            Direction::PreorderPrev,
+            2,
+            Path::root(),
+        );
+    }
+
+    #[test]
+    fn move_between_leaves() {
+        test_movement(
+            json!([[true, []], {"a": null}, 0]),
+            Path::root(),
+            3,
+            Direction::NextLeaf,
+            Path::from_vec(vec![1, 0, 0]),
+        );
+        // The cursor stays on the last leaf, even if there are more nodes after it
+        test_capped_movement(
+            json!([[true, []], {"a": null}, [[]]]),
+            Path::from_vec(vec![1, 0, 1]),
+            3,
+            Direction::NextLeaf,
+            1,
+            Path::from_vec(vec![2, 0]),
+        );
+        test_capped_movement(
+            json!([[true, []], {"a": null}, 0]),
+            Path::from_vec(vec![2]),
+            10,
+            Direction::PrevLeaf,
+            4,
+            Path::from_vec(vec![0, 0]),
+        );
+    }
+
+    /* UNDO/REDO */
+
+    #[test]
+    fn undo_root() {
+        let start_tree = json!([]);
+        let start_cursor_location = Path::root();
+        let end_tree = json!(false);
+        let end_cursor_location = Path::root();
+
+        // Initialise the Dag with the `start_tree`
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(start_tree.clone(), &arena);
+        let mut dag = Dag::new(&arena, root, start_cursor_location.clone());
+
+        // We start with an empty Json array (`[]`), and we replace it with `false`
+        assert_eq!(
+            Ok(EditSuccess::Replace(Class::False)),
+            dag.replace_cursor(1, Insertable::CountedNode(1, 'f')),
+            "Not equal in action result"
+        );
+        assert_eq!(*dag.root(), end_tree, "Not equal in tree.");
+        assert_eq!(
+            end_cursor_location, dag.current_cursor_path,
+            "Not equal in cursor location."
+        );
+
+        // Undo the change
+        assert_eq!(
+            Ok(EditSuccess::Undo),
+            dag.undo(1),
+            "Not equal in action result"
+        );
+        assert_eq!(*dag.root(), start_tree, "Not equal in tree.");
+        assert_eq!(
+            start_cursor_location, dag.current_cursor_path,
+            "Not equal in cursor location."
+        );
+
+        // Dag level == 1
+        let start_tree = json!([null, true, false]);
         let start_cursor_location = Path::root();
         // We move the cursor so we expect the cursor to move but no change to occur to the tree
         let end_cursor_location = Path::from_vec(vec![0]);
@@ -1670,6 +3277,170 @@ mod tests {
         assert_eq!(dag.root().to_text(&JsonFormat::Compact), tree_5_str);
     }
 
+    #[test]
+    fn undo_tree_branches() {
+        let arena: Arena<Json> = Arena::new();
+        let mut dag = Dag::new(&arena, add_value_to_arena(json!([]), &arena), Path::root());
+        // Snapshot 1: `[<true>]`
+        dag.insert_child(1, Insertable::CountedNode(1, 't'))
+            .unwrap();
+        // Snapshot 2: `[true, <null>]`
+        dag.insert_next_to_cursor(1, Insertable::CountedNode(1, 'n'), Side::Next)
+            .unwrap();
+        // Undoing and then making a change creates a new branch instead of removing snapshot 2
+        dag.undo(1).unwrap();
+        // Snapshot 3: `[<false>]`
+        dag.replace_cursor(1, Insertable::CountedNode(1, 'f'))
+            .unwrap();
+        assert_eq!(*dag.root(), json!([false]));
+        assert_eq!(dag.history_index(), 3);
+        assert_eq!(dag.history()[3].parent, Some(1));
+        assert_eq!(dag.branch_tips(), vec![2, 3]);
+        assert_eq!(dag.history()[0].description, "original tree");
+        assert_eq!(dag.history()[3].description, "replace with false");
+        assert_eq!(dag.redo(1), Err(EditErr::NoChangesToRedo));
+        // Undo and redo follow the branch that was most recently used ...
+        dag.undo(1).unwrap();
+        dag.redo(1).unwrap();
+        assert_eq!(*dag.root(), json!([false]));
+        // ... and jumping to a snapshot makes its branch the most recently used
+        assert_eq!(dag.jump_to_snapshot(2), Ok(EditSuccess::JumpToSnapshot(2)));
+        assert_eq!(*dag.root(), json!([true, null]));
+        assert_eq!(dag.cursor_path(), &Path::from_vec(vec![1]));
+        dag.undo(2).unwrap();
+        assert_eq!(*dag.root(), json!([]));
+        dag.redo(2).unwrap();
+        assert_eq!(*dag.root(), json!([true, null]));
+        assert_eq!(dag.jump_to_snapshot(4), Err(EditErr::NoSuchSnapshot(4)));
+        // Moving chronologically visits snapshots in the order that they were made, regardless
+        // of branches
+        assert_eq!(
+            dag.redo_chronologically(1),
+            Ok(EditSuccess::JumpToSnapshot(3))
+        );
+        assert_eq!(*dag.root(), json!([false]));
+        assert_eq!(dag.redo_chronologically(1), Err(EditErr::NoChangesToRedo));
+        assert_eq!(
+            dag.undo_chronologically(2),
+            Ok(EditSuccess::JumpToSnapshot(1))
+        );
+        assert_eq!(*dag.root(), json!([true]));
+        assert_eq!(
+            dag.undo_chronologically(5),
+            Ok(EditSuccess::JumpToSnapshot(0))
+        );
+        assert_eq!(dag.undo_chronologically(1), Err(EditErr::NoChangesToUndo));
+    }
+
+    #[test]
+    fn undo_levels() {
+        let arena: Arena<Json> = Arena::new();
+        let mut dag = Dag::new(&arena, add_value_to_arena(json!([]), &arena), Path::root());
+        // Snapshot 1 is `[true]`, and its children are 2 (`[true, null]`) and 3 (`[false]`)
+        dag.insert_child(1, Insertable::CountedNode(1, 't'))
+            .unwrap();
+        dag.insert_next_to_cursor(1, Insertable::CountedNode(1, 'n'), Side::Next)
+            .unwrap();
+        dag.mark_saved();
+        dag.undo(1).unwrap();
+        dag.replace_cursor(1, Insertable::CountedNode(1, 'f'))
+            .unwrap();
+        // The original tree only has one child, so it is dropped first
+        dag.set_undo_levels(Some(2));
+        assert_eq!(dag.history().len(), 3);
+        assert_eq!(*dag.history()[0].root, json!([true]));
+        assert_eq!(dag.history()[0].parent, None);
+        assert_eq!(dag.history_index(), 2);
+        assert_eq!(dag.branch_tips(), vec![1, 2]);
+        // Then the branch which doesn't lead to the current snapshot is dropped, along with the
+        // saved tree
+        dag.set_undo_levels(Some(1));
+        assert_eq!(dag.history().len(), 2);
+        assert_eq!(dag.history()[1].parent, Some(0));
+        assert_eq!(dag.history_index(), 1);
+        assert!(dag.is_modified());
+        assert_eq!(dag.undo(2), Ok(EditSuccess::Undo));
+        assert_eq!(*dag.root(), json!([true]));
+        assert_eq!(dag.undo(1), Err(EditErr::NoChangesToUndo));
+        dag.redo(1).unwrap();
+        assert_eq!(*dag.root(), json!([false]));
+        // New changes keep the undo tree within the limit
+        dag.insert_next_to_cursor(1, Insertable::CountedNode(1, 'n'), Side::Next)
+            .unwrap();
+        assert_eq!(dag.history().len(), 2);
+        assert_eq!(*dag.history()[0].root, json!([false]));
+        assert_eq!(*dag.root(), json!([false, null]));
+    }
+
+    #[test]
+    fn interning() {
+        let arena: Arena<Json> = Arena::with_interning();
+        let root = add_value_to_arena(json!([true, [true], true]), &arena);
+        assert!(std::ptr::eq(root.children()[0], root.children()[2]));
+        assert!(std::ptr::eq(
+            root.children()[0],
+            root.children()[1].children()[0]
+        ));
+        // Nodes are still identified by their paths, even though they are shared
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![2]));
+        dag.replace_cursor(1, Insertable::CountedNode(1, 'f'))
+            .unwrap();
+        assert_eq!(*dag.root(), json!([true, [true], false]));
+        // Undoing a change by hand gives back the same tree, which is the same node as before
+        dag.replace_cursor(1, Insertable::CountedNode(1, 't'))
+            .unwrap();
+        assert!(std::ptr::eq(dag.root(), dag.history()[0].root));
+    }
+
+    #[test]
+    fn detach_and_attach() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(json!([[true], []]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![1]));
+        dag.replace_cursor(1, Insertable::CountedNode(1, 'f'))
+            .unwrap();
+        dag.mark_saved();
+        dag.undo(1).unwrap();
+        dag.set_undo_levels(Some(10));
+        dag.move_cursor(1, Direction::Up).unwrap();
+        let unused = add_value_to_arena(json!(null), &arena);
+
+        let mut detacher = Detacher::new();
+        let unused_index = detacher.detach_node(unused);
+        let shared_index = detacher.detach_node(root.children()[0]);
+        let detached = detacher.detach_dag(&dag);
+        let detached_nodes = detacher.finish();
+
+        let new_arena: Arena<Json> = Arena::new();
+        let nodes = detached_nodes.attach(&new_arena);
+        let mut new_dag = detached.attach(&new_arena, &nodes);
+        // Both snapshots share `[true]`, so it (and its child) are only attached once
+        assert_eq!(new_arena.len(), 7);
+        let history = new_dag.history();
+        assert_eq!(*history[0].root, json!([[true], []]));
+        assert_eq!(*history[1].root, json!([[true], false]));
+        assert!(std::ptr::eq(
+            history[0].root.children()[0],
+            history[1].root.children()[0]
+        ));
+        assert!(std::ptr::eq(
+            nodes[shared_index],
+            history[0].root.children()[0]
+        ));
+        assert_eq!(*nodes[unused_index], json!(null));
+        // The rest of the `Dag` is unchanged
+        assert_eq!(history[1].description, "replace with false");
+        assert_eq!(new_dag.history_index(), 0);
+        assert_eq!(new_dag.cursor_path(), &Path::root());
+        assert_eq!(new_dag.undo_levels, Some(10));
+        // The cursor still goes back down to the child it was in
+        new_dag.move_cursor(1, Direction::Down).unwrap();
+        assert_eq!(new_dag.cursor_path(), &Path::from_vec(vec![1]));
+        assert!(new_dag.is_modified());
+        new_dag.redo(1).unwrap();
+        assert!(!new_dag.is_modified());
+    }
+
     /* INSERT CHILD */
 
     #[test]
@@ -1864,6 +3635,725 @@ mod tests {
         );
     }
 
+    /* PUT */
+
+    #[test]
+    fn put_next_to_cursor() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(json!([[1, 2], true, {"key": false}]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![0]));
+        // Yanking takes the cursor and its next siblings, as far as they go
+        let nodes = dag.cursor_and_next_siblings(2);
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(dag.cursor_and_next_siblings(10).len(), 3);
+        assert_eq!(
+            Dag::new(&arena, root, Path::root())
+                .cursor_and_next_siblings(3)
+                .len(),
+            1
+        );
+        // Put after the cursor
+        dag.current_cursor_path = Path::from_vec(vec![1]);
+        assert_eq!(
+            dag.put_next_to_cursor(1, &nodes, Side::Next),
+            Ok(EditSuccess::PutNextToCursor {
+                side: Side::Next,
+                count: 2
+            })
+        );
+        assert_eq!(
+            *dag.root(),
+            json!([[1, 2], true, [1, 2], true, {"key": false}])
+        );
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![3]));
+        // The put nodes are shared with the originals, not copied
+        assert!(std::ptr::eq(dag.root().children()[2], nodes[0]));
+        // Put with a count before the cursor
+        assert_eq!(
+            dag.put_next_to_cursor(2, &nodes[1..], Side::Prev),
+            Ok(EditSuccess::PutNextToCursor {
+                side: Side::Prev,
+                count: 2
+            })
+        );
+        assert_eq!(
+            *dag.root(),
+            json!([[1, 2], true, [1, 2], true, true, true, {"key": false}])
+        );
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![4]));
+        // Invalid puts don't change the tree
+        assert_eq!(
+            dag.put_next_to_cursor(1, &[], Side::Next),
+            Err(EditErr::NoNodesToInsert)
+        );
+        dag.current_cursor_path = Path::root();
+        assert_eq!(
+            dag.put_next_to_cursor(1, &nodes, Side::Next),
+            Err(EditErr::AddSiblingToRoot)
+        );
+        // The key of a field must be a string
+        dag.current_cursor_path = Path::from_vec(vec![6, 0, 0]);
+        assert_eq!(
+            dag.put_next_to_cursor(1, &nodes, Side::Prev),
+            Err(EditErr::CannotBeChild {
+                class: Class::Array,
+                parent_name: "field".to_string(),
+            })
+        );
+        assert_eq!(
+            *dag.root(),
+            json!([[1, 2], true, [1, 2], true, true, true, {"key": false}])
+        );
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![6, 0, 0]));
+    }
+
+    #[test]
+    fn put_child() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(json!([{"a": true}, [], null, {"b": false}]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![3, 0]));
+        let field = dag.cursor_and_next_siblings(1);
+        // Fields can be put into objects ...
+        dag.current_cursor_path = Path::from_vec(vec![0]);
+        assert_eq!(
+            dag.put_child(1, &field),
+            Ok(EditSuccess::PutChild { count: 1 })
+        );
+        assert_eq!(
+            *dag.root(),
+            json!([{"a": true, "b": false}, [], null, {"b": false}])
+        );
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![0, 1]));
+        // ... but not into arrays
+        dag.current_cursor_path = Path::from_vec(vec![1]);
+        assert_eq!(
+            dag.put_child(1, &field),
+            Err(EditErr::InsertError(InsertError::InvalidChild {
+                name: "array".to_string(),
+                child_name: "field".to_string(),
+            }))
+        );
+        // Other values can be put into arrays, and are given an empty key in objects
+        let null = dag.root().children()[2];
+        assert_eq!(
+            dag.put_child(2, &[null]),
+            Ok(EditSuccess::PutChild { count: 2 })
+        );
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![1, 1]));
+        dag.current_cursor_path = Path::from_vec(vec![3]);
+        assert_eq!(
+            dag.put_child(1, &[null]),
+            Ok(EditSuccess::PutChild { count: 1 })
+        );
+        assert_eq!(
+            *dag.root(),
+            json!([{"a": true, "b": false}, [null, null], null, {"b": false, "": null}])
+        );
+        // Nodes can't be put into nodes which can't have children
+        dag.current_cursor_path = Path::from_vec(vec![2]);
+        assert_eq!(
+            dag.put_child(1, &[null]),
+            Err(EditErr::CannotBeChild {
+                class: Class::Null,
+                parent_name: "null".to_string(),
+            })
+        );
+        assert_eq!(dag.put_child(0, &[null]), Err(EditErr::NoNodesToInsert));
+    }
+
+    /* WRAP */
+
+    #[test]
+    fn wrap_cursor() {
+        let wrap = |c: char| Action::Wrap(Insertable::CountedNode(1, c));
+        // Wrapping several siblings in an array
+        run_test_ok_count(
+            json!([true, false, null, 0]),
+            Path::from_vec(vec![1]),
+            2,
+            wrap('a'),
+            EditSuccess::Wrap {
+                class: Class::Array,
+                count: 2,
+            },
+            json!([true, [false, null], 0]),
+            Path::from_vec(vec![1]),
+        );
+        // The count is capped to the number of siblings after the cursor
+        run_test_ok_count(
+            json!([true, false]),
+            Path::from_vec(vec![1]),
+            5,
+            Action::Wrap(Insertable::CountedNode(2, 'a')),
+            EditSuccess::Wrap {
+                class: Class::Array,
+                count: 1,
+            },
+            json!([true, [[false]]]),
+            Path::from_vec(vec![1]),
+        );
+        // Values wrapped in objects are given an empty key
+        run_test_ok(
+            json!({"a": 1}),
+            Path::from_vec(vec![0, 1]),
+            wrap('o'),
+            EditSuccess::Wrap {
+                class: Class::Object,
+                count: 1,
+            },
+            json!({"a": {"": 1}}),
+            Path::from_vec(vec![0, 1]),
+        );
+        // Fields can be wrapped in an object, which is given an empty key in its parent
+        run_test_ok_count(
+            json!({"a": 1, "b": 2, "c": 3}),
+            Path::from_vec(vec![1]),
+            2,
+            wrap('o'),
+            EditSuccess::Wrap {
+                class: Class::Object,
+                count: 2,
+            },
+            json!({"a": 1, "": {"b": 2, "c": 3}}),
+            Path::from_vec(vec![1]),
+        );
+        // The root can be wrapped
+        run_test_ok(
+            json!([true]),
+            Path::root(),
+            wrap('a'),
+            EditSuccess::Wrap {
+                class: Class::Array,
+                count: 1,
+            },
+            json!([[true]]),
+            Path::root(),
+        );
+        // Fields can't go in arrays, and keys must be strings
+        run_test_err(
+            json!({"a": 1}),
+            Path::from_vec(vec![0]),
+            wrap('a'),
+            EditErr::InsertError(InsertError::InvalidChild {
+                name: "array".to_string(),
+                child_name: "field".to_string(),
+            }),
+        );
+        run_test_err(
+            json!({"a": 1}),
+            Path::from_vec(vec![0, 0]),
+            wrap('a'),
+            EditErr::CannotBeChild {
+                class: Class::Array,
+                parent_name: "field".to_string(),
+            },
+        );
+        run_test_err(
+            json!([true]),
+            Path::from_vec(vec![0]),
+            wrap('t'),
+            EditErr::CannotBeChild {
+                class: Class::True,
+                parent_name: "true".to_string(),
+            },
+        );
+        run_test_err_count(
+            json!([true]),
+            Path::from_vec(vec![0]),
+            0,
+            wrap('a'),
+            EditErr::NoNodesToInsert,
+        );
+    }
+
+    /* RAISE */
+
+    #[test]
+    fn raise_cursor() {
+        // Raising an element of an array replaces the array
+        run_test_ok(
+            json!([true, [false, null], 0]),
+            Path::from_vec(vec![1, 1]),
+            Action::Raise,
+            EditSuccess::Raise {
+                class: Class::Null,
+                count: 1,
+            },
+            json!([true, null, 0]),
+            Path::from_vec(vec![1]),
+        );
+        // Raising to the root
+        run_test_ok(
+            json!([{"a": 1}]),
+            Path::from_vec(vec![0]),
+            Action::Raise,
+            EditSuccess::Raise {
+                class: Class::Object,
+                count: 1,
+            },
+            json!({"a": 1}),
+            Path::root(),
+        );
+        // Raising the value of a field replaces the object which contains the field
+        run_test_ok(
+            json!({"x": {"a": 1, "b": 2}}),
+            Path::from_vec(vec![0, 1, 1, 1]),
+            Action::Raise,
+            EditSuccess::Raise {
+                class: Class::Number,
+                count: 1,
+            },
+            json!({"x": 2}),
+            Path::from_vec(vec![0, 1]),
+        );
+        run_test_ok(
+            json!({"x": {"a": 1}}),
+            Path::from_vec(vec![0, 1, 0, 0]),
+            Action::Raise,
+            EditSuccess::Raise {
+                class: Class::Str,
+                count: 1,
+            },
+            json!({"x": "a"}),
+            Path::from_vec(vec![0, 1]),
+        );
+        run_test_ok(
+            json!({"x": [true]}),
+            Path::from_vec(vec![0, 1, 0]),
+            Action::Raise,
+            EditSuccess::Raise {
+                class: Class::True,
+                count: 1,
+            },
+            json!({"x": true}),
+            Path::from_vec(vec![0, 1]),
+        );
+        // Counted raises are a single edit, which goes up past fields and stops at the root
+        run_test_ok_count(
+            json!([[[true]]]),
+            Path::from_vec(vec![0, 0, 0]),
+            2,
+            Action::Raise,
+            EditSuccess::Raise {
+                class: Class::True,
+                count: 2,
+            },
+            json!([true]),
+            Path::from_vec(vec![0]),
+        );
+        run_test_ok_count(
+            json!({"x": [{"a": 1}]}),
+            Path::from_vec(vec![0, 1, 0, 0, 1]),
+            2,
+            Action::Raise,
+            EditSuccess::Raise {
+                class: Class::Number,
+                count: 2,
+            },
+            json!({"x": 1}),
+            Path::from_vec(vec![0, 1]),
+        );
+        run_test_ok_count(
+            json!([[[true]]]),
+            Path::from_vec(vec![0, 0, 0]),
+            5,
+            Action::Raise,
+            EditSuccess::Raise {
+                class: Class::True,
+                count: 3,
+            },
+            json!(true),
+            Path::root(),
+        );
+        let arena = Arena::new();
+        let root = add_value_to_arena(json!([[[true]]]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![0, 0, 0]));
+        dag.raise_cursor(2).unwrap();
+        dag.undo(1).unwrap();
+        assert_eq!(*dag.root(), json!([[[true]]]));
+        // The root, and nodes which can't exist on their own, can't be raised
+        run_test_err(
+            json!([true]),
+            Path::root(),
+            Action::Raise,
+            EditErr::RaisingRoot,
+        );
+        run_test_err(
+            json!([{"a": 1}]),
+            Path::from_vec(vec![0, 0]),
+            Action::Raise,
+            EditErr::CannotRaise {
+                name: "field".to_string(),
+            },
+        );
+    }
+
+    /* MOVE NODES */
+
+    #[test]
+    fn move_node() {
+        // Swapping with a neighbour
+        run_test_ok(
+            json!([true, false, null]),
+            Path::from_vec(vec![0]),
+            Action::MoveNode(Side::Next),
+            EditSuccess::MoveNode {
+                side: Side::Next,
+                count: 1,
+            },
+            json!([false, true, null]),
+            Path::from_vec(vec![1]),
+        );
+        // Moving several places stops at the end of the siblings
+        run_test_ok_count(
+            json!([true, false, null, 0]),
+            Path::from_vec(vec![2]),
+            5,
+            Action::MoveNode(Side::Prev),
+            EditSuccess::MoveNode {
+                side: Side::Prev,
+                count: 2,
+            },
+            json!([null, true, false, 0]),
+            Path::from_vec(vec![0]),
+        );
+        // Fields keep their keys when they're moved
+        run_test_ok(
+            json!({"a": 1, "b": 2}),
+            Path::from_vec(vec![1]),
+            Action::MoveNode(Side::Prev),
+            EditSuccess::MoveNode {
+                side: Side::Prev,
+                count: 1,
+            },
+            json!({"b": 2, "a": 1}),
+            Path::from_vec(vec![0]),
+        );
+        run_test_err(
+            json!([true, false]),
+            Path::from_vec(vec![1]),
+            Action::MoveNode(Side::Next),
+            EditErr::NoSibling(Side::Next),
+        );
+        run_test_err(
+            json!([true]),
+            Path::root(),
+            Action::MoveNode(Side::Prev),
+            EditErr::MovingRoot,
+        );
+    }
+
+    #[test]
+    fn slurp_cursor() {
+        run_test_ok(
+            json!([[true, false], null, 0]),
+            Path::from_vec(vec![1]),
+            Action::Slurp(Side::Prev),
+            EditSuccess::Slurp(Side::Prev),
+            json!([[true, false, null], 0]),
+            Path::from_vec(vec![0, 2]),
+        );
+        run_test_ok(
+            json!([0, null, [true, false]]),
+            Path::from_vec(vec![1]),
+            Action::Slurp(Side::Next),
+            EditSuccess::Slurp(Side::Next),
+            json!([0, [null, true, false]]),
+            Path::from_vec(vec![1, 0]),
+        );
+        // Moving a value into an object gives it an empty key
+        run_test_ok(
+            json!([{"a": 1}, true]),
+            Path::from_vec(vec![1]),
+            Action::Slurp(Side::Prev),
+            EditSuccess::Slurp(Side::Prev),
+            json!([{"a": 1, "": true}]),
+            Path::from_vec(vec![0, 1]),
+        );
+        // Moving a field into another field moves it into that field's value
+        run_test_ok(
+            json!({"a": 1, "b": {"c": 2}}),
+            Path::from_vec(vec![0]),
+            Action::Slurp(Side::Next),
+            EditSuccess::Slurp(Side::Next),
+            json!({"b": {"a": 1, "c": 2}}),
+            Path::from_vec(vec![0, 1, 0]),
+        );
+        run_test_err(
+            json!([true, [false]]),
+            Path::from_vec(vec![1]),
+            Action::Slurp(Side::Next),
+            EditErr::NoSibling(Side::Next),
+        );
+        run_test_err(
+            json!([true, false]),
+            Path::from_vec(vec![1]),
+            Action::Slurp(Side::Prev),
+            EditErr::CannotBeChild {
+                class: Class::False,
+                parent_name: "true".to_string(),
+            },
+        );
+    }
+
+    #[test]
+    fn barf_cursor() {
+        run_test_ok(
+            json!([0, [true, false, null], 1]),
+            Path::from_vec(vec![1, 1]),
+            Action::Barf(Side::Next),
+            EditSuccess::Barf(Side::Next),
+            json!([0, [true, null], false, 1]),
+            Path::from_vec(vec![2]),
+        );
+        run_test_ok(
+            json!([0, [true, false, null], 1]),
+            Path::from_vec(vec![1, 2]),
+            Action::Barf(Side::Prev),
+            EditSuccess::Barf(Side::Prev),
+            json!([0, null, [true, false], 1]),
+            Path::from_vec(vec![1]),
+        );
+        // Fields can be moved between objects
+        run_test_ok(
+            json!({"x": {"a": 1, "b": 2}}),
+            Path::from_vec(vec![0, 1, 0]),
+            Action::Barf(Side::Next),
+            EditSuccess::Barf(Side::Next),
+            json!({"x": {"b": 2}, "a": 1}),
+            Path::from_vec(vec![1]),
+        );
+        run_test_ok(
+            json!([{"x": [true, false]}]),
+            Path::from_vec(vec![0, 0, 1, 0]),
+            Action::Barf(Side::Prev),
+            EditSuccess::Barf(Side::Prev),
+            json!([{"": true, "x": [false]}]),
+            Path::from_vec(vec![0, 0]),
+        );
+        run_test_err(
+            json!([true]),
+            Path::from_vec(vec![0]),
+            Action::Barf(Side::Next),
+            EditErr::MovingOutOfRoot,
+        );
+    }
+
+    /* MULTIPLE CURSORS */
+
+    #[test]
+    fn edit_every_cursor() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(json!([true, [false, true], null, true]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![0]));
+        let mut other_cursors = vec![
+            Path::from_vec(vec![3]),
+            Path::from_vec(vec![1, 1]),
+            Path::from_vec(vec![3]),
+        ];
+        // Inserting after every cursor shifts the cursors after each insertion
+        assert_eq!(
+            dag.edit_every_cursor(&mut other_cursors, |dag| {
+                dag.insert_next_to_cursor(1, Insertable::CountedNode(2, 'n'), Side::Next)
+            }),
+            Ok(EditSuccess::InsertNextToCursor {
+                side: Side::Next,
+                class: Class::Null
+            })
+        );
+        assert_eq!(
+            *dag.root(),
+            json!([
+                true,
+                null,
+                null,
+                [false, true, null, null],
+                null,
+                true,
+                null,
+                null
+            ])
+        );
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![2]));
+        assert_eq!(
+            other_cursors,
+            vec![Path::from_vec(vec![3, 3]), Path::from_vec(vec![7])]
+        );
+        // All the edits are one snapshot
+        assert_eq!(dag.history().len(), 2);
+        assert_eq!(
+            dag.history()[1].description,
+            "insert null after at 3 cursors"
+        );
+        assert_eq!(dag.undo(1), Ok(EditSuccess::Undo));
+        assert_eq!(*dag.root(), json!([true, [false, true], null, true]));
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![0]));
+
+        // Deleting nodes removes them from before the other cursors
+        let mut other_cursors = vec![Path::from_vec(vec![1, 0]), Path::from_vec(vec![3])];
+        dag.edit_every_cursor(&mut other_cursors, |dag| dag.delete_cursor(1))
+            .unwrap();
+        assert_eq!(*dag.root(), json!([[true], null]));
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![0]));
+        assert_eq!(
+            other_cursors,
+            vec![Path::from_vec(vec![0, 0]), Path::from_vec(vec![1])]
+        );
+
+        // If the edit fails at any cursor, nothing is changed
+        let mut other_cursors = vec![Path::from_vec(vec![1])];
+        assert_eq!(
+            dag.edit_every_cursor(&mut other_cursors, |dag| {
+                dag.insert_child(1, Insertable::CountedNode(1, 't'))
+            }),
+            Err(EditErr::CannotBeChild {
+                class: Class::True,
+                parent_name: "null".to_string(),
+            })
+        );
+        assert_eq!(*dag.root(), json!([[true], null]));
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![0]));
+        assert_eq!(other_cursors, vec![Path::from_vec(vec![1])]);
+        assert_eq!(dag.history().len(), 3);
+        assert_eq!(dag.history_index(), 2);
+
+        // ... even if it succeeded at the cursors which were edited before it
+        let root = add_value_to_arena(json!([true, []]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![0]));
+        let mut other_cursors = vec![Path::from_vec(vec![1])];
+        assert!(dag
+            .edit_every_cursor(&mut other_cursors, |dag| {
+                dag.insert_child(1, Insertable::CountedNode(1, 't'))
+            })
+            .is_err());
+        assert_eq!(*dag.root(), json!([true, []]));
+        assert_eq!(dag.current_cursor_path, Path::from_vec(vec![0]));
+        assert_eq!(other_cursors, vec![Path::from_vec(vec![1])]);
+        assert_eq!(
+            dag.node_and_next_siblings(&other_cursors[0], 1),
+            vec![root.children()[1]]
+        );
+        assert_eq!(dag.history().len(), 1);
+    }
+
+    /* SET TEXT */
+
+    #[test]
+    fn set_cursor_text() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(json!({"": [1, true]}), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![0, 0]));
+        // Set the key of the field
+        assert_eq!(
+            dag.set_cursor_text("key".to_string()),
+            Ok(EditSuccess::SetText {
+                name: r#""key""#.to_string()
+            })
+        );
+        assert_eq!(*dag.root(), json!({"key": [1, true]}));
+        // Set the value of a number
+        dag.current_cursor_path = Path::from_vec(vec![0, 1, 0]);
+        assert_eq!(
+            dag.set_cursor_text("-2.5e3".to_string()),
+            Ok(EditSuccess::SetText {
+                name: "-2.5e3".to_string()
+            })
+        );
+        assert_eq!(dag.cursor(), &Json::Number("-2.5e3".to_string()));
+        // Invalid numbers and nodes without text are errors, and don't create snapshots
+        assert_eq!(
+            dag.set_cursor_text("1.2.3".to_string()),
+            Err(EditErr::TextError(TextError::InvalidText {
+                name: "-2.5e3".to_string(),
+                text: "1.2.3".to_string(),
+            }))
+        );
+        dag.current_cursor_path = Path::from_vec(vec![0, 1, 1]);
+        assert_eq!(
+            dag.set_cursor_text("false".to_string()),
+            Err(EditErr::TextError(TextError::NoText {
+                name: "true".to_string()
+            }))
+        );
+        // Each successful text edit should be exactly one undo step
+        assert_eq!(dag.undo(1), Ok(EditSuccess::Undo));
+        assert_eq!(*dag.root(), json!({"key": [1, true]}));
+        assert_eq!(dag.undo(1), Ok(EditSuccess::Undo));
+        assert_eq!(*dag.root(), json!({"": [1, true]}));
+        assert_eq!(dag.undo(1), Err(EditErr::NoChangesToUndo));
+    }
+
+    /* INCREMENT */
+
+    #[test]
+    fn modified_tracking() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(json!([true, false]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![0]));
+        // A freshly loaded tree is unmodified, and moving the cursor doesn't change that
+        assert!(!dag.is_modified());
+        dag.execute_action_once(Action::MoveCursor(Direction::Next))
+            .unwrap();
+        assert!(!dag.is_modified());
+        // Edits modify the tree, but undoing them returns to the saved tree
+        dag.execute_action_once(Action::Delete('"')).unwrap();
+        assert!(dag.is_modified());
+        dag.execute_action_once(Action::Undo).unwrap();
+        assert!(!dag.is_modified());
+        dag.execute_action_once(Action::Redo).unwrap();
+        assert!(dag.is_modified());
+        // Saving makes the current tree the clean one
+        dag.mark_saved();
+        assert!(!dag.is_modified());
+        dag.execute_action_once(Action::Undo).unwrap();
+        assert!(dag.is_modified());
+        // Making a new edit removes the saved tree from the history, so the tree can never be
+        // clean again without another save
+        dag.execute_action_once(Action::Delete('"')).unwrap();
+        assert!(dag.is_modified());
+        dag.execute_action_once(Action::Undo).unwrap();
+        assert!(dag.is_modified());
+    }
+
+    #[test]
+    fn increment() {
+        run_test_ok_count(
+            json!([1, 2]),
+            Path::from_vec(vec![1]),
+            5,
+            Action::Increment,
+            EditSuccess::Increment(5),
+            json!([1, 7]),
+            Path::from_vec(vec![1]),
+        );
+        run_test_ok_count(
+            json!({"value": 3}),
+            Path::from_vec(vec![0, 1]),
+            4,
+            Action::Decrement,
+            EditSuccess::Increment(-4),
+            json!({"value": -1}),
+            Path::from_vec(vec![0, 1]),
+        );
+        // Inserted numbers start at 0
+        run_test_ok(
+            json!([]),
+            Path::root(),
+            Action::InsertChild(Insertable::CountedNode(1, 'N')),
+            EditSuccess::InsertChild(Class::Number),
+            json!([0]),
+            Path::from_vec(vec![0]),
+        );
+        run_test_err(
+            json!([true]),
+            Path::from_vec(vec![0]),
+            Action::Increment,
+            EditErr::CannotIncrement {
+                name: "true".to_string(),
+            },
+        );
+    }
+
     #[test]
     // This is the test cases for issue 27
     fn level_2_undo() {
@@ -0,0 +1,170 @@
+//! The code for 'insert-mode', which edits the text contents of the node under the cursor
+
+use super::dag::LogMessage;
+use super::{keystroke_log::Category, normal_mode, state, Editor};
+use crate::ast::Ast;
+
+use std::borrow::Cow;
+
+use tuikit::prelude::Key;
+
+/// The [`State`](state::State) which Sapling is in whilst the user is typing the text contents of
+/// a node (e.g. the contents of a JSON string).  The text is only written to the tree when the
+/// user leaves insert mode, so an entire insert-mode session is a single undo step.
+#[derive(Debug, Clone)]
+pub struct State {
+    /// The text that was in the node when insert mode was entered
+    original_text: String,
+    /// The text as it has been typed so far
+    text: String,
+    /// The index (in [`char`]s) into `text` of the caret
+    caret: usize,
+}
+
+impl State {
+    /// Creates a new insert mode `State` which is editing a given text, with the caret placed at
+    /// the end of the text.
+    pub fn new(text: &str) -> Self {
+        State {
+            original_text: text.to_owned(),
+            text: text.to_owned(),
+            caret: text.chars().count(),
+        }
+    }
+
+    /// Converts the caret's [`char`] index into a byte index into `self.text`
+    fn caret_byte_index(&self) -> usize {
+        self.text
+            .char_indices()
+            .nth(self.caret)
+            .map_or(self.text.len(), |(i, _)| i)
+    }
+
+    /// Updates the text buffer in response to a keystroke, returning `false` if the [`Key`]
+    /// doesn't correspond to any text edit.
+    fn edit_text(&mut self, key: Key) -> bool {
+        let char_count = self.text.chars().count();
+        match key {
+            Key::Char(c) => {
+                let index = self.caret_byte_index();
+                self.text.insert(index, c);
+                self.caret += 1;
+            }
+            Key::Backspace => {
+                if self.caret > 0 {
+                    self.caret -= 1;
+                    let index = self.caret_byte_index();
+                    self.text.remove(index);
+                }
+            }
+            Key::Delete => {
+                if self.caret < char_count {
+                    let index = self.caret_byte_index();
+                    self.text.remove(index);
+                }
+            }
+            Key::Left => self.caret = self.caret.saturating_sub(1),
+            Key::Right => self.caret = (self.caret + 1).min(char_count),
+            Key::Home => self.caret = 0,
+            Key::End => self.caret = char_count,
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> state::State<'arena, Node> for State {
+    fn transition(
+        mut self: Box<Self>,
+        key: Key,
+        editor: &mut Editor<'arena, Node>,
+    ) -> (
+        Box<dyn state::State<'arena, Node>>,
+        Option<(String, Category)>,
+    ) {
+        match key {
+            // Leaving insert mode commits the text to the tree
+            Key::ESC | Key::Enter => {
+                // If the text hasn't changed, then we don't need to make a new snapshot
+                if self.text == self.original_text {
+                    return (
+                        Box::new(normal_mode::State::default()),
+                        Some(("leave insert mode".to_owned(), Category::Insert)),
+                    );
+                }
+                let result = editor.buffer_mut().tree.set_cursor_text(self.text.clone());
+                let is_ok = result.is_ok();
+                result.log_message();
+                if is_ok {
+                    (
+                        Box::new(normal_mode::State::default()),
+                        Some((format!("set text to {:?}", self.text), Category::Replace)),
+                    )
+                } else {
+                    // If the text couldn't be written, then we stay in insert mode so that the
+                    // user can fix it
+                    let log_entry = (
+                        format!("can't set text to {:?}", self.text),
+                        Category::Undefined,
+                    );
+                    (self, Some(log_entry))
+                }
+            }
+            _ => {
+                if self.edit_text(key) {
+                    (self, None)
+                } else {
+                    (
+                        self,
+                        Some(("not a text edit".to_owned(), Category::Undefined)),
+                    )
+                }
+            }
+        }
+    }
+
+    fn keystroke_buffer(&self) -> Cow<'_, str> {
+        Cow::from("-- INSERT --")
+    }
+
+    fn text_edit(&self) -> Option<(&str, usize)> {
+        Some((&self.text, self.caret))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::State;
+    use tuikit::prelude::Key;
+
+    #[test]
+    fn edit_text() {
+        for (start_text, keys, expected_text, expected_caret) in &[
+            ("", vec![Key::Char('a'), Key::Char('b')], "ab", 2),
+            ("foo", vec![Key::Backspace], "fo", 2),
+            ("foo", vec![Key::Home, Key::Delete], "oo", 0),
+            ("foo", vec![Key::Home, Key::Backspace], "foo", 0),
+            ("foo", vec![Key::Delete, Key::Right], "foo", 3),
+            ("foo", vec![Key::Left, Key::Char('x')], "foxo", 3),
+            (
+                "café",
+                vec![Key::Left, Key::Backspace, Key::Char('e')],
+                "caeé",
+                3,
+            ),
+            ("", vec![Key::Left, Key::End, Key::Char('π')], "π", 1),
+        ] {
+            let mut state = State::new(start_text);
+            for key in keys {
+                assert!(state.edit_text(*key));
+            }
+            assert_eq!(state.text, *expected_text);
+            assert_eq!(state.caret, *expected_caret);
+        }
+        // Keys which aren't edits shouldn't change anything
+        let mut state = State::new("foo");
+        assert!(!state.edit_text(Key::Tab));
+        assert_eq!(state.text, "foo");
+        assert_eq!(state.caret, 3);
+    }
+}
@@ -13,7 +13,7 @@ use tuikit::prelude::*;
 pub enum Category {
     /// An [`Action`] that moves the cursor
     Move,
-    /// Either [`Action::Undo`] or [`Action::Redo`]
+    /// An [`Action`] that moves through the undo history
     History,
     /// An [`Action`] that inserts extra nodes into the tree
     Insert,
@@ -21,6 +21,8 @@ pub enum Category {
     Replace,
     /// An [`Action`] that causes nodes to be deleted from the tree
     Delete,
+    /// An [`Action`] that copies nodes into a register
+    Yank,
     /// The action of the keystrokes is that Sapling should quit
     Quit,
     /// An [`Action`] that handles reading and writing from disk
@@ -41,6 +43,7 @@ impl Category {
             Category::Insert => Color::LIGHT_GREEN,
             Category::Replace => Color::CYAN,
             Category::Delete => Color::RED,
+            Category::Yank => Color::LIGHT_MAGENTA,
             Category::Quit => Color::MAGENTA,
             Category::IO => Color::GREEN,
             Category::Undefined => Color::LIGHT_RED,
@@ -0,0 +1,335 @@
+//! A cache of where the pieces of each node are placed on the screen, so that rendering only has
+//! to visit the parts of the tree which are visible.
+
+use crate::ast::display_token::{DisplayToken, RecTok, SyntaxCategory, INDENT_WIDTH};
+use crate::ast::Ast;
+use crate::core::{Path, Size};
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A single visible piece of a node: either some text owned by that node, or one of its children
+#[derive(Debug, Clone)]
+pub enum Item<'arena, Node> {
+    /// Some text, which should be highlighted according to its [`SyntaxCategory`]
+    Text(Cow<'static, str>, SyntaxCategory),
+    /// A child node, which has its own [`Layout`]
+    Child(&'arena Node),
+}
+
+/// An [`Item`], along with where it is placed relative to the start of the node that owns it.
+#[derive(Debug, Clone)]
+pub struct PlacedItem<'arena, Node> {
+    /// The text or child node being placed
+    pub item: Item<'arena, Node>,
+    /// How many rows below the node's first row this `Item` starts
+    pub row: usize,
+    /// The column where this `Item` starts.  On the node's first row, this is relative to the
+    /// start of the node; on every other row it is relative to the node's indentation.
+    pub col: usize,
+    /// The indentation (relative to the node's indentation) at the start of this `Item`
+    pub indentation: usize,
+    /// The space occupied by this `Item`
+    pub size: Size,
+}
+
+/// The [`PlacedItem`]s which make up a node, along with the [`Size`] of the whole node.  The items
+/// are in the order that they are rendered, so their `row`s are sorted.
+#[derive(Debug, Clone)]
+pub struct Layout<'arena, Node> {
+    /// The items of this node, in rendering order
+    pub items: Vec<PlacedItem<'arena, Node>>,
+    /// The space occupied by this node
+    pub size: Size,
+}
+
+/// A cache of the [`Size`]s and [`Layout`]s of nodes, keyed by the nodes' addresses.  Nodes are
+/// immutable and are never deallocated whilst their arena exists, so a node's address uniquely
+/// determines its `Layout` (for a given format style).  Because the `Dag` shares unchanged
+/// subtrees between edits, each edit only invalidates the layouts of the edited node's ancestors.
+///
+/// `Layout`s are only kept for the nodes rendered in the last frame, whereas sizes are kept for
+/// longer because laying out a node needs the sizes of all of its children, including the ones
+/// which aren't on the screen (see [`next_frame`](LayoutCache::next_frame)).
+#[derive(Debug)]
+pub struct LayoutCache<'arena, Node> {
+    /// The sizes which have been used or measured since sizes were last evicted
+    sizes: RefCell<HashMap<*const Node, Size>>,
+    /// The sizes which were kept when sizes were last evicted, which are moved back into `sizes`
+    /// if they get used again
+    old_sizes: RefCell<HashMap<*const Node, Size>>,
+    layouts: RefCell<HashMap<*const Node, Rc<Layout<'arena, Node>>>>,
+    /// The layouts used in the previous frame, which are moved back into `layouts` if they get
+    /// used again
+    last_frame_layouts: RefCell<HashMap<*const Node, Rc<Layout<'arena, Node>>>>,
+}
+
+impl<'arena, Node: Ast<'arena>> LayoutCache<'arena, Node> {
+    /// Creates an empty `LayoutCache`
+    pub fn new() -> Self {
+        LayoutCache {
+            sizes: RefCell::new(HashMap::new()),
+            old_sizes: RefCell::new(HashMap::new()),
+            layouts: RefCell::new(HashMap::new()),
+            last_frame_layouts: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Empties the cache.  This must be called whenever the format style changes.
+    pub fn clear(&self) {
+        self.sizes.borrow_mut().clear();
+        self.old_sizes.borrow_mut().clear();
+        self.layouts.borrow_mut().clear();
+        self.last_frame_layouts.borrow_mut().clear();
+    }
+
+    /// Starts a new frame of rendering.  Any [`Layout`]s which weren't used since the last call
+    /// to `next_frame` are removed from the cache.  Sizes are evicted once at least as many have
+    /// been used since their last eviction as are left over from it, at which point the sizes
+    /// which haven't been used since then are removed.  This keeps the cache to a small multiple
+    /// of the number of sizes in use, without measuring nodes again after every frame.
+    pub fn next_frame(&self) {
+        let used_layouts = std::mem::take(&mut *self.layouts.borrow_mut());
+        *self.last_frame_layouts.borrow_mut() = used_layouts;
+        let mut sizes = self.sizes.borrow_mut();
+        if sizes.len() >= self.old_sizes.borrow().len() {
+            *self.old_sizes.borrow_mut() = std::mem::take(&mut *sizes);
+        }
+    }
+
+    /// Returns the [`Size`] of a node.  This gives the same result as [`Ast::size`], but reuses
+    /// the sizes of any nodes that have been measured before.
+    pub fn size(&self, node: &'arena Node, format_style: &Node::FormatStyle) -> Size {
+        let key = node as *const Node;
+        if let Some(size) = self.sizes.borrow().get(&key) {
+            return *size;
+        }
+        if let Some(size) = self.old_sizes.borrow_mut().remove(&key) {
+            self.sizes.borrow_mut().insert(key, size);
+            return size;
+        }
+        self.compute_layout(node, format_style).size
+    }
+
+    /// Returns the [`Layout`] of a node
+    pub fn layout(
+        &self,
+        node: &'arena Node,
+        format_style: &Node::FormatStyle,
+    ) -> Rc<Layout<'arena, Node>> {
+        let key = node as *const Node;
+        if let Some(layout) = self.layouts.borrow().get(&key) {
+            return layout.clone();
+        }
+        let layout = match self.last_frame_layouts.borrow_mut().remove(&key) {
+            Some(layout) => layout,
+            None => Rc::new(self.compute_layout(node, format_style)),
+        };
+        self.layouts.borrow_mut().insert(key, layout.clone());
+        layout
+    }
+
+    /// Returns the `(row, column)` in the rendered tree where the node at a given [`Path`]
+    /// starts.  This only has to look at the [`Layout`]s of the node's ancestors.
+    pub fn node_position(
+        &self,
+        root: &'arena Node,
+        path: &Path,
+        format_style: &Node::FormatStyle,
+    ) -> (usize, usize) {
+        let (mut row, mut col, mut indentation) = (0, 0, 0);
+        let mut node = root;
+        for &child_index in path.iter() {
+            let layout = self.layout(node, format_style);
+            let placed_child = layout
+                .items
+                .iter()
+                .filter(|placed| matches!(placed.item, Item::Child(_)))
+                .nth(child_index)
+                .expect("Cursor path should point to a node in the tree");
+            col = if placed_child.row == 0 {
+                col + placed_child.col
+            } else {
+                indentation + placed_child.col
+            };
+            row += placed_child.row;
+            indentation += placed_child.indentation;
+            if let Item::Child(child) = placed_child.item {
+                node = child;
+            }
+        }
+        (row, col)
+    }
+
+    /// Lays out a node from its [`RecTok`]s, and records its [`Size`]
+    fn compute_layout(
+        &self,
+        node: &'arena Node,
+        format_style: &Node::FormatStyle,
+    ) -> Layout<'arena, Node> {
+        let mut items = Vec::new();
+        let (mut row, mut col, mut indentation) = (0, 0, 0);
+        for tok in node.display_tokens_rec(format_style) {
+            let (item, size, base_col) = match tok {
+                RecTok::Child(child) => (
+                    Item::Child(child),
+                    self.size(child, format_style),
+                    indentation,
+                ),
+                // Text is printed verbatim, so any lines after the first start at column 0
+                RecTok::Tok(DisplayToken::Text(text, category)) => {
+                    let size = Size::from(&*text);
+                    (Item::Text(text, category), size, 0)
+                }
+                RecTok::Tok(DisplayToken::Whitespace(n)) => {
+                    col += n;
+                    continue;
+                }
+                RecTok::Tok(DisplayToken::Newline) => {
+                    row += 1;
+                    col = indentation;
+                    continue;
+                }
+                RecTok::Tok(DisplayToken::Indent) => {
+                    indentation += INDENT_WIDTH;
+                    continue;
+                }
+                RecTok::Tok(DisplayToken::Dedent) => {
+                    indentation -= INDENT_WIDTH;
+                    continue;
+                }
+            };
+            items.push(PlacedItem {
+                item,
+                row,
+                col,
+                indentation,
+                size,
+            });
+            // Move to the end of the item
+            if size.lines() == 0 {
+                col += size.last_line_length();
+            } else {
+                row += size.lines();
+                col = base_col + size.last_line_length();
+            }
+        }
+        let size = Size::new(row, col);
+        self.sizes.borrow_mut().insert(node as *const Node, size);
+        Layout { items, size }
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> Default for LayoutCache<'arena, Node> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LayoutCache;
+    use crate::arena::Arena;
+    use crate::ast::json::{add_value_to_arena, Json, JsonFormat};
+    use crate::ast::Ast;
+    use crate::core::Path;
+
+    fn test_paths() -> Vec<Path> {
+        vec![
+            vec![],
+            vec![0],
+            vec![1],
+            vec![1, 0],
+            vec![1, 0, 0],
+            vec![1, 0, 1],
+            vec![1, 0, 1, 1],
+            vec![1, 1, 1],
+            vec![2, 0, 0],
+            vec![3],
+        ]
+        .into_iter()
+        .map(Path::from_vec)
+        .collect()
+    }
+
+    #[test]
+    fn sizes() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(
+            serde_json::json!([true, {"a": [1, "two"], "b": {}}, [[null]], 3.5]),
+            &arena,
+        );
+        for format in &[JsonFormat::Pretty, JsonFormat::Compact] {
+            let cache = LayoutCache::new();
+            for path in test_paths() {
+                let node = path.cursor(root);
+                assert_eq!(cache.size(node, format), node.size(format));
+            }
+        }
+    }
+
+    #[test]
+    fn node_positions() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(
+            serde_json::json!([true, {"a": [1, "two"], "b": {}}, [[null]], 3.5]),
+            &arena,
+        );
+        for format in &[JsonFormat::Pretty, JsonFormat::Compact] {
+            let cache = LayoutCache::new();
+            let text = root.to_text(format);
+            let lines: Vec<&str> = text.lines().collect();
+            for path in test_paths() {
+                let (row, col) = cache.node_position(root, &path, format);
+                // The text at the computed position should be the start of the node's text
+                let node_text = path.cursor(root).to_text(format);
+                let first_line = node_text.lines().next().unwrap();
+                println!("Testing {:?}", path);
+                assert_eq!(&lines[row][col..col + first_line.len()], first_line);
+            }
+        }
+    }
+
+    #[test]
+    fn frames() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(serde_json::json!([[true], [false]]), &arena);
+        let format = JsonFormat::Pretty;
+        let cache = LayoutCache::new();
+        let first = root.children()[0];
+        let second = root.children()[1];
+        cache.layout(first, &format);
+        cache.layout(second, &format);
+        // Layouts used in consecutive frames are kept ...
+        cache.next_frame();
+        let first_layout = cache.layout(first, &format);
+        cache.next_frame();
+        assert!(std::rc::Rc::ptr_eq(
+            &first_layout,
+            &cache.layout(first, &format)
+        ));
+        // ... but unused layouts are dropped after a frame
+        assert!(cache
+            .layouts
+            .borrow()
+            .get(&(second as *const Json))
+            .is_none());
+        assert!(cache
+            .last_frame_layouts
+            .borrow()
+            .get(&(second as *const Json))
+            .is_none());
+        // Sizes are kept for longer, but are evicted once enough other nodes have been measured
+        let key = second as *const Json;
+        assert!(cache.old_sizes.borrow().contains_key(&key));
+        let other_root = add_value_to_arena(serde_json::json!([[1], [2], [3]]), &arena);
+        cache.size(other_root, &format);
+        cache.next_frame();
+        assert!(!cache.sizes.borrow().contains_key(&key));
+        assert!(!cache.old_sizes.borrow().contains_key(&key));
+        // Evicted sizes are measured again when they're needed
+        assert_eq!(cache.size(second, &format), second.size(&format));
+    }
+}
@@ -1,25 +1,47 @@
 //! The top-level functionality of Sapling
 
+pub mod buffer;
+pub mod command_mode;
 pub mod dag;
+pub mod insert_mode;
 pub mod keystroke_log;
+pub mod layout;
+pub mod multi_cursor;
 pub mod normal_mode;
+pub mod registers;
+pub mod search;
 pub mod state;
+pub mod undo_file;
+pub mod undo_tree;
+pub mod viewport;
+pub mod visual_mode;
+pub mod window;
 
-use crate::ast::display_token::{DisplayToken, SyntaxCategory};
+use crate::arena::Arena;
+use crate::ast::display_token::SyntaxCategory;
 use crate::ast::Ast;
 use crate::config::{Config, DEBUG_HIGHLIGHTING};
-use crate::core::Size;
+use crate::core::Path;
 
-use dag::Dag;
+use buffer::{Buffer, DetachedBuffer};
+use dag::{Dag, DetachedNodes, Detacher};
 use keystroke_log::KeyStrokeLog;
+use registers::Registers;
+use search::{Query, Search};
 use state::State;
+use window::{DetachedWindow, Rect, ScreenDirection, SplitDirection, Window, WindowLayout};
 
 use std::borrow::{Borrow, Cow};
+use std::cell::Cell;
 use std::collections::{hash_map::DefaultHasher, HashSet};
 use std::hash::Hasher;
+use std::io::Write;
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::SystemTime;
 
-use tuikit::prelude::*;
+use tuikit::prelude::{Attr, Color, Effect, Event, Key, Term};
 
 /// The [`State`] that Sapling is in during a transition function.  This has to exist, but
 /// none of the methods should ever be called, since doing so would require the transition function
@@ -51,12 +73,177 @@ impl<'arena, Node: Ast<'arena>> State<'arena, Node> for IntermediateState {
     }
 }
 
+/// The possible ways that reading or writing a file could fail
+#[derive(Debug)]
+pub enum FileError {
+    /// Trying to write the tree without having a file path to write it to
+    NoFilePath,
+    /// The operating system returned an error whilst reading or writing a file
+    Io(PathBuf, std::io::Error),
+    /// The contents of a file couldn't be parsed
+    Parse(PathBuf, String),
+}
+
+impl std::fmt::Display for FileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileError::NoFilePath => write!(f, "No file name"),
+            FileError::Io(path, e) => write!(f, "Error accessing {:?}: {}", path, e),
+            FileError::Parse(path, e) => write!(f, "Error parsing {:?}: {}", path, e),
+        }
+    }
+}
+
+impl std::error::Error for FileError {}
+
+/// Reads and parses the file at `path`, adding its tree to `arena` and returning the root.  If no
+/// file exists at `path`, then it is treated as a new file (like `vim <new-file>`) and the root is
+/// the [`Default`] node of the [`Ast`].  The file will then be created when the tree is written.
+pub fn load_file<'arena, Node: Ast<'arena>>(
+    path: &std::path::Path,
+    arena: &'arena Arena<Node>,
+) -> Result<&'arena Node, FileError> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            log::info!("{:?} doesn't exist, so starting a new file", path);
+            return Ok(arena.alloc(Node::default()));
+        }
+        Err(e) => return Err(FileError::Io(path.to_owned(), e)),
+    };
+    Node::parse_to_arena(file, arena).map_err(|e| FileError::Parse(path.to_owned(), e.to_string()))
+}
+
+/// Loads the file at `path` (see [`load_file`]) into a new [`Dag`].  If `use_undo_file` is `true`
+/// and the file has an [undo file](undo_file) which was written for its current contents, then the
+/// undo history is restored from that undo file.  Problems with the undo file aren't errors, since
+/// the file itself can still be edited.
+pub fn load_dag<'arena, Node: Ast<'arena>>(
+    path: &std::path::Path,
+    arena: &'arena Arena<Node>,
+    use_undo_file: bool,
+) -> Result<Dag<'arena, Node>, FileError> {
+    let root = load_file(path, arena)?;
+    if use_undo_file {
+        match undo_file::read(path, arena, root) {
+            Ok(Some(dag)) => {
+                log::info!("Restored the undo history of {:?}", path);
+                return Ok(dag);
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("{}", e),
+        }
+    }
+    Ok(Dag::new(arena, root, Path::root()))
+}
+
+/// Writes `content` to the file at `path` without ever leaving the file half-written.  The content
+/// is first written to a temporary file in the same directory, which is then renamed over `path`.
+/// Renaming is atomic, so if Sapling (or the computer) crashes then `path` contains either the old
+/// or the new content.  If `path` already exists, its permissions are copied to the new file, and
+/// if it's a symbolic link then the file that it points to is replaced instead of the link.
+fn write_atomically(path: &std::path::Path, content: &str) -> std::io::Result<()> {
+    // Renaming over a symbolic link would replace the link itself, so we find the real file first
+    let path = &std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let (temp_path, file) = create_temp_file(path)?;
+    // The file is closed before it is renamed
+    let write_temp_file = |mut file: std::fs::File| -> std::io::Result<()> {
+        file.write_all(content.as_bytes())?;
+        // Make sure that the content has reached the disk before the old file is replaced
+        file.sync_all()?;
+        if let Ok(metadata) = std::fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        Ok(())
+    };
+    let result = write_temp_file(file).and_then(|()| std::fs::rename(&temp_path, path));
+    // Don't leave the temporary file lying around if something went wrong
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+        return result;
+    }
+    // The rename is only on the disk once the directory containing the file has been synced
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => std::path::Path::new("."),
+    };
+    std::fs::File::open(dir)?.sync_all()
+}
+
+/// Creates a new temporary file next to the file at `path`, returning its path and the open file.
+/// The temporary file's name contains Sapling's process ID, and a file is never opened if it
+/// already exists, so that several Saplings writing the same file can't write into each other's
+/// temporary files.
+fn create_temp_file(path: &std::path::Path) -> std::io::Result<(PathBuf, std::fs::File)> {
+    /// How many names to try before giving up, in case old temporary files were left behind
+    const MAX_ATTEMPTS: usize = 100;
+
+    let file_name = path.file_name().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "path has no file name")
+    })?;
+    let mut attempt = 0;
+    loop {
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(file_name);
+        temp_name.push(format!(".{}-{}.sapling-tmp", std::process::id(), attempt));
+        let temp_path = path.with_file_name(temp_name);
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && attempt < MAX_ATTEMPTS => {
+                attempt += 1
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The colours used to highlight nodes when [`DEBUG_HIGHLIGHTING`] is enabled
+const DEBUG_COLORS: [Color; 14] = [
+    Color::MAGENTA,
+    Color::RED,
+    Color::YELLOW,
+    Color::GREEN,
+    Color::CYAN,
+    Color::BLUE,
+    Color::WHITE,
+    Color::LIGHT_RED,
+    Color::LIGHT_BLUE,
+    Color::LIGHT_CYAN,
+    Color::LIGHT_GREEN,
+    Color::LIGHT_YELLOW,
+    Color::LIGHT_MAGENTA,
+    Color::LIGHT_WHITE,
+];
+
+/// The maximum width of the undo tree panel, which never takes up more than half of the screen
+const UNDO_TREE_WIDTH: usize = 50;
+
+/// The arena is never compacted whilst it holds fewer nodes than this, since compacting small
+/// arenas frees very little memory
+const MIN_COMPACTION_THRESHOLD: usize = 100_000;
+
+/// Returns how many nodes an arena can hold before it is compacted, given how many nodes were
+/// still in use the last time it was compacted.  Doubling the threshold means that the time spent
+/// compacting is proportional to the number of nodes allocated.
+fn compaction_threshold(live_nodes: usize) -> usize {
+    (live_nodes * 2).max(MIN_COMPACTION_THRESHOLD)
+}
+
 /// A singleton struct to hold the top-level components of Sapling.
 pub struct Editor<'arena, Node: Ast<'arena>> {
-    /// The `Dag` that is storing the history of the `Editor`
-    tree: &'arena mut Dag<'arena, Node>,
-    /// The style that the tree is being printed to the screen
-    format_style: Node::FormatStyle,
+    /// The [`Buffer`]s which are open, in the order that they were opened.  There is always at
+    /// least one `Buffer`.
+    buffers: Vec<Buffer<'arena, Node>>,
+    /// The [`Window`]s which are showing the `buffers`.  There is always at least one `Window`.
+    windows: Vec<Window<'arena, Node>>,
+    /// How the screen is divided between the `windows`
+    window_layout: WindowLayout,
+    /// The index of the [`Window`] that the user is in, whose [`Buffer`] is being edited
+    current_window: usize,
     /// The `tuikit` terminal that the `Editor` is rendering to
     term: Term,
     /// The current state-machine [`State`] that Sapling is in
@@ -65,129 +252,599 @@ pub struct Editor<'arena, Node: Ast<'arena>> {
     config: Config,
     /// A list of the keystrokes that have been executed, along with a summary of what they mean
     keystroke_log: KeyStrokeLog,
-    file_path: Option<PathBuf>,
+    /// The registers holding nodes which have been yanked or deleted.  These are shared between
+    /// all the [`Buffer`]s, so nodes can be copied from one file to another.
+    registers: Registers<&'arena Node>,
+    /// The number of nodes in the arena which will cause it to be compacted
+    compaction_threshold: usize,
+    /// Set to `true` (by `:compact`) to compact the arena as soon as possible
+    compaction_requested: bool,
+    /// The last [`Search`] made from the search prompt, which is repeated by `n` and `N` and whose
+    /// matches are highlighted
+    last_search: Option<Search>,
+}
+
+/// The parts of an [`Editor`] which are kept when its arena is compacted.  This doesn't borrow the
+/// arena, so the old arena can be dropped (freeing every node that's no longer needed) before the
+/// `DetachedEditor` is [attached](Editor::attach) to a new arena.
+pub struct DetachedEditor<FormatStyle> {
+    /// Every node used by the `buffers` and `registers`
+    nodes: DetachedNodes,
+    buffers: Vec<DetachedBuffer>,
+    windows: Vec<DetachedWindow<FormatStyle>>,
+    window_layout: WindowLayout,
+    current_window: usize,
+    /// The registers, where each node is an index returned by [`Detacher::detach_node`]
+    registers: Registers<usize>,
+    term: Term,
+    config: Config,
+    keystroke_log: KeyStrokeLog,
+    last_search: Option<Search>,
 }
 
 impl<'arena, Node: Ast<'arena> + 'arena> Editor<'arena, Node> {
-    /// Create a new [`Editor`] with a given tree
+    /// Create a new [`Editor`] which is editing some [`Buffer`]s, starting with one [`Window`]
+    /// which shows the first buffer in a given format style.
+    ///
+    /// # Panics
+    /// Panics if `buffers` is empty.
     pub fn new(
-        tree: &'arena mut Dag<'arena, Node>,
+        mut buffers: Vec<Buffer<'arena, Node>>,
         format_style: Node::FormatStyle,
         config: Config,
-        file_path: Option<PathBuf>,
     ) -> Editor<'arena, Node> {
+        assert!(!buffers.is_empty(), "The editor needs at least one buffer");
         let term = Term::new().unwrap();
+        for buffer in &mut buffers {
+            buffer.tree.set_undo_levels(Some(config.undo_levels));
+        }
+        let compaction_threshold = compaction_threshold(buffers[0].tree.arena().len());
+        let window = Window::new(0, format_style, buffers[0].tree.cursor_path().clone());
         Editor {
-            tree,
+            buffers,
+            windows: vec![window],
+            window_layout: WindowLayout::Window(0),
+            current_window: 0,
             term,
-            format_style,
             state: Box::new(normal_mode::State::default()),
             config,
             keystroke_log: KeyStrokeLog::new(10),
-            file_path,
-        }
-    }
-
-    /// Render the tree to the screen
-    fn render_tree(&self, row: usize, col: usize) {
-        let cols = [
-            Color::MAGENTA,
-            Color::RED,
-            Color::YELLOW,
-            Color::GREEN,
-            Color::CYAN,
-            Color::BLUE,
-            Color::WHITE,
-            Color::LIGHT_RED,
-            Color::LIGHT_BLUE,
-            Color::LIGHT_CYAN,
-            Color::LIGHT_GREEN,
-            Color::LIGHT_YELLOW,
-            Color::LIGHT_MAGENTA,
-            Color::LIGHT_WHITE,
-        ];
-
-        // Mutable variables to track where the terminal cursor should go
-        let mut row = row;
-        let mut col = col;
-        let mut indentation_amount = 0;
+            registers: Registers::new(),
+            compaction_threshold,
+            compaction_requested: false,
+            last_search: None,
+        }
+    }
 
-        let mut unknown_categories: HashSet<SyntaxCategory> = HashSet::with_capacity(0);
+    /* ===== BUFFERS ===== */
 
-        /// A cheeky macro to print a string to the terminal
-        macro_rules! term_print {
-            ($string: expr) => {{
-                let string = $string;
-                // Print the string
-                self.term.print(row, col, string).unwrap();
-                // Move the cursor to the end of the string
-                let size = Size::from(string);
-                if size.lines() == 0 {
-                    col += size.last_line_length();
-                } else {
-                    row += size.lines();
-                    col = size.last_line_length();
-                }
-            }};
-            ($string: expr, $attr: expr) => {{
-                let string = $string;
-                // Print the string
-                self.term.print_with_attr(row, col, string, $attr).unwrap();
-                // Move the cursor to the end of the string
-                let size = Size::from(string);
-                if size.lines() == 0 {
-                    col += size.last_line_length();
-                } else {
-                    row += size.lines();
-                    col = size.last_line_length();
-                }
-            }};
-        }
-
-        for (node, tok) in self.tree.root().display_tokens(&self.format_style) {
-            match tok {
-                DisplayToken::Text(s, category) => {
-                    let col = if DEBUG_HIGHLIGHTING {
-                        // Hash the ref to decide on the colour
-                        let mut hasher = DefaultHasher::new();
-                        node.hash(&mut hasher);
-                        let hash = hasher.finish();
-                        cols[hash as usize % cols.len()]
+    /// Returns the index of the [`Buffer`] being shown and edited (i.e. the buffer of the current
+    /// [`Window`])
+    fn current_buffer(&self) -> usize {
+        self.window().buffer
+    }
+
+    /// Returns the [`Buffer`] being shown and edited
+    fn buffer(&self) -> &Buffer<'arena, Node> {
+        &self.buffers[self.current_buffer()]
+    }
+
+    /// Returns the [`Buffer`] being shown and edited, mutably
+    fn buffer_mut(&mut self) -> &mut Buffer<'arena, Node> {
+        let index = self.current_buffer();
+        &mut self.buffers[index]
+    }
+
+    /// Shows the [`Buffer`] `offset` places after the current one (or before it, if `offset` is
+    /// negative) in the current [`Window`], wrapping around the end of the buffer list like Vim's
+    /// `:bnext`/`:bprevious`
+    fn cycle_buffer(&mut self, offset: isize) {
+        let len = self.buffers.len() as isize;
+        let index = (self.current_buffer() as isize + offset).rem_euclid(len) as usize;
+        self.window_mut().buffer = index;
+    }
+
+    /// Shows the [`Buffer`] with a given number (as shown by `:ls`, so the first buffer is number
+    /// 1) in the current [`Window`], returning `false` if there is no such buffer
+    fn switch_to_buffer(&mut self, number: usize) -> bool {
+        if number == 0 || number > self.buffers.len() {
+            return false;
+        }
+        self.window_mut().buffer = number - 1;
+        true
+    }
+
+    /// Opens the file at `path` in a new [`Buffer`] without switching to it, returning the new
+    /// buffer's number.  If the file is already open, then its existing buffer's number is
+    /// returned instead.
+    fn add_buffer(&mut self, path: PathBuf) -> Result<usize, FileError> {
+        if let Some(index) = self
+            .buffers
+            .iter()
+            .position(|buffer| buffer.file_path.as_ref() == Some(&path))
+        {
+            return Ok(index + 1);
+        }
+        let mut tree = load_dag(&path, self.buffer().tree.arena(), self.config.undo_file)?;
+        tree.set_undo_levels(Some(self.config.undo_levels));
+        self.buffers.push(Buffer::new(tree, Some(path)));
+        Ok(self.buffers.len())
+    }
+
+    /// Describes every open [`Buffer`] (for `:ls`) as its number and name, marking the current
+    /// buffer with `%` and buffers with unsaved changes with `[+]`
+    fn buffer_list(&self) -> String {
+        self.buffers
+            .iter()
+            .enumerate()
+            .map(|(index, buffer)| {
+                format!(
+                    "{}{} {}{}",
+                    if index == self.current_buffer() {
+                        "%"
                     } else {
-                        *self.config.color_scheme.get(category).unwrap_or_else(|| {
-                            unknown_categories.insert(category);
-                            &Color::LIGHT_MAGENTA
-                        })
-                    };
-                    // Generate the display attributes depending on if the node is selected
-                    let attr = if std::ptr::eq(node, self.tree.cursor()) {
-                        Attr::default().fg(Color::BLACK).bg(col)
+                        ""
+                    },
+                    index + 1,
+                    buffer.name(),
+                    if buffer.tree.is_modified() {
+                        " [+]"
                     } else {
-                        Attr::default().fg(col)
-                    };
-                    // Print the token
-                    term_print!(s.borrow(), attr);
-                }
-                DisplayToken::Whitespace(n) => {
-                    col += n;
-                }
-                DisplayToken::Newline => {
-                    row += 1;
-                    col = indentation_amount;
-                }
-                DisplayToken::Indent => {
-                    indentation_amount += 4;
-                }
-                DisplayToken::Dedent => {
-                    indentation_amount -= 4;
-                }
+                        ""
+                    }
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// If any [`Buffer`] has unsaved changes, returns a message explaining why Sapling can't quit
+    /// (naming the buffer, unless it is the current one)
+    fn unsaved_changes_message(&self) -> Option<String> {
+        if self.buffer().tree.is_modified() {
+            return Some("unsaved changes (use ':q!' to quit anyway)".to_owned());
+        }
+        let buffer = self
+            .buffers
+            .iter()
+            .find(|buffer| buffer.tree.is_modified())?;
+        Some(format!(
+            "unsaved changes in {} (use ':q!' to quit anyway)",
+            buffer.name()
+        ))
+    }
+
+    /* ===== WINDOWS ===== */
+
+    /// Returns the [`Window`] that the user is in
+    fn window(&self) -> &Window<'arena, Node> {
+        &self.windows[self.current_window]
+    }
+
+    /// Returns the [`Window`] that the user is in, mutably
+    fn window_mut(&mut self) -> &mut Window<'arena, Node> {
+        &mut self.windows[self.current_window]
+    }
+
+    /// Returns the part of the screen used by each [`Window`] (see [`WindowLayout::rects`]).  The
+    /// windows share everything except the bottom bar and the undo tree panel (if it's open).
+    fn window_rects(&self) -> Vec<(usize, Rect)> {
+        let (width, height) = self.term.term_size().unwrap();
+        self.window_layout.rects(Rect {
+            row: 0,
+            col: 0,
+            height: height.saturating_sub(1),
+            width: width - self.undo_tree_width(width),
+        })
+    }
+
+    /// Moves the user into the [`Window`] with a given index.  The cursor of the window being
+    /// left is stored in that window, and the cursor of the new window is restored (since several
+    /// windows can show the same [`Dag`], but each has its own cursor).
+    fn go_to_window(&mut self, index: usize) {
+        let cursor_path = self.buffer().tree.cursor_path().clone();
+        self.window_mut().cursor_path = cursor_path;
+        self.current_window = index;
+        let cursor_path = self.window().cursor_path.clone();
+        self.buffer_mut().tree.set_cursor_path(cursor_path);
+    }
+
+    /// Moves to the [`Window`] `offset` places after the current one (or before it, if `offset` is
+    /// negative), in the order that the windows appear on the screen
+    fn cycle_window(&mut self, offset: isize) {
+        let order = self.window_layout.windows();
+        let position = order
+            .iter()
+            .position(|window| *window == self.current_window)
+            .unwrap();
+        let new_position = (position as isize + offset).rem_euclid(order.len() as isize);
+        self.go_to_window(order[new_position as usize]);
+    }
+
+    /// Moves to the [`Window`] next to the current one in a given direction, returning `false` if
+    /// there is no window in that direction
+    fn go_to_neighbouring_window(&mut self, direction: ScreenDirection) -> bool {
+        match window::neighbour(&self.window_rects(), self.current_window, direction) {
+            Some(index) => {
+                self.go_to_window(index);
+                true
             }
+            None => false,
+        }
+    }
+
+    /// Splits the current [`Window`] in two, like Vim's `:split` and `:vsplit`.  The new window
+    /// shows the same [`Buffer`] in the same way, and becomes the current window.  Returns `false`
+    /// if the current window is too small to be split.
+    fn split_window(&mut self, direction: SplitDirection) -> bool {
+        let rect = match self
+            .window_rects()
+            .into_iter()
+            .find(|(window, _)| *window == self.current_window)
+        {
+            Some((_, rect)) => rect,
+            None => return false,
+        };
+        // Each half needs room for at least one row of the tree and its status line
+        let is_big_enough = match direction {
+            SplitDirection::Horizontal => rect.height >= 4,
+            SplitDirection::Vertical => rect.width >= 3,
+        };
+        if !is_big_enough {
+            return false;
+        }
+        let window = self.window();
+        let mut new_window = Window::new(
+            window.buffer,
+            window.format_style.clone(),
+            self.buffer().tree.cursor_path().clone(),
+        );
+        new_window.viewport = window.viewport;
+        self.windows.push(new_window);
+        let new_index = self.windows.len() - 1;
+        self.window_layout
+            .split(self.current_window, new_index, direction);
+        // Both windows have the same cursor, so the `Dag`'s cursor doesn't need to change
+        self.current_window = new_index;
+        true
+    }
+
+    /// Closes the current [`Window`] (but not its [`Buffer`]), moving to the window before it.
+    /// Returns `false` if this is the only window, since it can't be closed.
+    fn close_window(&mut self) -> bool {
+        let order = self.window_layout.windows();
+        if order.len() == 1 {
+            return false;
+        }
+        let closed = self.current_window;
+        let position = order.iter().position(|window| *window == closed).unwrap();
+        let next = order[if position == 0 { 1 } else { position - 1 }];
+        self.window_layout.remove(closed);
+        self.windows.remove(closed);
+        self.current_window = if next > closed { next - 1 } else { next };
+        let cursor_path = self.window().cursor_path.clone();
+        self.buffer_mut().tree.set_cursor_path(cursor_path);
+        true
+    }
+
+    /// Closes every [`Window`] except the current one, like Vim's `:only`
+    fn close_other_windows(&mut self) {
+        let window = self.windows.swap_remove(self.current_window);
+        self.windows = vec![window];
+        self.window_layout = WindowLayout::Window(0);
+        self.current_window = 0;
+    }
+
+    /* ===== COMPACTION ===== */
+
+    /// Copies everything this `Editor` needs out of its arena: every [`Buffer`] and the contents
+    /// of every register.  Nodes shared between buffers stay shared.
+    fn detach(self) -> DetachedEditor<Node::FormatStyle> {
+        let mut detacher = Detacher::new();
+        let registers = self.registers.map(|node| detacher.detach_node(*node));
+        let buffers = self
+            .buffers
+            .into_iter()
+            .map(|buffer| buffer.detach(&mut detacher))
+            .collect();
+        DetachedEditor {
+            nodes: detacher.finish(),
+            buffers,
+            windows: self.windows.into_iter().map(Window::detach).collect(),
+            window_layout: self.window_layout,
+            current_window: self.current_window,
+            registers,
+            term: self.term,
+            config: self.config,
+            keystroke_log: self.keystroke_log,
+            last_search: self.last_search,
+        }
+    }
+
+    /// Re-creates an `Editor` from a [`DetachedEditor`], copying its nodes into `arena`.  The
+    /// `Editor` starts in normal mode.
+    pub fn attach(
+        detached: DetachedEditor<Node::FormatStyle>,
+        arena: &'arena Arena<Node>,
+    ) -> Editor<'arena, Node> {
+        let nodes = detached.nodes.attach(arena);
+        let buffers = detached
+            .buffers
+            .into_iter()
+            .map(|buffer| buffer.attach(arena, &nodes))
+            .collect();
+        let registers = detached.registers.map(|index| nodes[*index]);
+        log::info!("Compacted the arena to {} nodes", arena.len());
+        Editor {
+            buffers,
+            windows: detached
+                .windows
+                .into_iter()
+                .map(DetachedWindow::attach)
+                .collect(),
+            window_layout: detached.window_layout,
+            current_window: detached.current_window,
+            term: detached.term,
+            state: Box::new(normal_mode::State::default()),
+            config: detached.config,
+            keystroke_log: detached.keystroke_log,
+            registers,
+            compaction_threshold: compaction_threshold(arena.len()),
+            compaction_requested: false,
+            last_search: detached.last_search,
         }
+    }
+
+    /// Returns `true` if the arena should be compacted before handling the next keystroke
+    fn should_compact(&self) -> bool {
+        // Compacting replaces the current state, so only happens between commands
+        self.state.is_idle()
+            && (self.compaction_requested
+                || self.buffer().tree.arena().len() > self.compaction_threshold)
+    }
+
+    /* ===== RENDERING ===== */
+
+    /// Render the part of a [`Window`]'s tree inside its [`Viewport`](viewport::Viewport) into the
+    /// window's `rect` of the screen, returning the on-screen location of the text-editing caret
+    /// (if the window's cursor is having its text edited and the caret is on the screen).  If
+    /// `has_status_line` is `true`, the bottom row of `rect` shows the name of the window's
+    /// [`Buffer`].
+    fn render_window(
+        &self,
+        index: usize,
+        rect: Rect,
+        has_status_line: bool,
+    ) -> Option<(usize, usize)> {
+        let window = &self.windows[index];
+        let buffer = &self.buffers[window.buffer];
+        let is_current = index == self.current_window;
+        window.layout_cache.next_frame();
+
+        // The current window's cursor is stored in its `Dag`
+        let cursor_path = if is_current {
+            buffer.tree.cursor_path()
+        } else {
+            &window.cursor_path
+        };
+        // Only the current window can be selecting siblings of its cursor
+        let selection = match (is_current, cursor_path.last()) {
+            (true, Some(cursor_index)) => self
+                .state
+                .visual_anchor()
+                .map(|anchor| visual_mode::selected_range(anchor, cursor_index)),
+            _ => None,
+        };
+        // Every window highlights the matches of the last search
+        let (search_query, search_match_paths) =
+            match self.last_search.as_ref().and_then(|search| {
+                window
+                    .search_matches
+                    .matches(buffer.tree.root(), &search.pattern)
+            }) {
+                Some(matches) => (Some(matches.query), matches.paths),
+                None => (None, Rc::from([])),
+            };
+        let renderer = WindowRenderer {
+            term: &self.term,
+            config: &self.config,
+            window,
+            origin: (rect.row, rect.col),
+            text_edit: if is_current {
+                self.state.text_edit()
+            } else {
+                None
+            },
+            selection,
+            caret_position: Cell::new(None),
+            search_query,
+        };
+        let root_marks = NodeMarks {
+            cursor_path: Some(cursor_path.iter().as_slice()),
+            // Only the current window can have several cursors
+            other_cursor_paths: if is_current {
+                self.state
+                    .other_cursors()
+                    .iter()
+                    .map(|path| path.iter().as_slice())
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            is_selected: false,
+            depth: 0,
+            search_match_paths: &search_match_paths,
+        };
+        let mut unknown_categories: HashSet<SyntaxCategory> = HashSet::with_capacity(0);
+        renderer.render_node(
+            buffer.tree.root(),
+            (0, 0),
+            0,
+            &root_marks,
+            &mut unknown_categories,
+        );
 
         // Print warning messages for unknown syntax categories
         for c in unknown_categories {
             log::error!("Unknown highlight category '{}'", c);
         }
+
+        if has_status_line && rect.height > 0 {
+            let modified_indicator = if buffer.tree.is_modified() {
+                " [+]"
+            } else {
+                ""
+            };
+            let text = format!(" {}{}", buffer.name(), modified_indicator);
+            let status_line: String = text
+                .chars()
+                .chain(std::iter::repeat(' '))
+                .take(rect.width)
+                .collect();
+            // Like Vim, the current window's status line is bold
+            let attr = if is_current {
+                Attr::default().effect(Effect::REVERSE | Effect::BOLD)
+            } else {
+                Attr::default().effect(Effect::REVERSE)
+            };
+            self.term
+                .print_with_attr(rect.bottom() - 1, rect.col, &status_line, attr)
+                .unwrap();
+        }
+
+        renderer.caret_position.get()
+    }
+
+    /// Returns how many columns on the right of a terminal `width` columns wide are taken up by
+    /// the undo tree panel, which is 0 if the panel isn't open
+    fn undo_tree_width(&self, width: usize) -> usize {
+        match self.state.undo_tree_selection() {
+            Some(_) => UNDO_TREE_WIDTH.min(width / 2),
+            None => 0,
+        }
+    }
+
+    /// Render the undo tree panel into the `width` columns starting at column `col`, using the top
+    /// `height` rows of the screen.  Each [`Snapshot`](dag::Snapshot) is shown with its number,
+    /// description and age.  The current snapshot is highlighted, and the `selected` snapshot is
+    /// shown in reverse video (scrolling the panel if needed to keep it on the screen).
+    fn render_undo_tree(&self, selected: usize, col: usize, width: usize, height: usize) {
+        let tree = &self.buffer().tree;
+        let history = tree.history();
+        let rows = undo_tree::graph_rows(history);
+        let selected_row = rows
+            .iter()
+            .position(|row| row.index == selected)
+            .unwrap_or(0);
+        let first_row = (selected_row + 1).saturating_sub(height);
+        let now = SystemTime::now();
+        for screen_row in 0..height {
+            self.term.print(screen_row, col, "│").unwrap();
+        }
+        for (screen_row, row) in rows[first_row..].iter().take(height).enumerate() {
+            let snapshot = &history[row.index];
+            let text = format!(
+                "{} {}: {} ({} ago)",
+                row.graph,
+                row.index,
+                snapshot.description,
+                undo_tree::format_age(snapshot.time, now)
+            );
+            let mut attr = Attr::default();
+            if row.index == tree.history_index() {
+                attr = attr.fg(Color::LIGHT_YELLOW).effect(Effect::BOLD);
+            }
+            if row.index == selected {
+                attr = attr.effect(attr.effect | Effect::REVERSE);
+            }
+            let visible_text: String = text.chars().take(width.saturating_sub(2)).collect();
+            self.term
+                .print_with_attr(screen_row, col + 2, &visible_text, attr)
+                .unwrap();
+        }
+    }
+
+    /* ===== SCROLLING ===== */
+
+    /// Resizes the [`Viewport`](viewport::Viewport) of every [`Window`] to fit its part of the
+    /// screen and, if the cursor has moved (or the tree has changed) since the last call, scrolls
+    /// the current window so that the cursor is visible.  Whilst text is being edited, the caret
+    /// is always kept on the screen.
+    fn update_viewports(&mut self) {
+        let rects = self.window_rects();
+        // Every window has a status line if there is more than one window
+        let status_line_height = if rects.len() > 1 { 1 } else { 0 };
+        for (index, rect) in rects {
+            let viewport = &mut self.windows[index].viewport;
+            viewport.height = rect.height.saturating_sub(status_line_height).max(1);
+            viewport.width = rect.width.max(1);
+        }
+        let caret = self.state.text_edit().map(|(_, caret)| caret);
+        let window = &mut self.windows[self.current_window];
+        window.follow_cursor(&self.buffers[window.buffer].tree, caret);
+    }
+
+    /// Scrolls the current [`Window`] up or down by a number of pages (see
+    /// [`Window::scroll_pages`])
+    fn scroll_pages(&mut self, pages: isize) {
+        let window = &mut self.windows[self.current_window];
+        window.scroll_pages(&self.buffers[window.buffer].tree, pages);
+    }
+
+    /// Scrolls the current [`Window`] so that the cursor is in the middle of it
+    fn center_on_cursor(&mut self) {
+        let window = &mut self.windows[self.current_window];
+        window.center_on_cursor(&self.buffers[window.buffer].tree);
+    }
+
+    /* ===== FILE I/O ===== */
+
+    /// Writes the current [`Buffer`] (in the current [`Window`]'s format style) to `path`, or to
+    /// its file if `path` is `None`, returning the path that was written to (see
+    /// [`Buffer::write`])
+    fn write(&mut self, path: Option<PathBuf>) -> Result<PathBuf, FileError> {
+        let window = &self.windows[self.current_window];
+        self.buffers[window.buffer].write(path, &window.format_style, self.config.undo_file)
+    }
+
+    /// Replaces the tree of the current [`Buffer`] with the contents of the file at `path` (see
+    /// [`Buffer::open`])
+    fn open(&mut self, path: PathBuf) -> Result<(), FileError> {
+        let index = self.current_buffer();
+        self.buffers[index].open(path, &self.config)
+    }
+
+    /// Sets the value of a user-configurable option, as used by `:set <option>=<value>`
+    fn set_option(&mut self, option: &str, value: &str) -> Result<(), String> {
+        match option {
+            // Each window has its own format style
+            "format" => {
+                let window = self.window_mut();
+                window.format_style = Node::FormatStyle::from_str(value)
+                    .map_err(|_| format!("Unknown format '{}'", value))?;
+                // The layouts of the nodes depend on the format style
+                window.layout_cache.clear();
+                Ok(())
+            }
+            "undofile" => {
+                self.config.undo_file = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err(format!("Expected 'true' or 'false', got '{}'", value)),
+                };
+                Ok(())
+            }
+            "undolevels" => {
+                self.config.undo_levels = value
+                    .parse()
+                    .map_err(|_| format!("Expected a non-negative integer, got '{}'", value))?;
+                for buffer in &mut self.buffers {
+                    buffer.tree.set_undo_levels(Some(self.config.undo_levels));
+                }
+                Ok(())
+            }
+            _ => Err(format!("Unknown option '{}'", option)),
+        }
     }
 
     /* ===== MAIN FUNCTIONS ===== */
@@ -199,20 +856,88 @@ impl<'arena, Node: Ast<'arena> + 'arena> Editor<'arena, Node> {
         // Clear the terminal
         self.term.clear().unwrap();
 
-        /* RENDER MAIN TEXT VIEW */
+        /* RENDER THE WINDOWS */
 
-        self.render_tree(0, 0);
+        let rects = self.window_rects();
+        let mut caret_position = None;
+        for &(index, rect) in &rects {
+            let window_caret = self.render_window(index, rect, rects.len() > 1);
+            if index == self.current_window {
+                caret_position = window_caret;
+            }
+        }
+        // Windows which are side by side are separated by a vertical line
+        let tree_area_width = width - self.undo_tree_width(width);
+        for (_, rect) in &rects {
+            if rect.right() < tree_area_width {
+                for row in rect.row..rect.bottom() {
+                    self.term.print(row, rect.right(), "│").unwrap();
+                }
+            }
+        }
 
-        /* RENDER LOG SECTION */
+        /* RENDER LOG SECTION OR UNDO TREE */
 
-        self.keystroke_log.render(&self.term, 0, width / 2);
+        // The undo tree panel covers the right of the screen, and the log covers the right half of
+        // the current window
+        match self.state.undo_tree_selection() {
+            Some(selected) => {
+                let panel_width = self.undo_tree_width(width);
+                self.render_undo_tree(
+                    selected,
+                    width - panel_width,
+                    panel_width,
+                    height.saturating_sub(1),
+                );
+            }
+            None => {
+                let rect = rects
+                    .iter()
+                    .find(|(index, _)| *index == self.current_window)
+                    .map_or_else(Rect::default, |(_, rect)| *rect);
+                self.keystroke_log
+                    .render(&self.term, rect.row, rect.col + rect.width / 2);
+            }
+        }
 
         /* RENDER BOTTOM BAR */
 
-        // Add the `Press 'q' to exit.` message
-        self.term
-            .print(height - 1, 0, "Press 'q' to exit.")
-            .unwrap();
+        // Draw the command line if the user is typing a command, otherwise show the buffer's name
+        // (with `[+]` if there are unsaved changes) and the `Press 'q' to exit.` message.  If
+        // there are several buffers, the name is preceded by the buffer's number.
+        let caret_position = match self.state.command_line() {
+            Some((prompt, command)) => {
+                self.term.print(height - 1, 0, &prompt.to_string()).unwrap();
+                self.term.print(height - 1, 1, command).unwrap();
+                Some((height - 1, 1 + command.chars().count()))
+            }
+            None => {
+                let buffer = self.buffer();
+                let buffer_number = if self.buffers.len() > 1 {
+                    format!("[{}/{}] ", self.current_buffer() + 1, self.buffers.len())
+                } else {
+                    String::new()
+                };
+                let modified_indicator = if buffer.tree.is_modified() {
+                    " [+]"
+                } else {
+                    ""
+                };
+                self.term
+                    .print(
+                        height - 1,
+                        0,
+                        &format!(
+                            "{}{}{}  Press 'q' to exit.",
+                            buffer_number,
+                            buffer.name(),
+                            modified_indicator
+                        ),
+                    )
+                    .unwrap();
+                caret_position
+            }
+        };
         // Draw the current keystroke buffer
         let keystroke_buffer = self.state.keystroke_buffer();
         self.term
@@ -223,13 +948,24 @@ impl<'arena, Node: Ast<'arena> + 'arena> Editor<'arena, Node> {
             )
             .unwrap();
 
+        // Only show the terminal's cursor if it's being used as a text-editing caret
+        match caret_position {
+            Some((row, col)) => self.term.set_cursor(row, col).unwrap(),
+            None => self.term.show_cursor(false).unwrap(),
+        }
+
         /* UPDATE THE TERMINAL SCREEN */
 
         self.term.present().unwrap();
     }
 
-    fn mainloop(&mut self) {
+    /// Runs the mainloop, returning `true` if it stopped so that the arena can be compacted, or
+    /// `false` if Sapling should quit
+    fn mainloop(&mut self) -> bool {
         log::trace!("Starting mainloop");
+        // Draw the screen straight away, since the mainloop could be restarting after compacting
+        self.update_viewports();
+        self.update_display();
         // Sit in the infinte mainloop
         while let Ok(event) = self.term.poll_event() {
             /* RESPOND TO THE USER'S INPUT */
@@ -264,25 +1000,352 @@ impl<'arena, Node: Ast<'arena> + 'arena> Editor<'arena, Node> {
             if self.state.is_quit() {
                 break;
             }
+            if self.should_compact() {
+                return true;
+            }
 
             // Make sure that the logger isn't taller than the screen
             self.keystroke_log
                 .set_max_entries(self.term.term_size().unwrap().1.min(10));
+            // Scroll the viewports to follow the cursor
+            self.update_viewports();
             // Update the screen after every input (if this becomes a bottleneck then we can
             // optimise the number of calls to `update_display` but for now it's not worth the
             // added complexity)
             self.update_display();
         }
+        false
     }
 
-    /// Start the editor and enter the mainloop
-    pub fn run(mut self) {
-        // Start the mainloop, which will not exit until Sapling is ready to close
-        self.mainloop();
+    /// Start the editor and enter the mainloop.  This returns `None` once Sapling quits.  The
+    /// mainloop also stops when the arena needs compacting, in which case this returns the
+    /// [`DetachedEditor`], which should be [attached](Editor::attach) to a new arena to carry on
+    /// editing.
+    pub fn run(mut self) -> Option<DetachedEditor<Node::FormatStyle>> {
+        // Start the mainloop, which will not exit until Sapling is ready to close or compact
+        if self.mainloop() {
+            return Some(self.detach());
+        }
         // Show the cursor before closing so that the cursor isn't permanently disabled
         // (see issue `lotabout/tuikit#28`: https://github.com/lotabout/tuikit/issues/28)
         log::trace!("Making the cursor reappear.");
         self.term.show_cursor(true).unwrap();
         self.term.present().unwrap();
+        None
+    }
+}
+
+/// Everything needed to render the tree of one [`Window`] into its part of the screen
+struct WindowRenderer<'e, 'arena, Node: Ast<'arena>> {
+    term: &'e Term,
+    config: &'e Config,
+    window: &'e Window<'arena, Node>,
+    /// The `(row, column)` of the window's top-left corner on the screen
+    origin: (usize, usize),
+    /// The text being edited in place of the cursor's text, and the caret's position in it
+    text_edit: Option<(&'e str, usize)>,
+    /// The child indices of the first and last of the cursor's siblings which are selected in
+    /// [visual mode](visual_mode)
+    selection: Option<(usize, usize)>,
+    /// The on-screen location of the text-editing caret, once it has been rendered
+    caret_position: Cell<Option<(usize, usize)>>,
+    /// The query of the last [search](Search), whose matches are highlighted
+    search_query: Option<Rc<Query<Node::Class>>>,
+}
+
+/// Where a node being rendered is in relation to the cursors, the visual mode selection and the
+/// matches of the last [search](Search).  The cursors and matches can't be found by their
+/// addresses, because an [interning](crate::arena::Arena::with_interning) arena shares identical
+/// nodes within the same tree.
+struct NodeMarks<'p> {
+    /// If the node is on the path to the cursor, this is the rest of that path (so the node is the
+    /// cursor if it is empty)
+    cursor_path: Option<&'p [usize]>,
+    /// The rest of the paths to each of the [other cursors](multi_cursor) which the node is on the
+    /// path to
+    other_cursor_paths: Vec<&'p [usize]>,
+    /// `true` if the node is inside the [visual mode](visual_mode) selection
+    is_selected: bool,
+    /// The depth of the node in the tree
+    depth: usize,
+    /// The paths to the matches of the last [search](Search) which are the node or its
+    /// descendants, if the search is a [`PathQuery`](crate::core::PathQuery).  Other searches are
+    /// matched against each node as it is rendered.
+    search_match_paths: &'p [Path],
+}
+
+impl<'p> NodeMarks<'p> {
+    /// Returns the `NodeMarks` of the `index`th child of this node, where `selection` is the range
+    /// of the cursor's siblings which are selected
+    fn child(&self, index: usize, selection: Option<(usize, usize)>) -> NodeMarks<'p> {
+        let cursor_path = match self.cursor_path {
+            Some([first, rest @ ..]) if *first == index => Some(rest),
+            _ => None,
+        };
+        // Keep the rest of the paths which go through the child
+        let child_paths = |paths: &[&'p [usize]]| -> Vec<&'p [usize]> {
+            paths
+                .iter()
+                .filter_map(|path| match path {
+                    [first, rest @ ..] if *first == index => Some(rest),
+                    _ => None,
+                })
+                .collect()
+        };
+        // The selected nodes are siblings of the cursor, so they are children of the node whose
+        // remaining cursor path has one step
+        let is_selected = self.is_selected
+            || match (self.cursor_path, selection) {
+                (Some([_]), Some((first, last))) => (first..=last).contains(&index),
+                _ => false,
+            };
+        // The matches are sorted in tree order, so the ones inside the child come after any match
+        // of this node itself and are next to each other
+        let descendant_matches = match self.search_match_paths.first() {
+            Some(path) if path.depth() == self.depth => &self.search_match_paths[1..],
+            _ => self.search_match_paths,
+        };
+        let child_index_of = |path: &Path| path.iter().as_slice()[self.depth];
+        let start = descendant_matches.partition_point(|path| child_index_of(path) < index);
+        let end = descendant_matches.partition_point(|path| child_index_of(path) <= index);
+        NodeMarks {
+            cursor_path,
+            other_cursor_paths: child_paths(&self.other_cursor_paths),
+            is_selected,
+            depth: self.depth + 1,
+            search_match_paths: &descendant_matches[start..end],
+        }
+    }
+
+    /// Returns `true` if the node is the cursor
+    fn is_cursor(&self) -> bool {
+        self.cursor_path == Some(&[])
+    }
+
+    /// Returns `true` if the node is one of the other cursors
+    fn is_other_cursor(&self) -> bool {
+        self.other_cursor_paths.iter().any(|path| path.is_empty())
+    }
+
+    /// Returns `true` if the node is one of the matches of the last [`PathQuery`] search
+    ///
+    /// [`PathQuery`]: crate::core::PathQuery
+    fn is_path_query_match(&self) -> bool {
+        self.search_match_paths
+            .first()
+            .is_some_and(|path| path.depth() == self.depth)
+    }
+}
+
+impl<'e, 'arena, Node: Ast<'arena>> WindowRenderer<'e, 'arena, Node> {
+    /// Render the visible parts of a node which starts at a given `(row, column)` in the rendered
+    /// tree, and is indented by `indentation` columns.  Any [`Item`](layout::Item)s of the node
+    /// which are entirely outside the [`Viewport`](viewport::Viewport) are skipped without being
+    /// rendered.  The node is highlighted according to its [`NodeMarks`].
+    fn render_node(
+        &self,
+        node: &'arena Node,
+        (row, col): (usize, usize),
+        indentation: usize,
+        marks: &NodeMarks<'_>,
+        unknown_categories: &mut HashSet<SyntaxCategory>,
+    ) {
+        let window = self.window;
+        let layout = window.layout_cache.layout(node, &window.format_style);
+        let is_search_match = match &self.search_query {
+            Some(query) => query
+                .matches_node(node)
+                .unwrap_or_else(|| marks.is_path_query_match()),
+            None => false,
+        };
+        // The items' rows are sorted, so we can binary search for the first one that isn't
+        // entirely above the viewport
+        let first_visible_item = layout
+            .items
+            .partition_point(|placed| row + placed.row + placed.size.lines() < window.viewport.top);
+
+        let mut child_index = layout.items[..first_visible_item]
+            .iter()
+            .filter(|placed| matches!(placed.item, layout::Item::Child(_)))
+            .count();
+        for placed in &layout.items[first_visible_item..] {
+            let item_row = row + placed.row;
+            // Everything after the bottom of the viewport is off the screen
+            if item_row >= window.viewport.top + window.viewport.height {
+                break;
+            }
+            let item_col = if placed.row == 0 {
+                col + placed.col
+            } else {
+                indentation + placed.col
+            };
+            let (text, category) = match &placed.item {
+                layout::Item::Child(child) => {
+                    self.render_node(
+                        child,
+                        (item_row, item_col),
+                        indentation + placed.indentation,
+                        &marks.child(child_index, self.selection),
+                        unknown_categories,
+                    );
+                    child_index += 1;
+                    continue;
+                }
+                layout::Item::Text(text, category) => (text, *category),
+            };
+
+            let color = if DEBUG_HIGHLIGHTING {
+                // Hash the ref to decide on the colour
+                let mut hasher = DefaultHasher::new();
+                node.hash(&mut hasher);
+                let hash = hasher.finish();
+                DEBUG_COLORS[hash as usize % DEBUG_COLORS.len()]
+            } else {
+                *self.config.color_scheme.get(category).unwrap_or_else(|| {
+                    unknown_categories.insert(category);
+                    &Color::LIGHT_MAGENTA
+                })
+            };
+            // Generate the display attributes depending on if the node is selected
+            let is_cursor = marks.is_cursor();
+            let attr = if is_cursor {
+                Attr::default().fg(Color::BLACK).bg(color)
+            } else if marks.is_other_cursor() {
+                Attr::default()
+                    .fg(Color::BLACK)
+                    .bg(color)
+                    .effect(Effect::UNDERLINE)
+            } else if marks.is_selected {
+                Attr::default().fg(color).bg(Color::LIGHT_BLACK)
+            } else if is_search_match {
+                Attr::default()
+                    .fg(color)
+                    .effect(Effect::BOLD | Effect::UNDERLINE)
+            } else {
+                Attr::default().fg(color)
+            };
+            // Print the text, replacing the cursor's text with the text being edited
+            match self.text_edit {
+                Some((edit_text, caret)) if is_cursor => {
+                    // Only print the edited text in place of the cursor's first piece of text
+                    if placed.row == 0 && placed.col == 0 {
+                        self.caret_position.set(
+                            window
+                                .viewport
+                                .to_screen(item_row, item_col + caret)
+                                .map(|(row, col)| (self.origin.0 + row, self.origin.1 + col)),
+                        );
+                        self.print_in_viewport(item_row, item_col, edit_text, attr);
+                    }
+                }
+                _ => self.print_in_viewport(item_row, item_col, text, attr),
+            }
+        }
+    }
+
+    /// Prints some text at a position in the rendered tree, clipping any of it which is outside
+    /// the [`Viewport`](viewport::Viewport)
+    fn print_in_viewport(&self, row: usize, col: usize, string: &str, attr: Attr) {
+        let viewport = &self.window.viewport;
+        for (i, line) in string.split('\n').enumerate() {
+            let (row, col) = if i == 0 { (row, col) } else { (row + i, 0) };
+            if !viewport.contains_row(row) {
+                continue;
+            }
+            // Skip any chars which are to the left of the viewport
+            let skipped_chars = viewport.left.saturating_sub(col);
+            let screen_col = col + skipped_chars - viewport.left;
+            if screen_col >= viewport.width {
+                continue;
+            }
+            let visible_text: String = line
+                .chars()
+                .skip(skipped_chars)
+                .take(viewport.width - screen_col)
+                .collect();
+            self.term
+                .print_with_attr(
+                    self.origin.0 + row - viewport.top,
+                    self.origin.1 + screen_col,
+                    &visible_text,
+                    attr,
+                )
+                .unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_file, write_atomically, FileError};
+    use crate::arena::Arena;
+    use crate::ast::json::Json;
+
+    #[test]
+    fn load_files() {
+        let dir = std::env::temp_dir().join(format!("sapling-load-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let arena: Arena<Json> = Arena::new();
+        // Existing files are parsed
+        let path = dir.join("file.json");
+        std::fs::write(&path, "[true, 1.5]").unwrap();
+        assert_eq!(
+            *load_file(&path, &arena).unwrap(),
+            serde_json::json!([true, 1.5])
+        );
+        // Files which don't exist are new files, which start as the default tree
+        let new_path = dir.join("new.json");
+        assert_eq!(*load_file(&new_path, &arena).unwrap(), Json::default());
+        assert!(!new_path.exists());
+        // Invalid files are reported
+        std::fs::write(&path, "[true,").unwrap();
+        assert!(matches!(
+            load_file(&path, &arena),
+            Err(FileError::Parse(..))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn atomic_write() {
+        let dir = std::env::temp_dir().join(format!("sapling-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.json");
+        // Creating a new file
+        write_atomically(&path, "[true]\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[true]\n");
+        // Overwriting an existing file
+        write_atomically(&path, "{}\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}\n");
+        // Temporary files which already exist are left alone
+        let other_temp_path = dir.join(format!(".file.json.{}-0.sapling-tmp", std::process::id()));
+        std::fs::write(&other_temp_path, "other").unwrap();
+        write_atomically(&path, "[]\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[]\n");
+        assert_eq!(std::fs::read_to_string(&other_temp_path).unwrap(), "other");
+        std::fs::remove_file(&other_temp_path).unwrap();
+        // Writing into a directory that doesn't exist fails without creating anything
+        let bad_path = dir.join("missing-dir").join("file.json");
+        assert!(write_atomically(&bad_path, "[]").is_err());
+        assert!(!bad_path.exists());
+        // No temporary files should be left behind
+        let file_names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(file_names, vec!["file.json"]);
+        // Writing through a symbolic link replaces the file that it points to, keeping the link
+        #[cfg(unix)]
+        {
+            let link_path = dir.join("link.json");
+            std::os::unix::fs::symlink(&path, &link_path).unwrap();
+            write_atomically(&link_path, "[null]\n").unwrap();
+            assert!(std::fs::symlink_metadata(&link_path)
+                .unwrap()
+                .file_type()
+                .is_symlink());
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "[null]\n");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,276 @@
+//! The code for multi-cursor mode, where every edit is made at several structurally similar nodes
+//! at once (e.g. every value of a given key in an array of JSON objects)
+
+use super::dag::LogMessage;
+use super::normal_mode::{self, parse_command, Action, ParseErr};
+use super::{keystroke_log::Category, state, Editor};
+use crate::ast::Ast;
+use crate::core::{keystrokes_to_string, Path, Side};
+
+use std::borrow::Cow;
+
+use tuikit::prelude::Key;
+
+/// Returns the paths of every node in the tree which is structurally similar to the node at
+/// `path` (including that node), in the order that they appear in the tree.
+///
+/// A similar node is found by following the same steps down the tree as `path`, where each step
+/// can go to any child which matches the child that `path` goes to.  Children with a
+/// [`key`](Ast::key) match children with the same key (so in JSON, the fields of different objects
+/// are matched by their keys).  The children of nodes without a [`class`](Ast::class) (such as JSON
+/// fields) each have a fixed role, so they only match children at the same position.  Otherwise,
+/// every child without a key matches (e.g. every element of a JSON array).
+pub fn similar_paths<'arena, Node: Ast<'arena>>(root: &'arena Node, path: &Path) -> Vec<Path> {
+    let mut matches: Vec<(Path, &'arena Node)> = vec![(Path::root(), root)];
+    let path_nodes: Vec<&'arena Node> = path.node_iter(root).collect();
+    for (step, &index) in path.iter().enumerate() {
+        let (parent, child) = (path_nodes[step], path_nodes[step + 1]);
+        let mut next_matches = Vec::new();
+        for (matched_path, node) in matches {
+            for (i, other_child) in node.children().iter().enumerate() {
+                let is_match = match child.key() {
+                    Some(key) => other_child.key() == Some(key),
+                    None if parent.class().is_none() => i == index,
+                    None => other_child.key().is_none(),
+                };
+                if is_match {
+                    let mut child_path = matched_path.clone();
+                    child_path.push(i);
+                    next_matches.push((child_path, *other_child));
+                }
+            }
+        }
+        matches = next_matches;
+    }
+    matches.into_iter().map(|(path, _)| path).collect()
+}
+
+/// The [`State`](state::State) which Sapling is in whilst there are several cursors.  The
+/// [`Dag`](super::dag::Dag)'s cursor is the primary cursor, and this stores the paths to the other
+/// cursors.  Moving the cursor moves every cursor, and each edit is made at every cursor as a
+/// single undo step.
+#[derive(Debug, Clone)]
+pub struct State {
+    /// The paths to every cursor except the primary one, in the order they appear in the tree
+    other_cursors: Vec<Path>,
+    keystroke_buffer: Vec<Key>,
+}
+
+impl State {
+    /// Creates a new multi-cursor `State`, with cursors at the given paths as well as the primary
+    /// cursor
+    pub fn new(other_cursors: Vec<Path>) -> Self {
+        State {
+            other_cursors,
+            keystroke_buffer: Vec::new(),
+        }
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> state::State<'arena, Node> for State {
+    fn transition(
+        mut self: Box<Self>,
+        key: Key,
+        editor: &mut Editor<'arena, Node>,
+    ) -> (
+        Box<dyn state::State<'arena, Node>>,
+        Option<(String, Category)>,
+    ) {
+        if key == Key::ESC {
+            return (
+                Box::new(normal_mode::State::default()),
+                Some(("leave multi-cursor mode".to_owned(), Category::Undefined)),
+            );
+        }
+        self.keystroke_buffer.push(key);
+
+        let current_buffer = editor.current_buffer();
+        let tree = &mut editor.buffers[current_buffer].tree;
+
+        let (count, action) = match parse_command(&editor.config.keymap, &self.keystroke_buffer) {
+            Ok(command) => command,
+            Err(ParseErr::Incomplete) => return (self, None),
+            Err(ParseErr::Invalid) => {
+                let log_entry = format!(
+                    "Undefined command '{}'",
+                    keystrokes_to_string(&self.keystroke_buffer)
+                );
+                self.keystroke_buffer.clear();
+                return (self, Some((log_entry, Category::Undefined)));
+            }
+        };
+        self.keystroke_buffer.clear();
+        if count == 0 {
+            return (self, Some(("no action".to_owned(), Category::Undefined)));
+        }
+
+        let other_cursors = &mut self.other_cursors;
+        match action {
+            // Every cursor is moved on its own.  A cursor that can't move stays where it is.
+            Action::MoveCursor(direction) => {
+                for path in other_cursors.iter_mut() {
+                    path.clamp_to(tree.root());
+                    let _ = tree.move_path(path, count, direction);
+                }
+                tree.move_cursor(count, direction).log_message();
+                // Cursors which have moved onto the same node are merged
+                let primary_cursor = tree.cursor_path();
+                other_cursors.sort();
+                other_cursors.dedup();
+                other_cursors.retain(|path| path != primary_cursor);
+            }
+            // Yanking and deleting store the nodes from every cursor in one register
+            Action::Yank(register) | Action::Delete(register) => {
+                let mut paths = other_cursors.clone();
+                paths.push(tree.cursor_path().clone());
+                paths.sort();
+                let nodes = paths
+                    .iter()
+                    .flat_map(|path| tree.node_and_next_siblings(path, count))
+                    .collect();
+                if action == Action::Yank(register) {
+                    editor.registers.store(register, nodes);
+                } else {
+                    let result =
+                        tree.edit_every_cursor(other_cursors, |tree| tree.delete_cursor(count));
+                    if result.is_ok() {
+                        editor.registers.store(register, nodes);
+                    }
+                    result.log_message();
+                }
+            }
+            Action::PutBefore(register)
+            | Action::PutAfter(register)
+            | Action::PutChild(register) => {
+                let nodes = match editor.registers.get(register) {
+                    Some(nodes) => nodes,
+                    None => {
+                        return (
+                            self,
+                            Some((
+                                format!("register '{}' is empty", register),
+                                Category::Undefined,
+                            )),
+                        )
+                    }
+                };
+                tree.edit_every_cursor(other_cursors, |tree| match action {
+                    Action::PutBefore(_) => tree.put_next_to_cursor(count, nodes, Side::Prev),
+                    Action::PutAfter(_) => tree.put_next_to_cursor(count, nodes, Side::Next),
+                    _ => tree.put_child(count, nodes),
+                })
+                .log_message();
+            }
+            Action::Replace(c) => tree
+                .edit_every_cursor(other_cursors, |tree| tree.replace_cursor(count, c))
+                .log_message(),
+            Action::InsertChild(c) => tree
+                .edit_every_cursor(other_cursors, |tree| tree.insert_child(count, c))
+                .log_message(),
+            Action::InsertBefore(c) | Action::InsertAfter(c) => {
+                let side = match action {
+                    Action::InsertBefore(_) => Side::Prev,
+                    _ => Side::Next,
+                };
+                tree.edit_every_cursor(other_cursors, |tree| {
+                    tree.insert_next_to_cursor(count, c, side)
+                })
+                .log_message();
+            }
+            Action::Wrap(c) => tree
+                .edit_every_cursor(other_cursors, |tree| tree.wrap_cursor(count, c))
+                .log_message(),
+            Action::Raise => tree
+                .edit_every_cursor(other_cursors, |tree| tree.raise_cursor(count))
+                .log_message(),
+            Action::Increment | Action::Decrement => {
+                let delta = match action {
+                    Action::Increment => count as i64,
+                    _ => -(count as i64),
+                };
+                tree.edit_every_cursor(other_cursors, |tree| tree.increment_cursor(delta))
+                    .log_message();
+            }
+            // Undoing and redoing can't move the other cursors, so they are removed
+            Action::Undo | Action::Redo => {
+                match action {
+                    Action::Undo => tree.undo(count),
+                    _ => tree.redo(count),
+                }
+                .log_message();
+                return (
+                    Box::new(normal_mode::State::default()),
+                    Some((action.description(), action.category())),
+                );
+            }
+            _ => {
+                return (
+                    self,
+                    Some((
+                        format!("can't {} with multiple cursors", action.description()),
+                        Category::Undefined,
+                    )),
+                )
+            }
+        }
+        (self, Some((action.description(), action.category())))
+    }
+
+    fn keystroke_buffer(&self) -> Cow<'_, str> {
+        if self.keystroke_buffer.is_empty() {
+            Cow::from(format!("-- {} CURSORS --", self.other_cursors.len() + 1))
+        } else {
+            Cow::from(keystrokes_to_string(&self.keystroke_buffer))
+        }
+    }
+
+    fn other_cursors(&self) -> &[Path] {
+        &self.other_cursors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::similar_paths;
+    use crate::arena::Arena;
+    use crate::ast::json::add_value_to_arena;
+    use crate::core::Path;
+
+    use serde_json::json;
+
+    #[test]
+    fn similar() {
+        let arena = Arena::new();
+        let root = add_value_to_arena(
+            json!([
+                {"enabled": true, "name": "a"},
+                {"name": "enabled", "enabled": false},
+                [true, "enabled"],
+                {"name": "c"}
+            ]),
+            &arena,
+        );
+        let paths = |indices: &[&[usize]]| -> Vec<Path> {
+            indices.iter().map(|i| Path::from_vec(i.to_vec())).collect()
+        };
+        // Fields are matched by their keys, and their values by position
+        assert_eq!(
+            similar_paths(root, &Path::from_vec(vec![0, 0, 1])),
+            paths(&[&[0, 0, 1], &[1, 1, 1]])
+        );
+        assert_eq!(
+            similar_paths(root, &Path::from_vec(vec![1, 0])),
+            paths(&[&[0, 1], &[1, 0], &[3, 0]])
+        );
+        // Every element of an array matches
+        assert_eq!(
+            similar_paths(root, &Path::from_vec(vec![2])),
+            paths(&[&[0], &[1], &[2], &[3]])
+        );
+        assert_eq!(
+            similar_paths(root, &Path::from_vec(vec![2, 1])),
+            paths(&[&[2, 0], &[2, 1]])
+        );
+        assert_eq!(similar_paths(root, &Path::root()), paths(&[&[]]));
+    }
+}
@@ -1,32 +1,28 @@
 //! The code for 'normal-mode', similar to that of Vim
 
-use super::dag::{Insertable, LogMessage};
-use super::{keystroke_log::Category, state, Editor};
-use crate::ast::Ast;
+use super::dag::{EditResult, Insertable, LogMessage};
+use super::registers::UNNAMED;
+use super::window::{ScreenDirection, SplitDirection};
+use super::{
+    command_mode, insert_mode, keystroke_log::Category, multi_cursor, search, state, undo_tree,
+    visual_mode, Editor,
+};
+use crate::ast::{Ast, AstClass};
 use crate::config::KeyMap;
 use crate::core::{keystrokes_to_string, Direction, Side};
 
 use std::borrow::Cow;
-use std::io::prelude::*;
 use std::iter::Peekable;
 
 use tuikit::prelude::Key;
 
 /// The struct covering all the [`State`](state::State)s which correspond to Sapling being in
 /// normal mode.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct State {
     keystroke_buffer: Vec<Key>,
 }
 
-impl Default for State {
-    fn default() -> Self {
-        State {
-            keystroke_buffer: Vec::new(),
-        }
-    }
-}
-
 impl<'arena, Node: Ast<'arena>> state::State<'arena, Node> for State {
     // TODO: Fix some of the jank of this function
     fn transition(
@@ -39,7 +35,8 @@ impl<'arena, Node: Ast<'arena>> state::State<'arena, Node> for State {
     ) {
         self.keystroke_buffer.push(key);
 
-        let tree = &mut editor.tree;
+        let current_buffer = editor.current_buffer();
+        let tree = &mut editor.buffers[current_buffer].tree;
 
         let log_entry = match parse_command(&editor.config.keymap, &self.keystroke_buffer) {
             // If the command buffer is a valid and complete command, then we execute the resulting
@@ -53,42 +50,235 @@ impl<'arena, Node: Ast<'arena>> state::State<'arena, Node> for State {
                 match action {
                     // If the command was a 'quit', then immediately make a state transition to the
                     // 'Quitted' state.  It doesn't matter what the count is, because quitting is
-                    // idempotent
+                    // idempotent.  Quitting with unsaved changes is refused, and requires `:q!`.
                     Action::Quit => {
+                        // If there are several windows, only the current window is closed
+                        if editor.close_window() {
+                            self.keystroke_buffer.clear();
+                            return (self, Some(("close window".to_owned(), action.category())));
+                        }
+                        if let Some(message) = editor.unsaved_changes_message() {
+                            self.keystroke_buffer.clear();
+                            return (self, Some((message, action.category())));
+                        }
                         return (
                             Box::new(state::Quit),
                             Some((action.description(), action.category())),
                         );
                     }
                     Action::Write => {
-                        if let Some(path) = &editor.file_path {
-                            // If the editor was given a file-path, then write to it
-                            let mut file = std::fs::File::create(path).unwrap();
-                            let mut content = tree.to_text(&editor.format_style);
-                            // Force the file to finish with a newline.  BTW, <str>.chars().last()
-                            // is O(1), regardless of the length of the string.
-                            if content.chars().last() != Some('\n') {
-                                content.push('\n');
+                        self.keystroke_buffer.clear();
+                        let log_entry = match editor.write(None) {
+                            Ok(_) => (action.description(), action.category()),
+                            Err(e) => {
+                                log::warn!("{}", e);
+                                (e.to_string(), action.category())
                             }
-                            file.write_all(content.as_bytes()).unwrap();
-                        } else {
-                            // Otherwise, log a warning and do nothing
-                            log::warn!("No file to write to!");
+                        };
+                        return (self, Some(log_entry));
+                    }
+                    // Scrolling changes the view, not the tree
+                    Action::PageUp | Action::PageDown | Action::CenterCursor => {
+                        self.keystroke_buffer.clear();
+                        match action {
+                            Action::PageUp => editor.scroll_pages(-(count as isize)),
+                            Action::PageDown => editor.scroll_pages(count as isize),
+                            _ => editor.center_on_cursor(),
                         }
-                        // If we haven't returned yet, then clear the buffer
+                        return (self, Some((action.description(), action.category())));
+                    }
+                    // Moving between, splitting and closing windows changes the view, not the tree
+                    Action::NextWindow
+                    | Action::PrevWindow
+                    | Action::SplitWindow(_)
+                    | Action::CloseWindow
+                    | Action::OnlyWindow
+                    | Action::WindowInDirection(_) => {
                         self.keystroke_buffer.clear();
+                        let description = match action {
+                            Action::NextWindow => {
+                                editor.cycle_window(count as isize);
+                                action.description()
+                            }
+                            Action::PrevWindow => {
+                                editor.cycle_window(-(count as isize));
+                                action.description()
+                            }
+                            Action::SplitWindow(direction) if !editor.split_window(direction) => {
+                                "not enough room to split the window".to_owned()
+                            }
+                            Action::CloseWindow if !editor.close_window() => {
+                                "can't close the last window".to_owned()
+                            }
+                            Action::OnlyWindow => {
+                                editor.close_other_windows();
+                                action.description()
+                            }
+                            Action::WindowInDirection(direction) => {
+                                let mut moved = false;
+                                for _ in 0..count {
+                                    moved |= editor.go_to_neighbouring_window(direction);
+                                }
+                                if moved {
+                                    action.description()
+                                } else {
+                                    "no window in that direction".to_owned()
+                                }
+                            }
+                            _ => action.description(),
+                        };
+                        return (self, Some((description, action.category())));
+                    }
+                    // Yanking copies nodes into a register without changing the tree
+                    Action::Yank(register) => {
+                        self.keystroke_buffer.clear();
+                        let nodes = tree.cursor_and_next_siblings(count);
+                        editor.registers.store(register, nodes);
                         return (self, Some((action.description(), action.category())));
                     }
+                    // Opening the undo tree panel starts with the current snapshot selected
+                    Action::UndoTree => {
+                        self.keystroke_buffer.clear();
+                        return (
+                            Box::new(undo_tree::State::new(tree.history_index())),
+                            Some((action.description(), action.category())),
+                        );
+                    }
+                    // Typing `:` moves Sapling into command mode
+                    Action::CommandMode => {
+                        self.keystroke_buffer.clear();
+                        return (
+                            Box::new(command_mode::State::default()),
+                            Some((action.description(), action.category())),
+                        );
+                    }
+                    // Typing `/` or `?` opens the search prompt
+                    Action::Search(side) => {
+                        self.keystroke_buffer.clear();
+                        return (
+                            Box::new(search::State::new(side)),
+                            Some((action.description(), action.category())),
+                        );
+                    }
+                    // Repeating a search moves the cursor without editing the tree
+                    Action::RepeatSearch(side) => {
+                        self.keystroke_buffer.clear();
+                        let log_entry = search::repeat_search(editor, side, count);
+                        return (self, Some(log_entry));
+                    }
+                    // Editing text moves Sapling into insert mode, but only if the cursor has text
+                    // that can be edited
+                    Action::EditText => {
+                        self.keystroke_buffer.clear();
+                        let cursor = tree.cursor();
+                        return match cursor.text() {
+                            Some(text) => (
+                                Box::new(insert_mode::State::new(text)),
+                                Some((action.description(), action.category())),
+                            ),
+                            None => (
+                                self,
+                                Some((
+                                    format!("{} has no text to edit", cursor.display_name()),
+                                    Category::Undefined,
+                                )),
+                            ),
+                        };
+                    }
+                    // Selecting a range of siblings moves Sapling into visual mode, with the cursor
+                    // as the anchor of the selection
+                    Action::VisualMode => {
+                        self.keystroke_buffer.clear();
+                        return match tree.cursor_path().last() {
+                            Some(anchor) => (
+                                Box::new(visual_mode::State::new(anchor)),
+                                Some((action.description(), action.category())),
+                            ),
+                            None => (
+                                self,
+                                Some((
+                                    "the root has no siblings to select".to_owned(),
+                                    Category::Undefined,
+                                )),
+                            ),
+                        };
+                    }
+                    // Selecting similar nodes puts a cursor on each of them
+                    Action::SelectSimilar => {
+                        self.keystroke_buffer.clear();
+                        let cursor_path = tree.cursor_path();
+                        let other_cursors: Vec<_> =
+                            multi_cursor::similar_paths(tree.root(), cursor_path)
+                                .into_iter()
+                                .filter(|path| path != cursor_path)
+                                .collect();
+                        if other_cursors.is_empty() {
+                            return (
+                                self,
+                                Some(("no similar nodes".to_owned(), Category::Undefined)),
+                            );
+                        }
+                        let log_entry = format!("select {} similar nodes", other_cursors.len() + 1);
+                        return (
+                            Box::new(multi_cursor::State::new(other_cursors)),
+                            Some((log_entry, action.category())),
+                        );
+                    }
                     // Otherwise, we perform the action on the `Dag`.  This returns the
                     // `EditResult`, which is logged outside the `match`
                     Action::Undo => tree.undo(count),
                     Action::Redo => tree.redo(count),
+                    Action::OlderState => tree.undo_chronologically(count),
+                    Action::NewerState => tree.redo_chronologically(count),
                     Action::MoveCursor(direction) => tree.move_cursor(count, direction),
                     Action::Replace(c) => tree.replace_cursor(count, c),
                     Action::InsertChild(c) => tree.insert_child(count, c),
                     Action::InsertBefore(c) => tree.insert_next_to_cursor(count, c, Side::Prev),
                     Action::InsertAfter(c) => tree.insert_next_to_cursor(count, c, Side::Next),
-                    Action::Delete => tree.delete_cursor(count),
+                    Action::Wrap(c) => tree.wrap_cursor(count, c),
+                    // Raising, slurping or barfing the cursor `count` times moves it through
+                    // `count` levels, stopping at the first level that it can't move through
+                    Action::Raise => tree.raise_cursor(count),
+                    Action::MoveNode(side) => tree.move_node(count, side),
+                    Action::Slurp(side) => repeat_edit(count, || tree.slurp_cursor(side)),
+                    Action::Barf(side) => repeat_edit(count, || tree.barf_cursor(side)),
+                    // Deleted nodes are stored in a register, but only if the deletion succeeded
+                    Action::Delete(register) => {
+                        let deleted_nodes = tree.cursor_and_next_siblings(count);
+                        let result = tree.delete_cursor(count);
+                        if result.is_ok() {
+                            editor.registers.store(register, deleted_nodes);
+                        }
+                        result
+                    }
+                    Action::PutBefore(register)
+                    | Action::PutAfter(register)
+                    | Action::PutChild(register) => {
+                        let nodes = match editor.registers.get(register) {
+                            Some(nodes) => nodes,
+                            None => {
+                                self.keystroke_buffer.clear();
+                                return (
+                                    self,
+                                    Some((
+                                        format!("register '{}' is empty", register),
+                                        Category::Undefined,
+                                    )),
+                                );
+                            }
+                        };
+                        match action {
+                            Action::PutBefore(_) => {
+                                tree.put_next_to_cursor(count, nodes, Side::Prev)
+                            }
+                            Action::PutAfter(_) => {
+                                tree.put_next_to_cursor(count, nodes, Side::Next)
+                            }
+                            _ => tree.put_child(count, nodes),
+                        }
+                    }
+                    Action::Increment => tree.increment_cursor(count as i64),
+                    Action::Decrement => tree.increment_cursor(-(count as i64)),
                 }
                 .log_message();
                 (action.description(), action.category())
@@ -114,10 +304,14 @@ impl<'arena, Node: Ast<'arena>> state::State<'arena, Node> for State {
     fn keystroke_buffer(&self) -> Cow<'_, str> {
         Cow::from(keystrokes_to_string(&self.keystroke_buffer))
     }
+
+    fn is_idle(&self) -> bool {
+        self.keystroke_buffer.is_empty()
+    }
 }
 
-/// The possible keystroke typed by user without any parameters.  Each `KeyStroke` can be mapped to
-/// an individual [`char`].
+/// The possible keystroke typed by user without any parameters.  Each `CmdType` is bound to a
+/// sequence of [`Key`]s by the [`KeyMap`].
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum CmdType {
     /// Quit Sapling
@@ -132,8 +326,26 @@ pub enum CmdType {
     InsertBefore,
     /// Insert a new node after the cursor, expects an argument
     InsertAfter,
-    /// Delete the cursor
+    /// Wrap the cursor in a new node, expects an argument
+    Wrap,
+    /// Replace the cursor's parent with the cursor
+    Raise,
+    /// Move the cursor past its siblings on a given side
+    MoveNode(Side),
+    /// Move the cursor into its sibling on a given side
+    Slurp(Side),
+    /// Move the cursor out of its parent, to a given side of the parent
+    Barf(Side),
+    /// Delete the cursor, storing it in a register
     Delete,
+    /// Copy the cursor into a register
+    Yank,
+    /// Put the contents of a register before the cursor
+    PutBefore,
+    /// Put the contents of a register after the cursor
+    PutAfter,
+    /// Put the contents of a register as the last children of the cursor
+    PutChild,
     /// Move cursor in given direction.  The direction is part of the keystroke, since movements in
     /// all 4 directions are mapped to single characters.
     MoveCursor(Direction),
@@ -141,6 +353,47 @@ pub enum CmdType {
     Undo,
     /// Redo a change
     Redo,
+    /// Move to an older snapshot in the undo tree, regardless of branches
+    OlderState,
+    /// Move to a newer snapshot in the undo tree, regardless of branches
+    NewerState,
+    /// Open the undo tree panel
+    UndoTree,
+    /// Add to the number under the cursor
+    Increment,
+    /// Subtract from the number under the cursor
+    Decrement,
+    /// Enter insert mode to edit the text of the cursor
+    EditText,
+    /// Enter command mode
+    CommandMode,
+    /// Open the search prompt, to search in a given direction
+    Search(Side),
+    /// Repeat the last search, in the same direction ([`Side::Next`]) or the opposite direction
+    /// ([`Side::Prev`])
+    RepeatSearch(Side),
+    /// Enter visual mode to select a range of siblings
+    VisualMode,
+    /// Put a cursor on every node which is similar to the cursor
+    SelectSimilar,
+    /// Scroll the view up by a page
+    PageUp,
+    /// Scroll the view down by a page
+    PageDown,
+    /// Scroll the view so that the cursor is in the middle of the screen
+    CenterCursor,
+    /// Move to the next window
+    NextWindow,
+    /// Move to the previous window
+    PrevWindow,
+    /// Split the current window in two
+    SplitWindow(SplitDirection),
+    /// Close the current window
+    CloseWindow,
+    /// Close every window except the current one
+    OnlyWindow,
+    /// Move to the window next to the current one in a given direction
+    WindowInDirection(ScreenDirection),
 }
 
 impl CmdType {
@@ -153,15 +406,195 @@ impl CmdType {
             CmdType::InsertChild => "insert child",
             CmdType::InsertBefore => "insert before",
             CmdType::InsertAfter => "insert after",
+            CmdType::Wrap => "wrap",
+            CmdType::Raise => "raise",
+            CmdType::MoveNode(Side::Prev) => "move node back",
+            CmdType::MoveNode(Side::Next) => "move node forward",
+            CmdType::Slurp(Side::Prev) => "move into previous sibling",
+            CmdType::Slurp(Side::Next) => "move into next sibling",
+            CmdType::Barf(Side::Prev) => "move out before parent",
+            CmdType::Barf(Side::Next) => "move out after parent",
             CmdType::Delete => "delete",
-            CmdType::MoveCursor(Direction::Down) => "move to first child",
+            CmdType::Yank => "yank",
+            CmdType::PutBefore => "put before",
+            CmdType::PutAfter => "put after",
+            CmdType::PutChild => "put child",
+            CmdType::MoveCursor(Direction::Down) => "move to child",
             CmdType::MoveCursor(Direction::Up) => "move to parent",
             CmdType::MoveCursor(Direction::Prev) => "move to previous sibling",
             CmdType::MoveCursor(Direction::Next) => "move to next sibling",
+            CmdType::MoveCursor(Direction::LastChild) => "move to last child",
+            CmdType::MoveCursor(Direction::FirstSibling) => "move to first sibling",
+            CmdType::MoveCursor(Direction::LastSibling) => "move to last sibling",
+            CmdType::MoveCursor(Direction::Root) => "move to root",
+            CmdType::MoveCursor(Direction::PreorderNext) => "move to next node",
+            CmdType::MoveCursor(Direction::PreorderPrev) => "move to previous node",
+            CmdType::MoveCursor(Direction::NextLeaf) => "move to next leaf",
+            CmdType::MoveCursor(Direction::PrevLeaf) => "move to previous leaf",
             CmdType::Undo => "undo",
             CmdType::Redo => "redo",
+            CmdType::OlderState => "older state",
+            CmdType::NewerState => "newer state",
+            CmdType::UndoTree => "open undo tree",
+            CmdType::Increment => "increment",
+            CmdType::Decrement => "decrement",
+            CmdType::EditText => "edit text",
+            CmdType::CommandMode => "enter command mode",
+            CmdType::Search(Side::Next) => "search forward",
+            CmdType::Search(Side::Prev) => "search backward",
+            CmdType::RepeatSearch(Side::Next) => "repeat search",
+            CmdType::RepeatSearch(Side::Prev) => "repeat search reversed",
+            CmdType::VisualMode => "enter visual mode",
+            CmdType::SelectSimilar => "select similar nodes",
+            CmdType::PageUp => "page up",
+            CmdType::PageDown => "page down",
+            CmdType::CenterCursor => "centre on cursor",
+            CmdType::NextWindow => "next window",
+            CmdType::PrevWindow => "previous window",
+            CmdType::SplitWindow(SplitDirection::Horizontal) => "split window",
+            CmdType::SplitWindow(SplitDirection::Vertical) => "split window vertically",
+            CmdType::CloseWindow => "close window",
+            CmdType::OnlyWindow => "close other windows",
+            CmdType::WindowInDirection(ScreenDirection::Left) => "move to left window",
+            CmdType::WindowInDirection(ScreenDirection::Down) => "move to window below",
+            CmdType::WindowInDirection(ScreenDirection::Up) => "move to window above",
+            CmdType::WindowInDirection(ScreenDirection::Right) => "move to right window",
         }
     }
+
+    /// Returns the name used to refer to this `CmdType` in config files
+    pub fn name(&self) -> &'static str {
+        match self {
+            CmdType::Quit => "quit",
+            CmdType::Write => "write",
+            CmdType::Replace => "replace",
+            CmdType::InsertChild => "insert-child",
+            CmdType::InsertBefore => "insert-before",
+            CmdType::InsertAfter => "insert-after",
+            CmdType::Wrap => "wrap",
+            CmdType::Raise => "raise",
+            CmdType::MoveNode(Side::Prev) => "move-node-back",
+            CmdType::MoveNode(Side::Next) => "move-node-forward",
+            CmdType::Slurp(Side::Prev) => "slurp-into-prev",
+            CmdType::Slurp(Side::Next) => "slurp-into-next",
+            CmdType::Barf(Side::Prev) => "barf-before",
+            CmdType::Barf(Side::Next) => "barf-after",
+            CmdType::Delete => "delete",
+            CmdType::Yank => "yank",
+            CmdType::PutBefore => "put-before",
+            CmdType::PutAfter => "put-after",
+            CmdType::PutChild => "put-child",
+            CmdType::MoveCursor(Direction::Down) => "move-down",
+            CmdType::MoveCursor(Direction::Up) => "move-up",
+            CmdType::MoveCursor(Direction::Prev) => "move-prev",
+            CmdType::MoveCursor(Direction::Next) => "move-next",
+            CmdType::MoveCursor(Direction::LastChild) => "move-last-child",
+            CmdType::MoveCursor(Direction::FirstSibling) => "move-first-sibling",
+            CmdType::MoveCursor(Direction::LastSibling) => "move-last-sibling",
+            CmdType::MoveCursor(Direction::Root) => "move-root",
+            CmdType::MoveCursor(Direction::PreorderNext) => "move-preorder-next",
+            CmdType::MoveCursor(Direction::PreorderPrev) => "move-preorder-prev",
+            CmdType::MoveCursor(Direction::NextLeaf) => "move-next-leaf",
+            CmdType::MoveCursor(Direction::PrevLeaf) => "move-prev-leaf",
+            CmdType::Undo => "undo",
+            CmdType::Redo => "redo",
+            CmdType::OlderState => "older-state",
+            CmdType::NewerState => "newer-state",
+            CmdType::UndoTree => "undo-tree",
+            CmdType::Increment => "increment",
+            CmdType::Decrement => "decrement",
+            CmdType::EditText => "edit-text",
+            CmdType::CommandMode => "command-mode",
+            CmdType::Search(Side::Next) => "search-forward",
+            CmdType::Search(Side::Prev) => "search-backward",
+            CmdType::RepeatSearch(Side::Next) => "repeat-search",
+            CmdType::RepeatSearch(Side::Prev) => "repeat-search-reversed",
+            CmdType::VisualMode => "visual-mode",
+            CmdType::SelectSimilar => "select-similar",
+            CmdType::PageUp => "page-up",
+            CmdType::PageDown => "page-down",
+            CmdType::CenterCursor => "center-cursor",
+            CmdType::NextWindow => "next-window",
+            CmdType::PrevWindow => "prev-window",
+            CmdType::SplitWindow(SplitDirection::Horizontal) => "split-window",
+            CmdType::SplitWindow(SplitDirection::Vertical) => "vsplit-window",
+            CmdType::CloseWindow => "close-window",
+            CmdType::OnlyWindow => "only-window",
+            CmdType::WindowInDirection(ScreenDirection::Left) => "window-left",
+            CmdType::WindowInDirection(ScreenDirection::Down) => "window-down",
+            CmdType::WindowInDirection(ScreenDirection::Up) => "window-up",
+            CmdType::WindowInDirection(ScreenDirection::Right) => "window-right",
+        }
+    }
+
+    /// Returns the `CmdType` with a given [`name`](CmdType::name), or `None` if no such `CmdType`
+    /// exists
+    pub fn from_name(name: &str) -> Option<CmdType> {
+        CmdType::ALL.iter().copied().find(|cmd| cmd.name() == name)
+    }
+
+    /// Every possible `CmdType`
+    pub const ALL: &'static [CmdType] = &[
+        CmdType::Quit,
+        CmdType::Write,
+        CmdType::Replace,
+        CmdType::InsertChild,
+        CmdType::InsertBefore,
+        CmdType::InsertAfter,
+        CmdType::Wrap,
+        CmdType::Raise,
+        CmdType::MoveNode(Side::Prev),
+        CmdType::MoveNode(Side::Next),
+        CmdType::Slurp(Side::Prev),
+        CmdType::Slurp(Side::Next),
+        CmdType::Barf(Side::Prev),
+        CmdType::Barf(Side::Next),
+        CmdType::Delete,
+        CmdType::Yank,
+        CmdType::PutBefore,
+        CmdType::PutAfter,
+        CmdType::PutChild,
+        CmdType::MoveCursor(Direction::Down),
+        CmdType::MoveCursor(Direction::Up),
+        CmdType::MoveCursor(Direction::Prev),
+        CmdType::MoveCursor(Direction::Next),
+        CmdType::MoveCursor(Direction::LastChild),
+        CmdType::MoveCursor(Direction::FirstSibling),
+        CmdType::MoveCursor(Direction::LastSibling),
+        CmdType::MoveCursor(Direction::Root),
+        CmdType::MoveCursor(Direction::PreorderNext),
+        CmdType::MoveCursor(Direction::PreorderPrev),
+        CmdType::MoveCursor(Direction::NextLeaf),
+        CmdType::MoveCursor(Direction::PrevLeaf),
+        CmdType::Undo,
+        CmdType::Redo,
+        CmdType::OlderState,
+        CmdType::NewerState,
+        CmdType::UndoTree,
+        CmdType::Increment,
+        CmdType::Decrement,
+        CmdType::EditText,
+        CmdType::CommandMode,
+        CmdType::Search(Side::Next),
+        CmdType::Search(Side::Prev),
+        CmdType::RepeatSearch(Side::Next),
+        CmdType::RepeatSearch(Side::Prev),
+        CmdType::VisualMode,
+        CmdType::SelectSimilar,
+        CmdType::PageUp,
+        CmdType::PageDown,
+        CmdType::CenterCursor,
+        CmdType::NextWindow,
+        CmdType::PrevWindow,
+        CmdType::SplitWindow(SplitDirection::Horizontal),
+        CmdType::SplitWindow(SplitDirection::Vertical),
+        CmdType::CloseWindow,
+        CmdType::OnlyWindow,
+        CmdType::WindowInDirection(ScreenDirection::Left),
+        CmdType::WindowInDirection(ScreenDirection::Down),
+        CmdType::WindowInDirection(ScreenDirection::Up),
+        CmdType::WindowInDirection(ScreenDirection::Right),
+    ];
 }
 
 /// The [`Action`] generated by a single normal-mode 'command'.
@@ -175,15 +608,74 @@ pub enum Action {
     InsertBefore(Insertable),
     /// Insert a new node (given by some [`char`]) after the cursor
     InsertAfter(Insertable),
-    /// Remove the node under the cursor
-    Delete,
+    /// Wrap the cursor in a new node (given by some [`char`])
+    Wrap(Insertable),
+    /// Replace the cursor's parent with the cursor
+    Raise,
+    /// Move the cursor past the count's worth of its siblings on a given side
+    MoveNode(Side),
+    /// Move the cursor into its sibling on a given side
+    Slurp(Side),
+    /// Move the cursor out of its parent, to a given side of the parent
+    Barf(Side),
+    /// Remove the node under the cursor, storing it in the register with a given name
+    Delete(char),
+    /// Copy the node under the cursor into the register with a given name
+    Yank(char),
+    /// Put the contents of the register with a given name before the cursor
+    PutBefore(char),
+    /// Put the contents of the register with a given name after the cursor
+    PutAfter(char),
+    /// Put the contents of the register with a given name as the last children of the cursor
+    PutChild(char),
     /// Move the node in a given direction
     MoveCursor(Direction),
     /// Undo the last change
     Undo,
     /// Redo a change
     Redo,
-    /// Quit Sapling
+    /// Move back in time through the undo tree, regardless of branches
+    OlderState,
+    /// Move forward in time through the undo tree, regardless of branches
+    NewerState,
+    /// Open the undo tree panel
+    UndoTree,
+    /// Add the count to the number under the cursor
+    Increment,
+    /// Subtract the count from the number under the cursor
+    Decrement,
+    /// Start editing the text of the node under the cursor
+    EditText,
+    /// Start typing a command into the command line
+    CommandMode,
+    /// Start typing a pattern into the search prompt, to search in a given direction
+    Search(Side),
+    /// Move to the count's worth of matches of the last search, in the same direction as that
+    /// search ([`Side::Next`]) or the opposite direction ([`Side::Prev`])
+    RepeatSearch(Side),
+    /// Start selecting a range of siblings, starting from the cursor
+    VisualMode,
+    /// Put a cursor on every node which is similar to the cursor
+    SelectSimilar,
+    /// Scroll the view up by the count's worth of pages
+    PageUp,
+    /// Scroll the view down by the count's worth of pages
+    PageDown,
+    /// Scroll the view so that the cursor is in the middle of the screen
+    CenterCursor,
+    /// Move the count's worth of windows forward
+    NextWindow,
+    /// Move the count's worth of windows backward
+    PrevWindow,
+    /// Split the current window in two
+    SplitWindow(SplitDirection),
+    /// Close the current window
+    CloseWindow,
+    /// Close every window except the current one
+    OnlyWindow,
+    /// Move to the window next to the current one in a given direction
+    WindowInDirection(ScreenDirection),
+    /// Quit Sapling (or close the current window, if there are several)
     Quit,
     /// Write current buffer to disk
     Write,
@@ -198,13 +690,78 @@ impl Action {
             Action::InsertChild(c) => format!("insert '{}' as last child", c),
             Action::InsertBefore(c) => format!("insert '{}' before cursor", c),
             Action::InsertAfter(c) => format!("insert '{}' after cursor", c),
-            Action::Delete => "delete cursor".to_string(),
-            Action::MoveCursor(Direction::Down) => "move to first child".to_string(),
+            Action::Wrap(c) => format!("wrap cursor in '{}'", c),
+            Action::Raise => "raise cursor".to_string(),
+            Action::MoveNode(Side::Prev) => "move cursor back".to_string(),
+            Action::MoveNode(Side::Next) => "move cursor forward".to_string(),
+            Action::Slurp(Side::Prev) => "move cursor into previous sibling".to_string(),
+            Action::Slurp(Side::Next) => "move cursor into next sibling".to_string(),
+            Action::Barf(Side::Prev) => "move cursor out before parent".to_string(),
+            Action::Barf(Side::Next) => "move cursor out after parent".to_string(),
+            Action::Delete(register) => {
+                format!("delete cursor{}", register_description(" into", *register))
+            }
+            Action::Yank(register) => {
+                format!("yank cursor{}", register_description(" into", *register))
+            }
+            Action::PutBefore(register) => {
+                format!(
+                    "put{} before cursor",
+                    register_description(" from", *register)
+                )
+            }
+            Action::PutAfter(register) => {
+                format!(
+                    "put{} after cursor",
+                    register_description(" from", *register)
+                )
+            }
+            Action::PutChild(register) => {
+                format!(
+                    "put{} as last child",
+                    register_description(" from", *register)
+                )
+            }
+            Action::MoveCursor(Direction::Down) => "move to child".to_string(),
             Action::MoveCursor(Direction::Up) => "move to parent".to_string(),
             Action::MoveCursor(Direction::Prev) => "move to previous sibling".to_string(),
             Action::MoveCursor(Direction::Next) => "move to next sibling".to_string(),
+            Action::MoveCursor(Direction::LastChild) => "move to last child".to_string(),
+            Action::MoveCursor(Direction::FirstSibling) => "move to first sibling".to_string(),
+            Action::MoveCursor(Direction::LastSibling) => "move to last sibling".to_string(),
+            Action::MoveCursor(Direction::Root) => "move to root".to_string(),
+            Action::MoveCursor(Direction::PreorderNext) => "move to next node".to_string(),
+            Action::MoveCursor(Direction::PreorderPrev) => "move to previous node".to_string(),
+            Action::MoveCursor(Direction::NextLeaf) => "move to next leaf".to_string(),
+            Action::MoveCursor(Direction::PrevLeaf) => "move to previous leaf".to_string(),
             Action::Undo => "undo a change".to_string(),
             Action::Redo => "redo a change".to_string(),
+            Action::OlderState => "go to an older state".to_string(),
+            Action::NewerState => "go to a newer state".to_string(),
+            Action::UndoTree => "open undo tree".to_string(),
+            Action::Increment => "increment cursor".to_string(),
+            Action::Decrement => "decrement cursor".to_string(),
+            Action::EditText => "edit text".to_string(),
+            Action::CommandMode => "enter command mode".to_string(),
+            Action::Search(Side::Next) => "search forward".to_string(),
+            Action::Search(Side::Prev) => "search backward".to_string(),
+            Action::RepeatSearch(Side::Next) => "repeat search".to_string(),
+            Action::RepeatSearch(Side::Prev) => "repeat search reversed".to_string(),
+            Action::VisualMode => "enter visual mode".to_string(),
+            Action::SelectSimilar => "select similar nodes".to_string(),
+            Action::PageUp => "scroll up a page".to_string(),
+            Action::PageDown => "scroll down a page".to_string(),
+            Action::CenterCursor => "centre on cursor".to_string(),
+            Action::NextWindow => "go to next window".to_string(),
+            Action::PrevWindow => "go to previous window".to_string(),
+            Action::SplitWindow(SplitDirection::Horizontal) => "split window".to_string(),
+            Action::SplitWindow(SplitDirection::Vertical) => "split window vertically".to_string(),
+            Action::CloseWindow => "close window".to_string(),
+            Action::OnlyWindow => "close other windows".to_string(),
+            Action::WindowInDirection(ScreenDirection::Left) => "go to left window".to_string(),
+            Action::WindowInDirection(ScreenDirection::Down) => "go to window below".to_string(),
+            Action::WindowInDirection(ScreenDirection::Up) => "go to window above".to_string(),
+            Action::WindowInDirection(ScreenDirection::Right) => "go to right window".to_string(),
             Action::Quit => "quit Sapling".to_string(),
             Action::Write => "write to disk".to_string(),
         }
@@ -213,24 +770,77 @@ impl Action {
     /// Returns the [`Category`] of this `Action`
     pub fn category(&self) -> Category {
         match self {
-            Action::Replace(_) => Category::Replace,
-            Action::InsertChild(_) | Action::InsertBefore(_) | Action::InsertAfter(_) => {
-                Category::Insert
-            }
-            Action::Delete => Category::Delete,
-            Action::MoveCursor(_) => Category::Move,
-            Action::Undo | Action::Redo => Category::History,
+            Action::Replace(_)
+            | Action::Raise
+            | Action::MoveNode(_)
+            | Action::Slurp(_)
+            | Action::Barf(_)
+            | Action::Increment
+            | Action::Decrement => Category::Replace,
+            Action::InsertChild(_)
+            | Action::InsertBefore(_)
+            | Action::InsertAfter(_)
+            | Action::Wrap(_)
+            | Action::PutBefore(_)
+            | Action::PutAfter(_)
+            | Action::PutChild(_) => Category::Insert,
+            Action::Delete(_) => Category::Delete,
+            Action::Yank(_) => Category::Yank,
+            Action::EditText => Category::Insert,
+            Action::MoveCursor(_)
+            | Action::RepeatSearch(_)
+            | Action::SelectSimilar
+            | Action::PageUp
+            | Action::PageDown
+            | Action::CenterCursor
+            | Action::NextWindow
+            | Action::PrevWindow
+            | Action::SplitWindow(_)
+            | Action::CloseWindow
+            | Action::OnlyWindow
+            | Action::WindowInDirection(_) => Category::Move,
+            Action::Undo
+            | Action::Redo
+            | Action::OlderState
+            | Action::NewerState
+            | Action::UndoTree => Category::History,
             Action::Quit => Category::Quit,
+            Action::CommandMode | Action::Search(_) | Action::VisualMode => Category::Undefined,
             Action::Write => Category::IO,
         }
     }
 }
 
+/// Makes an `edit` up to `count` times, stopping at the first edit which fails
+fn repeat_edit<C: AstClass>(
+    count: usize,
+    mut edit: impl FnMut() -> EditResult<C>,
+) -> EditResult<C> {
+    let mut result = edit();
+    for _ in 1..count {
+        if result.is_err() {
+            break;
+        }
+        result = edit();
+    }
+    result
+}
+
+/// Returns the text used in an [`Action`]'s description to name its register, which is empty for
+/// the [`UNNAMED`] register
+pub(super) fn register_description(preposition: &str, register: char) -> String {
+    if register == UNNAMED {
+        String::new()
+    } else {
+        format!("{} register '{}'", preposition, register)
+    }
+}
+
 type ParseResult<T> = Result<T, ParseErr>;
 
 /// The possible ways a parsing operation could fail
 #[derive(Debug, Clone, Eq, PartialEq)]
-enum ParseErr {
+pub(super) enum ParseErr {
     Invalid,
     Incomplete,
 }
@@ -239,33 +849,65 @@ enum ParseErr {
 /// is a recursive descent parser, where there is a separate function for each syntactic element
 /// ([`parse_insertable`], [`parse_count`], etc.).
 ///
+/// Like in Vim, a command can name the register that it uses with `"<name>`, either before or after
+/// the count (e.g. `"a3x` or `3"ax`).  Commands which don't use a register ignore it.
+///
 /// Note that this parser will return as soon as a valid command is reached.  Therefore,
 /// `"q489flshb"` will be treated like `"q"`, and will return [`Action::Quit`] even though
 /// `"q489flshb"` is not technically valid.  However, the command buffer is parsed every time the
 /// user types a keystroke character, so the user would not be able to input `"q489flshb"` in one
 /// go because doing so would require them to first input every possible prefix of `"q489flshb"`,
 /// including `"q"`.
-fn parse_command(keymap: &KeyMap, keys: &[Key]) -> ParseResult<(usize, Action)> {
+pub(super) fn parse_command(keymap: &KeyMap, keys: &[Key]) -> ParseResult<(usize, Action)> {
     // Generate an iterator of keystrokes, which are treated similar to tokens by the parser.
     let mut key_iter = keys.iter().copied().peekable();
 
-    // Parse a count off the front of the command
+    // Parse a count and register name off the front of the command.  If counts are given both
+    // before and after the register, then they get multiplied together.
     let count = parse_count(&mut key_iter);
-    // The first non-count keystroke represents the command name.  No keystrokes is an incomplete
-    // command.
-    let first_key = key_iter.next().ok_or(ParseErr::Incomplete)?;
+    let register = parse_register(&mut key_iter)?;
+    let count = count * parse_count(&mut key_iter);
 
     Ok((
         count,
-        match keymap.get(&first_key).ok_or(ParseErr::Invalid)? {
+        match parse_cmd_type(keymap, &mut key_iter)? {
             CmdType::InsertChild => Action::InsertChild(parse_insertable(&mut key_iter)?),
             CmdType::InsertBefore => Action::InsertBefore(parse_insertable(&mut key_iter)?),
             CmdType::InsertAfter => Action::InsertAfter(parse_insertable(&mut key_iter)?),
-            CmdType::Delete => Action::Delete,
+            CmdType::Wrap => Action::Wrap(parse_insertable(&mut key_iter)?),
+            CmdType::Raise => Action::Raise,
+            CmdType::MoveNode(side) => Action::MoveNode(side),
+            CmdType::Slurp(side) => Action::Slurp(side),
+            CmdType::Barf(side) => Action::Barf(side),
+            CmdType::Delete => Action::Delete(register),
+            CmdType::Yank => Action::Yank(register),
+            CmdType::PutBefore => Action::PutBefore(register),
+            CmdType::PutAfter => Action::PutAfter(register),
+            CmdType::PutChild => Action::PutChild(register),
             CmdType::Replace => Action::Replace(parse_insertable(&mut key_iter)?),
-            CmdType::MoveCursor(direction) => Action::MoveCursor(*direction),
+            CmdType::MoveCursor(direction) => Action::MoveCursor(direction),
             CmdType::Undo => Action::Undo,
             CmdType::Redo => Action::Redo,
+            CmdType::OlderState => Action::OlderState,
+            CmdType::NewerState => Action::NewerState,
+            CmdType::UndoTree => Action::UndoTree,
+            CmdType::Increment => Action::Increment,
+            CmdType::Decrement => Action::Decrement,
+            CmdType::EditText => Action::EditText,
+            CmdType::CommandMode => Action::CommandMode,
+            CmdType::Search(side) => Action::Search(side),
+            CmdType::RepeatSearch(side) => Action::RepeatSearch(side),
+            CmdType::VisualMode => Action::VisualMode,
+            CmdType::SelectSimilar => Action::SelectSimilar,
+            CmdType::PageUp => Action::PageUp,
+            CmdType::PageDown => Action::PageDown,
+            CmdType::CenterCursor => Action::CenterCursor,
+            CmdType::NextWindow => Action::NextWindow,
+            CmdType::PrevWindow => Action::PrevWindow,
+            CmdType::SplitWindow(direction) => Action::SplitWindow(direction),
+            CmdType::CloseWindow => Action::CloseWindow,
+            CmdType::OnlyWindow => Action::OnlyWindow,
+            CmdType::WindowInDirection(direction) => Action::WindowInDirection(direction),
             // "q" quits Sapling
             CmdType::Quit => Action::Quit,
             CmdType::Write => Action::Write,
@@ -273,6 +915,44 @@ fn parse_command(keymap: &KeyMap, keys: &[Key]) -> ParseResult<(usize, Action)>
     ))
 }
 
+/// Attempt to parse the [`Key`]s bound to a [`CmdType`] off the front of a sequence of
+/// [`Key`]strokes.  Keys are consumed until they match a binding in the [`KeyMap`] exactly, so if
+/// one binding is a prefix of another (e.g. `g` and `gg`) then the shorter binding always wins.
+fn parse_cmd_type(
+    keymap: &KeyMap,
+    keystroke_char_iter: &mut Peekable<impl Iterator<Item = Key>>,
+) -> ParseResult<CmdType> {
+    let mut cmd_keys = Vec::new();
+    loop {
+        // Running out of keystrokes whilst the keys are still a prefix of some binding means that
+        // the command is incomplete
+        cmd_keys.push(keystroke_char_iter.next().ok_or(ParseErr::Incomplete)?);
+        if let Some(cmd_type) = keymap.get(&cmd_keys) {
+            return Ok(*cmd_type);
+        }
+        if !keymap.keys().any(|binding| binding.starts_with(&cmd_keys)) {
+            return Err(ParseErr::Invalid);
+        }
+    }
+}
+
+/// Attempt to parse a register name (i.e. `"` followed by any character) off the front of a
+/// sequence of [`Key`]strokes.  If there is no register name, this returns the [`UNNAMED`]
+/// register.
+fn parse_register(
+    keystroke_char_iter: &mut Peekable<impl Iterator<Item = Key>>,
+) -> ParseResult<char> {
+    if keystroke_char_iter.peek() != Some(&Key::Char(UNNAMED)) {
+        return Ok(UNNAMED);
+    }
+    keystroke_char_iter.next();
+    match keystroke_char_iter.next() {
+        Some(Key::Char(c)) => Ok(c),
+        Some(_) => Err(ParseErr::Invalid),
+        None => Err(ParseErr::Incomplete),
+    }
+}
+
 /// Attempt to parse a sequence of [`Key`]strokes into an [`Insertable`].
 ///
 /// Currently an [`Insertable`] only has one form ([`Insertable::CountedNode`]), and so this is a
@@ -324,30 +1004,62 @@ fn parse_count(keystroke_char_iter: &mut Peekable<impl Iterator<Item = Key>>) ->
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_command, Action, Insertable, ParseErr};
+    use super::{parse_command, Action, CmdType, Insertable, ParseErr, UNNAMED};
     use crate::config::default_keymap;
-    use crate::core::Direction;
+    use crate::core::{Direction, Side};
+    use crate::editor::window::SplitDirection;
     use tuikit::prelude::Key;
 
     fn to_char_keys(string: &str) -> Vec<Key> {
-        string.chars().map(|c| Key::Char(c)).collect::<Vec<_>>()
+        string.chars().map(Key::Char).collect::<Vec<_>>()
     }
 
     #[test]
     fn parse_single_cmd_valid() {
         let keymap = default_keymap();
         for (keystrokes, expected_effect) in &[
-            ("x", Action::Delete),
+            ("x", Action::Delete(UNNAMED)),
+            ("y", Action::Yank(UNNAMED)),
+            ("p", Action::PutAfter(UNNAMED)),
+            ("P", Action::PutBefore(UNNAMED)),
+            ("gp", Action::PutChild(UNNAMED)),
+            ("\"ax", Action::Delete('a')),
+            ("\"Zy", Action::Yank('Z')),
+            ("\"\"p", Action::PutAfter(UNNAMED)),
+            ("\"xgp", Action::PutChild('x')),
+            ("\"aj", Action::MoveCursor(Direction::Next)),
             ("h", Action::MoveCursor(Direction::Prev)),
             ("j", Action::MoveCursor(Direction::Next)),
             ("k", Action::MoveCursor(Direction::Prev)),
             ("l", Action::MoveCursor(Direction::Next)),
-            ("pajlbsi", Action::MoveCursor(Direction::Up)),
+            ("-ajlbsi", Action::MoveCursor(Direction::Up)),
+            ("c", Action::MoveCursor(Direction::Down)),
+            ("L", Action::MoveCursor(Direction::LastChild)),
+            ("^", Action::MoveCursor(Direction::FirstSibling)),
+            ("$", Action::MoveCursor(Direction::LastSibling)),
+            ("gg", Action::MoveCursor(Direction::Root)),
+            ("]", Action::MoveCursor(Direction::PreorderNext)),
+            ("[", Action::MoveCursor(Direction::PreorderPrev)),
+            ("}", Action::MoveCursor(Direction::NextLeaf)),
+            ("{", Action::MoveCursor(Direction::PrevLeaf)),
             ("ra", Action::Replace(Insertable::CountedNode(1, 'a'))),
             ("rg", Action::Replace(Insertable::CountedNode(1, 'g'))),
             ("oX", Action::InsertChild(Insertable::CountedNode(1, 'X'))),
             ("oP", Action::InsertChild(Insertable::CountedNode(1, 'P'))),
             ("a3t", Action::InsertAfter(Insertable::CountedNode(3, 't'))),
+            ("Wa", Action::Wrap(Insertable::CountedNode(1, 'a'))),
+            ("v", Action::VisualMode),
+            ("/", Action::Search(Side::Next)),
+            ("?", Action::Search(Side::Prev)),
+            ("n", Action::RepeatSearch(Side::Next)),
+            ("N", Action::RepeatSearch(Side::Prev)),
+            ("gr", Action::Raise),
+            ("J", Action::MoveNode(Side::Next)),
+            ("K", Action::MoveNode(Side::Prev)),
+            (">", Action::Slurp(Side::Prev)),
+            ("g>", Action::Slurp(Side::Next)),
+            ("<", Action::Barf(Side::Next)),
+            ("g<", Action::Barf(Side::Prev)),
             ("an", Action::InsertAfter(Insertable::CountedNode(1, 'n'))),
             ("a1n", Action::InsertAfter(Insertable::CountedNode(1, 'n'))),
             ("i0X", Action::InsertBefore(Insertable::CountedNode(0, 'X'))),
@@ -357,10 +1069,11 @@ mod tests {
                 Action::InsertAfter(Insertable::CountedNode(15, 'x')),
             ),
             ("q", Action::Quit),
+            ("zz", Action::CenterCursor),
         ] {
             assert_eq!(
                 parse_command(&keymap, &to_char_keys(keystrokes)),
-                Ok((1, expected_effect.clone()))
+                Ok((1, *expected_effect))
             );
         }
     }
@@ -369,7 +1082,10 @@ mod tests {
     fn parse_counted_command() {
         let keymap = default_keymap();
         for (keystrokes, exp_count, exp_action) in &[
-            ("1x", 1, Action::Delete),
+            ("1x", 1, Action::Delete(UNNAMED)),
+            ("\"a3y", 3, Action::Yank('a')),
+            ("3\"ay", 3, Action::Yank('a')),
+            ("2\"b3p", 6, Action::PutAfter('b')),
             ("0ra", 0, Action::Replace(Insertable::CountedNode(1, 'a'))),
             (
                 "12o5p",
@@ -379,7 +1095,7 @@ mod tests {
         ] {
             assert_eq!(
                 parse_command(&keymap, &to_char_keys(keystrokes)),
-                Ok((*exp_count, exp_action.clone()))
+                Ok((*exp_count, *exp_action))
             );
         }
     }
@@ -387,7 +1103,7 @@ mod tests {
     #[test]
     fn parse_keystroke_invalid() {
         let keymap = default_keymap();
-        for keystroke in &["d", "Pxx", "Qsx", "t", "Y", "X", "\""] {
+        for keystroke in &["d", "gx", "Qsx", "t", "Y", "X", "\"aQ", "3\"a2g3"] {
             println!("Testing {}", keystroke);
             assert_eq!(
                 parse_command(&keymap, &to_char_keys(keystroke)),
@@ -400,7 +1116,8 @@ mod tests {
     fn parse_keystroke_incomplete() {
         let keymap = default_keymap();
         for keystroke in &[
-            "", "r", "o", "a", "i", "o3", "i34", "3", "1o", "0o3", "41523",
+            "", "r", "o", "a", "i", "o3", "i34", "3", "1o", "0o3", "41523", "g", "\"", "3\"",
+            "\"a", "\"a4", "\"bg",
         ] {
             println!("Testing {}", keystroke);
             assert_eq!(
@@ -409,4 +1126,46 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn parse_multi_key_bindings() {
+        let mut keymap = default_keymap();
+        keymap.insert(to_char_keys("gp"), CmdType::MoveCursor(Direction::Up));
+        keymap.insert(to_char_keys("dd"), CmdType::Delete);
+        keymap.insert(vec![Key::Ctrl('r')], CmdType::Redo);
+        keymap.insert(vec![Key::Char('Z'), Key::PageDown], CmdType::Undo);
+
+        let parse = |keys: Vec<Key>| parse_command(&keymap, &keys);
+        assert_eq!(
+            parse(to_char_keys("gp")),
+            Ok((1, Action::MoveCursor(Direction::Up)))
+        );
+        assert_eq!(parse(to_char_keys("3dd")), Ok((3, Action::Delete(UNNAMED))));
+        assert_eq!(parse(vec![Key::Ctrl('r')]), Ok((1, Action::Redo)));
+        assert_eq!(
+            parse(vec![Key::Char('Z'), Key::PageDown]),
+            Ok((1, Action::Undo))
+        );
+        assert_eq!(
+            parse(vec![Key::Ctrl('w'), Key::Char('v')]),
+            Ok((1, Action::SplitWindow(SplitDirection::Vertical)))
+        );
+        // Prefixes of bindings are incomplete
+        assert_eq!(parse(vec![Key::Ctrl('w')]), Err(ParseErr::Incomplete));
+        assert_eq!(parse(to_char_keys("g")), Err(ParseErr::Incomplete));
+        assert_eq!(parse(to_char_keys("4d")), Err(ParseErr::Incomplete));
+        assert_eq!(parse(to_char_keys("Z")), Err(ParseErr::Incomplete));
+        // Keys which diverge from every binding are invalid
+        assert_eq!(parse(to_char_keys("gx")), Err(ParseErr::Invalid));
+        assert_eq!(parse(to_char_keys("ZZ")), Err(ParseErr::Invalid));
+        assert_eq!(parse(vec![Key::Ctrl('q')]), Err(ParseErr::Invalid));
+    }
+
+    #[test]
+    fn cmd_type_names() {
+        for cmd_type in CmdType::ALL {
+            assert_eq!(CmdType::from_name(cmd_type.name()), Some(*cmd_type));
+        }
+        assert_eq!(CmdType::from_name("move to parent"), None);
+    }
 }
@@ -0,0 +1,80 @@
+//! Vim-like registers, which store subtrees that have been yanked or deleted so that they can be
+//! put back into the tree.
+
+use std::collections::HashMap;
+
+/// The name of the register used when the user doesn't specify one
+pub const UNNAMED: char = '"';
+
+/// A set of named registers, each of which holds a sequence of nodes.  Because nodes are
+/// immutable and never deallocated whilst their arena exists, the registers can just store
+/// references to the nodes (`T = &'arena Node`) rather than copying the subtrees.
+#[derive(Debug, Clone)]
+pub struct Registers<T> {
+    contents: HashMap<char, Vec<T>>,
+}
+
+impl<T: Clone> Registers<T> {
+    /// Creates a set of registers which are all empty
+    pub fn new() -> Self {
+        Registers {
+            contents: HashMap::new(),
+        }
+    }
+
+    /// Returns the nodes stored in a given register, or `None` if that register is empty
+    pub fn get(&self, name: char) -> Option<&[T]> {
+        self.contents.get(&name).map(Vec::as_slice)
+    }
+
+    /// Stores some nodes in a given register.  Like in Vim, the nodes are also stored in the
+    /// [`UNNAMED`] register so that they can be put without naming the register again.
+    pub fn store(&mut self, name: char, nodes: Vec<T>) {
+        if name != UNNAMED {
+            self.contents.insert(UNNAMED, nodes.clone());
+        }
+        self.contents.insert(name, nodes);
+    }
+
+    /// Creates a new set of registers by applying a function to every node in these registers.
+    /// This is used to move the registers' contents to a new arena.
+    pub fn map<U>(&self, mut f: impl FnMut(&T) -> U) -> Registers<U> {
+        Registers {
+            contents: self
+                .contents
+                .iter()
+                .map(|(name, nodes)| (*name, nodes.iter().map(&mut f).collect()))
+                .collect(),
+        }
+    }
+}
+
+impl<T: Clone> Default for Registers<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Registers, UNNAMED};
+
+    #[test]
+    fn store_and_get() {
+        let (a, b, c) = (1, 2, 3);
+        let mut registers: Registers<&i32> = Registers::new();
+        assert_eq!(registers.get(UNNAMED), None);
+        // Storing in the unnamed register doesn't affect any others
+        registers.store(UNNAMED, vec![&a]);
+        assert_eq!(registers.get(UNNAMED), Some(&[&1][..]));
+        assert_eq!(registers.get('x'), None);
+        // Storing in a named register also overwrites the unnamed register
+        registers.store('x', vec![&b, &c]);
+        assert_eq!(registers.get('x'), Some(&[&2, &3][..]));
+        assert_eq!(registers.get(UNNAMED), Some(&[&2, &3][..]));
+        // Mapping the registers keeps their names
+        let mapped = registers.map(|n| **n * 10);
+        assert_eq!(mapped.get('x'), Some(&[20, 30][..]));
+        assert_eq!(mapped.get('y'), None);
+    }
+}
@@ -0,0 +1,523 @@
+//! The code for searching the tree, both for the search prompt (entered by typing `/` or `?`) and
+//! for the queries which it parses
+
+use super::{keystroke_log::Category, normal_mode, state, Editor};
+use crate::ast::{Ast, AstClass};
+use crate::core::{Path, PathQuery, PathQueryErr, Side};
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use tuikit::prelude::Key;
+
+/// A search typed into the search prompt, which is kept so that it can be repeated (with `n` and
+/// `N`) and its matches highlighted
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Search {
+    /// The pattern that was typed, which is parsed into a [`Query`]
+    pub pattern: String,
+    /// The direction that the search was made in (`/` searches forwards, `?` backwards)
+    pub side: Side,
+}
+
+/// The nodes which a search pattern matches.  A pattern is either some text, which matches any
+/// node whose text (e.g. a JSON string, including the keys of objects) contains it, `:` followed
+/// by the name or key of a class (e.g. `:null` or `:n`), which matches every node of that class, or
+/// a [`PathQuery`] starting with `$` (e.g. `$.services[*].timeout`).  A class can be written as
+/// `:empty <class>` to only match nodes without children (e.g. `:empty array`).  Text which starts
+/// with `:` or `$` can be searched for by putting `\` before it.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Query<C: AstClass> {
+    /// Matches nodes whose text contains a string
+    Text(String),
+    /// Matches nodes of a given class, which have no children if `is_empty` is `true`
+    Class {
+        /// The class of the matched nodes
+        class: C,
+        /// If `true`, only nodes without children are matched
+        is_empty: bool,
+    },
+    /// Matches the nodes selected by a [`PathQuery`]
+    Path(PathQuery),
+}
+
+impl<C: AstClass> Query<C> {
+    /// Parses a search pattern into a `Query`
+    pub fn parse(pattern: &str) -> Result<Self, QueryErr> {
+        if pattern.is_empty() {
+            return Err(QueryErr::Empty);
+        }
+        if pattern.starts_with('$') {
+            return PathQuery::parse(pattern)
+                .map(Query::Path)
+                .map_err(QueryErr::InvalidPathQuery);
+        }
+        let class_name = match pattern.strip_prefix(':') {
+            Some(class_name) => class_name.trim(),
+            None => {
+                let text = pattern.strip_prefix('\\').unwrap_or(pattern);
+                return Ok(Query::Text(text.to_owned()));
+            }
+        };
+        let (class_name, is_empty) = match class_name.strip_prefix("empty ") {
+            Some(class_name) => (class_name.trim_start(), true),
+            None => (class_name, false),
+        };
+        let mut chars = class_name.chars();
+        let class = match (chars.next(), chars.next()) {
+            (Some(c), None) => C::from_char(c),
+            _ => C::from_name(class_name),
+        }
+        .ok_or_else(|| QueryErr::UnknownClass(class_name.to_owned()))?;
+        Ok(Query::Class { class, is_empty })
+    }
+
+    /// Returns whether or not this `Query` matches `node`, or `None` if that depends on where
+    /// the node is in the tree (as it does for a [`PathQuery`])
+    pub fn matches_node<'arena, Node: Ast<'arena, Class = C> + 'arena>(
+        &self,
+        node: &Node,
+    ) -> Option<bool> {
+        match self {
+            Query::Text(text) => Some(node.text().is_some_and(|t| t.contains(text.as_str()))),
+            Query::Class { class, is_empty } => {
+                Some(node.class() == Some(*class) && (!is_empty || node.children().is_empty()))
+            }
+            Query::Path(_) => None,
+        }
+    }
+
+    /// Returns the paths of every node in the tree under `root` which this `Query` matches, in the
+    /// order that they appear in the tree
+    pub fn matching_paths<'arena, Node: Ast<'arena, Class = C> + 'arena>(
+        &self,
+        root: &'arena Node,
+    ) -> Vec<Path> {
+        match self {
+            Query::Path(query) => query.evaluate(root, &Path::root()),
+            _ => paths_where(root, |node| self.matches_node(node) == Some(true)),
+        }
+    }
+}
+
+/// Returns the paths of every node in the tree under `root` which satisfies `predicate`, in the
+/// order that they appear in the tree
+fn paths_where<'arena, Node: Ast<'arena>>(
+    root: &'arena Node,
+    predicate: impl Fn(&'arena Node) -> bool,
+) -> Vec<Path> {
+    let mut paths = Vec::new();
+    let mut path = Path::root();
+    loop {
+        if predicate(path.cursor(root)) {
+            paths.push(path.clone());
+        }
+        if !path.preorder_next(root) {
+            return paths;
+        }
+    }
+}
+
+/// The parsed [`Query`] of a search pattern, and the nodes it matches in one tree if it is a
+/// [`PathQuery`]
+#[derive(Debug, Clone)]
+pub struct Matches<C: AstClass> {
+    /// The parsed search pattern
+    pub query: Rc<Query<C>>,
+    /// The paths to the nodes that the query matches, in tree order.  This is only filled in for
+    /// a [`PathQuery`], because other queries don't depend on where a node is in the tree, so
+    /// they can be matched against each node as it is rendered.
+    pub paths: Rc<[Path]>,
+}
+
+/// A cache of the [`Matches`] of the last search, which are kept between frames so that the
+/// pattern doesn't have to be parsed (nor the tree searched) every time the tree is rendered
+#[derive(Debug)]
+pub struct MatchCache<'arena, Node: Ast<'arena>> {
+    cached: RefCell<Option<CachedMatches<'arena, Node>>>,
+}
+
+/// The contents of a [`MatchCache`]
+#[derive(Debug)]
+struct CachedMatches<'arena, Node: Ast<'arena>> {
+    /// The root of the tree that was searched
+    root: &'arena Node,
+    /// The pattern that was searched for
+    pattern: String,
+    /// The `Matches` of the pattern in that tree
+    matches: Matches<Node::Class>,
+}
+
+impl<'arena, Node: Ast<'arena>> MatchCache<'arena, Node> {
+    /// Creates an empty `MatchCache`
+    pub fn new() -> Self {
+        MatchCache {
+            cached: RefCell::new(None),
+        }
+    }
+
+    /// Returns the [`Matches`] of a `pattern` in the tree under `root`, or `None` if the pattern
+    /// isn't a valid [`Query`]
+    pub fn matches(&self, root: &'arena Node, pattern: &str) -> Option<Matches<Node::Class>> {
+        if let Some(cached) = &*self.cached.borrow() {
+            if std::ptr::eq(cached.root, root) && cached.pattern == pattern {
+                return Some(cached.matches.clone());
+            }
+        }
+        let query = Query::parse(pattern).ok()?;
+        let paths = match &query {
+            Query::Path(_) => query.matching_paths(root).into(),
+            _ => Rc::from([]),
+        };
+        let matches = Matches {
+            query: Rc::new(query),
+            paths,
+        };
+        *self.cached.borrow_mut() = Some(CachedMatches {
+            root,
+            pattern: pattern.to_owned(),
+            matches: matches.clone(),
+        });
+        Some(matches)
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> Default for MatchCache<'arena, Node> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The possible ways that parsing a [`Query`] could fail
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum QueryErr {
+    /// The pattern was empty, and there is no previous search to repeat
+    Empty,
+    /// The pattern names a class which doesn't exist
+    UnknownClass(String),
+    /// The pattern is an invalid [`PathQuery`]
+    InvalidPathQuery(PathQueryErr),
+}
+
+impl std::fmt::Display for QueryErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryErr::Empty => write!(f, "No previous search"),
+            QueryErr::UnknownClass(name) => write!(f, "Not a node type: '{}'", name),
+            QueryErr::InvalidPathQuery(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Finds the `count`th node matching `query`, starting from the node at `start` and searching in
+/// the order that the nodes appear in the text (backwards if `side` is [`Side::Prev`]).  The
+/// search wraps around the end of the tree, so every node is searched.  Returns the path to the
+/// match and `true` if the search wrapped around, or `None` if no node matches.
+pub fn find_match<'arena, Node: Ast<'arena>>(
+    root: &'arena Node,
+    query: &Query<Node::Class>,
+    start: &Path,
+    side: Side,
+    count: usize,
+) -> Option<(Path, bool)> {
+    let matches = query.matching_paths(root);
+    if matches.is_empty() {
+        return None;
+    }
+    // Like in Vim, a count of 0 moves to the next match
+    let count = count.max(1) as isize;
+    let len = matches.len() as isize;
+    // The paths are in tree order, so the matches before `start` can be found by binary search
+    let index = match side {
+        Side::Next => matches.partition_point(|path| path <= start) as isize + count - 1,
+        Side::Prev => matches.partition_point(|path| path < start) as isize - count,
+    };
+    let has_wrapped = index < 0 || index >= len;
+    Some((matches[index.rem_euclid(len) as usize].clone(), has_wrapped))
+}
+
+/// Searches for a `pattern` in the direction of `side`, moving the cursor of the current buffer to
+/// the first match.  If the pattern is valid, it becomes the [`Editor`]'s last search.  Returns a
+/// log entry describing what happened.
+pub fn search<'arena, Node: Ast<'arena>>(
+    editor: &mut Editor<'arena, Node>,
+    pattern: String,
+    side: Side,
+) -> (String, Category) {
+    match Query::<Node::Class>::parse(&pattern) {
+        Ok(_) => {
+            editor.last_search = Some(Search { pattern, side });
+            repeat_search(editor, Side::Next, 1)
+        }
+        Err(e) => (e.to_string(), Category::Undefined),
+    }
+}
+
+/// Moves the cursor of the current buffer to the `count`th match of the [`Editor`]'s last search,
+/// searching in `side` relative to the direction of that search.  Returns a log entry describing
+/// what happened.
+pub fn repeat_search<'arena, Node: Ast<'arena>>(
+    editor: &mut Editor<'arena, Node>,
+    side: Side,
+    count: usize,
+) -> (String, Category) {
+    let search = match &editor.last_search {
+        Some(search) => search.clone(),
+        None => return (QueryErr::Empty.to_string(), Category::Undefined),
+    };
+    let query = match Query::parse(&search.pattern) {
+        Ok(query) => query,
+        Err(e) => return (e.to_string(), Category::Undefined),
+    };
+    // `N` searches in the opposite direction to the original search
+    let side = match (search.side, side) {
+        (Side::Next, side) => side,
+        (Side::Prev, Side::Next) => Side::Prev,
+        (Side::Prev, Side::Prev) => Side::Next,
+    };
+    let current_buffer = editor.current_buffer();
+    let tree = &mut editor.buffers[current_buffer].tree;
+    match find_match(tree.root(), &query, tree.cursor_path(), side, count) {
+        Some((path, has_wrapped)) => {
+            tree.set_cursor_path(path);
+            let description = match (has_wrapped, side) {
+                (false, _) => format!("search for '{}'", search.pattern),
+                (true, Side::Next) => "search hit the end, continuing at the start".to_owned(),
+                (true, Side::Prev) => "search hit the start, continuing at the end".to_owned(),
+            };
+            (description, Category::Move)
+        }
+        None => (
+            format!("pattern not found: '{}'", search.pattern),
+            Category::Undefined,
+        ),
+    }
+}
+
+/// The [`State`](state::State) which Sapling is in whilst the user is typing a search pattern
+/// into the search prompt.  Pressing `<Enter>` moves the cursor to the first match and returns to
+/// normal mode.
+#[derive(Debug, Clone)]
+pub struct State {
+    /// The pattern typed so far (not including the leading `/` or `?`)
+    pattern: String,
+    /// The direction to search in
+    side: Side,
+    /// The character that started the search prompt
+    prompt: char,
+}
+
+impl State {
+    /// Creates a new search prompt `State` which searches in the direction of `side`
+    pub fn new(side: Side) -> Self {
+        State {
+            pattern: String::new(),
+            side,
+            prompt: match side {
+                Side::Prev => '?',
+                Side::Next => '/',
+            },
+        }
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> state::State<'arena, Node> for State {
+    fn transition(
+        mut self: Box<Self>,
+        key: Key,
+        editor: &mut Editor<'arena, Node>,
+    ) -> (
+        Box<dyn state::State<'arena, Node>>,
+        Option<(String, Category)>,
+    ) {
+        match key {
+            Key::Char(c) => {
+                self.pattern.push(c);
+                (self, None)
+            }
+            // Deleting from an empty prompt leaves the prompt, like in Vim
+            Key::Backspace if self.pattern.is_empty() => (
+                Box::new(normal_mode::State::default()),
+                Some(("leave search".to_owned(), Category::Undefined)),
+            ),
+            Key::Backspace => {
+                self.pattern.pop();
+                (self, None)
+            }
+            Key::ESC => (
+                Box::new(normal_mode::State::default()),
+                Some(("leave search".to_owned(), Category::Undefined)),
+            ),
+            Key::Enter => {
+                // An empty pattern repeats the last search in the new direction
+                let pattern = if self.pattern.is_empty() {
+                    editor.last_search.as_ref().map(|s| s.pattern.clone())
+                } else {
+                    Some(self.pattern.clone())
+                };
+                let log_entry = match pattern {
+                    Some(pattern) => search(editor, pattern, self.side),
+                    None => (QueryErr::Empty.to_string(), Category::Undefined),
+                };
+                (Box::new(normal_mode::State::default()), Some(log_entry))
+            }
+            _ => (self, None),
+        }
+    }
+
+    fn keystroke_buffer(&self) -> Cow<'_, str> {
+        Cow::from("-- SEARCH --")
+    }
+
+    fn command_line(&self) -> Option<(char, &str)> {
+        Some((self.prompt, &self.pattern))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_match, MatchCache, Query, QueryErr};
+    use crate::arena::Arena;
+    use crate::ast::json::{add_value_to_arena, Class};
+    use crate::core::{Path, PathQuery, PathQueryErr, Side};
+
+    use serde_json::json;
+    use std::rc::Rc;
+
+    #[test]
+    fn parse() {
+        let parse = Query::<Class>::parse;
+        assert_eq!(parse("abc"), Ok(Query::Text("abc".to_owned())));
+        assert_eq!(parse("\\:a"), Ok(Query::Text(":a".to_owned())));
+        assert_eq!(
+            parse(":null"),
+            Ok(Query::Class {
+                class: Class::Null,
+                is_empty: false
+            })
+        );
+        assert_eq!(
+            parse(":empty a"),
+            Ok(Query::Class {
+                class: Class::Array,
+                is_empty: true
+            })
+        );
+        assert_eq!(
+            parse("$.a[*]"),
+            Ok(Query::Path(PathQuery::parse("$.a[*]").unwrap()))
+        );
+        assert_eq!(parse("\\$a"), Ok(Query::Text("$a".to_owned())));
+        assert_eq!(
+            parse("$["),
+            Err(QueryErr::InvalidPathQuery(PathQueryErr::UnexpectedEnd))
+        );
+        assert_eq!(parse(""), Err(QueryErr::Empty));
+        assert_eq!(
+            parse(":nothing"),
+            Err(QueryErr::UnknownClass("nothing".to_owned()))
+        );
+    }
+
+    #[test]
+    fn find() {
+        let arena = Arena::new();
+        let root = add_value_to_arena(json!([{"name": "x"}, [], [null], "a name", null]), &arena);
+        let text = Query::Text("name".to_owned());
+        let find = |query: &Query<Class>, start: Vec<usize>, side, count| {
+            find_match(root, query, &Path::from_vec(start), side, count)
+        };
+        // Keys and string contents both match text
+        assert_eq!(
+            find(&text, vec![], Side::Next, 1),
+            Some((Path::from_vec(vec![0, 0, 0]), false))
+        );
+        assert_eq!(
+            find(&text, vec![0, 0, 0], Side::Next, 1),
+            Some((Path::from_vec(vec![3]), false))
+        );
+        // Searches wrap around the ends of the tree
+        assert_eq!(
+            find(&text, vec![3], Side::Next, 1),
+            Some((Path::from_vec(vec![0, 0, 0]), true))
+        );
+        assert_eq!(
+            find(&text, vec![0, 0, 0], Side::Prev, 1),
+            Some((Path::from_vec(vec![3]), true))
+        );
+        // Counts larger than the number of matches carry on wrapping
+        assert_eq!(
+            find(&text, vec![], Side::Next, 4),
+            Some((Path::from_vec(vec![3]), true))
+        );
+        // Searching for classes
+        let null = Query::Class {
+            class: Class::Null,
+            is_empty: false,
+        };
+        assert_eq!(
+            find(&null, vec![2, 0], Side::Next, 1),
+            Some((Path::from_vec(vec![4]), false))
+        );
+        let empty_array = Query::Class {
+            class: Class::Array,
+            is_empty: true,
+        };
+        assert_eq!(
+            find(&empty_array, vec![2], Side::Next, 1),
+            Some((Path::from_vec(vec![1]), true))
+        );
+        assert_eq!(
+            find(&Query::Text("y".to_owned()), vec![], Side::Next, 1),
+            None
+        );
+        // Searching with path queries
+        let nulls = Query::Path(PathQuery::parse("$..[?(@ == null)]").unwrap());
+        assert_eq!(
+            find(&nulls, vec![], Side::Prev, 1),
+            Some((Path::from_vec(vec![4]), true))
+        );
+        assert_eq!(
+            find(&nulls, vec![4], Side::Prev, 1),
+            Some((Path::from_vec(vec![2, 0]), false))
+        );
+        assert_eq!(
+            find(
+                &Query::Path(PathQuery::parse("$[5]").unwrap()),
+                vec![],
+                Side::Next,
+                1
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn match_cache() {
+        let arena = Arena::new();
+        let root = add_value_to_arena(json!([null, [null], true]), &arena);
+        let other_root = add_value_to_arena(json!([true, null]), &arena);
+        let cache = MatchCache::new();
+        // Only the matches of path queries are found up front
+        let text_matches = cache.matches(root, "null").unwrap();
+        assert_eq!(*text_matches.query, Query::Text("null".to_owned()));
+        assert!(text_matches.paths.is_empty());
+        let nulls = "$..[?(@ == null)]";
+        let matches = cache.matches(root, nulls).unwrap();
+        assert_eq!(
+            &*matches.paths,
+            &[Path::from_vec(vec![0]), Path::from_vec(vec![1, 0])]
+        );
+        // The matches are reused until the tree or the pattern changes
+        assert!(Rc::ptr_eq(
+            &matches.paths,
+            &cache.matches(root, nulls).unwrap().paths
+        ));
+        assert_eq!(
+            &*cache.matches(other_root, nulls).unwrap().paths,
+            &[Path::from_vec(vec![1])]
+        );
+        assert!(cache.matches(root, "$[").is_none());
+    }
+}
@@ -2,6 +2,7 @@
 
 use super::{keystroke_log::Category, Editor};
 use crate::ast::Ast;
+use crate::core::Path;
 
 use std::borrow::Cow;
 
@@ -30,6 +31,12 @@ impl<'arena, Node: Ast<'arena>> State<'arena, Node> for Quit {
 /// The current states are:
 /// - [`Quit`]
 /// - [`crate::editor::normal_mode::State`]
+/// - [`crate::editor::insert_mode::State`]
+/// - [`crate::editor::command_mode::State`]
+/// - [`crate::editor::search::State`]
+/// - [`crate::editor::undo_tree::State`]
+/// - [`crate::editor::visual_mode::State`]
+/// - [`crate::editor::multi_cursor::State`]
 /// - `crate::editor::IntermediateState` (link doesn't work because `IntermediateState` is private)
 pub trait State<'arena, Node: Ast<'arena>>: std::fmt::Debug {
     /// Consume a keystroke, returning the `State` after this transition
@@ -45,6 +52,48 @@ pub trait State<'arena, Node: Ast<'arena>>: std::fmt::Debug {
         Cow::from("")
     }
 
+    /// If this `State` is editing the text of the node under the cursor, then this returns the
+    /// text as it currently stands, along with the index (in [`char`]s) of the caret within that
+    /// text.  By default, this returns `None`.
+    fn text_edit(&self) -> Option<(&str, usize)> {
+        None
+    }
+
+    /// If this `State` is editing a command line, then this returns the character which prompted
+    /// it (e.g. `:` or `/`) and the command typed so far (which are rendered in the bottom bar).
+    /// By default, this returns `None`.
+    fn command_line(&self) -> Option<(char, &str)> {
+        None
+    }
+
+    /// If this `State` is showing the undo tree panel, then this returns the index of the
+    /// [`Snapshot`](crate::editor::dag::Snapshot) selected in that panel.  By default, this returns
+    /// `None`.
+    fn undo_tree_selection(&self) -> Option<usize> {
+        None
+    }
+
+    /// If this `State` is selecting a range of the cursor's siblings, then this returns the child
+    /// index of the sibling at the other end of the selection to the cursor.  By default, this
+    /// returns `None`.
+    fn visual_anchor(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns the paths to every cursor apart from the [`Dag`](crate::editor::dag::Dag)'s own
+    /// cursor (see [`multi_cursor`](crate::editor::multi_cursor)).  By default, there are no other
+    /// cursors.
+    fn other_cursors(&self) -> &[Path] {
+        &[]
+    }
+
+    /// Returns `true` if this `State` is waiting for a new command and holds nothing that would
+    /// be lost by replacing it with a new [`normal_mode::State`](crate::editor::normal_mode::State)
+    /// (which happens when the arena is compacted).  By default, this returns `false`.
+    fn is_idle(&self) -> bool {
+        false
+    }
+
     /// Returns `true` if Sapling should quit.  By default, this returns `false`.  This should
     /// **only** be `true` for [`Quit`].
     fn is_quit(&self) -> bool {
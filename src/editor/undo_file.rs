@@ -0,0 +1,410 @@
+//! Code to store the undo history of a tree in an 'undo file' next to the file being edited, so
+//! that undo and redo keep working after Sapling is restarted (like Vim's `undofile` option).
+//!
+//! An undo file is a JSON object containing every node in the undo history exactly once (nodes
+//! which are shared between snapshots are only stored once), along with each [`Snapshot`]'s root,
+//! cursor locations, parent snapshot, creation time (in milliseconds since the Unix epoch) and
+//! description:
+//! ```json
+//! {
+//!     "version": 3,
+//!     "nodes": [["true", []], ["false", []], ["array", [0, 1]], ["array", [0]]],
+//!     "history": [
+//!         [2, [], [], null, 1600000000000, "original tree"],
+//!         [3, [1], [0], 0, 1600000005000, "delete false"]
+//!     ],
+//!     "history-index": 1
+//! }
+//! ```
+//! Each node is stored as its [`undo data`](Ast::to_undo_data) and the indices of its children,
+//! which always come before their parents.  Likewise, each snapshot's parent comes before it.  The
+//! `history-index`th snapshot is the tree that was written to the file, so the undo file is only
+//! used if the file still contains that tree.
+
+use super::dag::{Dag, Snapshot};
+use super::write_atomically;
+use crate::arena::Arena;
+use crate::ast::Ast;
+use crate::core::Path;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+/// The version of the undo file format, which should be increased whenever the format changes
+const FORMAT_VERSION: u64 = 3;
+
+/// The possible ways that reading an undo file could fail
+#[derive(Debug)]
+pub enum UndoFileError {
+    /// The undo file couldn't be read or written
+    Io(PathBuf, std::io::Error),
+    /// The undo file isn't valid JSON
+    Json(serde_json::Error),
+    /// The undo file was written by a different version of Sapling
+    WrongVersion(Option<u64>),
+    /// The undo file is valid JSON, but doesn't describe a valid undo history.  The string
+    /// describes what was wrong.
+    Invalid(&'static str),
+}
+
+impl std::fmt::Display for UndoFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UndoFileError::Io(path, e) => write!(f, "Error accessing undo file {:?}: {}", path, e),
+            UndoFileError::Json(e) => write!(f, "Undo file isn't valid JSON: {}", e),
+            UndoFileError::WrongVersion(Some(v)) => write!(
+                f,
+                "Undo file has version {} (expected {})",
+                v, FORMAT_VERSION
+            ),
+            UndoFileError::WrongVersion(None) => write!(f, "Undo file has no version"),
+            UndoFileError::Invalid(reason) => write!(f, "Invalid undo file: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for UndoFileError {}
+
+/// Returns the path of the undo file for the file at `path`, which is a hidden file in the same
+/// directory (e.g. the undo file for `dir/file.json` is `dir/.file.json.sapling-undo`).  Returns
+/// `None` if `path` doesn't have a file name.
+pub fn undo_file_path(path: &std::path::Path) -> Option<PathBuf> {
+    let mut undo_file_name = std::ffi::OsString::from(".");
+    undo_file_name.push(path.file_name()?);
+    undo_file_name.push(".sapling-undo");
+    Some(path.with_file_name(undo_file_name))
+}
+
+/// Writes the undo history of a [`Dag`] to the undo file for the file at `path`.  The current
+/// tree of the `Dag` should be the one which was just written to `path`.
+pub fn write<'arena, Node: Ast<'arena>>(
+    dag: &Dag<'arena, Node>,
+    path: &std::path::Path,
+) -> Result<(), UndoFileError> {
+    let undo_path = undo_file_path(path).ok_or_else(|| {
+        UndoFileError::Io(
+            path.to_owned(),
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "path has no file name"),
+        )
+    })?;
+    let content = to_json(dag).to_string();
+    write_atomically(&undo_path, &content).map_err(|e| UndoFileError::Io(undo_path, e))
+}
+
+/// Reads the undo file for the file at `path`, whose contents have already been loaded as
+/// `file_root`.  This returns `None` if there is no undo file, or if the undo file was written
+/// for different contents (e.g. because the file has since been changed by another program).
+pub fn read<'arena, Node: Ast<'arena>>(
+    path: &std::path::Path,
+    arena: &'arena Arena<Node>,
+    file_root: &'arena Node,
+) -> Result<Option<Dag<'arena, Node>>, UndoFileError> {
+    let undo_path = match undo_file_path(path) {
+        Some(p) => p,
+        None => return Ok(None),
+    };
+    let text = match std::fs::read_to_string(&undo_path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(UndoFileError::Io(undo_path, e)),
+    };
+    let value: Value = serde_json::from_str(&text).map_err(UndoFileError::Json)?;
+    let dag = from_json(&value, arena)?;
+    if dag.root() != file_root {
+        log::info!(
+            "{:?} doesn't match the contents of {:?}, so not using it",
+            undo_path,
+            path
+        );
+        return Ok(None);
+    }
+    Ok(Some(dag))
+}
+
+/// Converts the undo history of a [`Dag`] into the JSON value stored in an undo file
+fn to_json<'arena, Node: Ast<'arena>>(dag: &Dag<'arena, Node>) -> Value {
+    /// Adds a node and its descendants to `nodes` (children first), unless it has already been
+    /// added, and returns the node's index
+    fn add_node<'arena, Node: Ast<'arena>>(
+        node: &'arena Node,
+        nodes: &mut Vec<Value>,
+        indices: &mut HashMap<*const Node, usize>,
+    ) -> usize {
+        if let Some(index) = indices.get(&(node as *const Node)) {
+            return *index;
+        }
+        let child_indices: Vec<usize> = node
+            .children()
+            .iter()
+            .map(|child| add_node(*child, nodes, indices))
+            .collect();
+        nodes.push(json!([node.to_undo_data(), child_indices]));
+        indices.insert(node as *const Node, nodes.len() - 1);
+        nodes.len() - 1
+    }
+
+    let mut nodes = Vec::new();
+    let mut indices = HashMap::new();
+    let history: Vec<Value> = dag
+        .history()
+        .iter()
+        .map(|snapshot| {
+            json!([
+                add_node(snapshot.root, &mut nodes, &mut indices),
+                snapshot.cursor_before.iter().collect::<Vec<_>>(),
+                snapshot.cursor_after.iter().collect::<Vec<_>>(),
+                snapshot.parent,
+                snapshot
+                    .time
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_millis() as u64),
+                snapshot.description,
+            ])
+        })
+        .collect();
+    json!({
+        "version": FORMAT_VERSION,
+        "nodes": nodes,
+        "history": history,
+        "history-index": dag.history_index(),
+    })
+}
+
+/// Rebuilds a [`Dag`] from the JSON value stored in an undo file, allocating its nodes in `arena`
+fn from_json<'arena, Node: Ast<'arena>>(
+    value: &Value,
+    arena: &'arena Arena<Node>,
+) -> Result<Dag<'arena, Node>, UndoFileError> {
+    let version = value.get("version").and_then(Value::as_u64);
+    if version != Some(FORMAT_VERSION) {
+        return Err(UndoFileError::WrongVersion(version));
+    }
+    let as_index = |value: &Value| value.as_u64().map(|i| i as usize);
+
+    // Recreate the nodes, checking that every node only refers to nodes before it
+    let mut nodes: Vec<&'arena Node> = Vec::new();
+    let node_values = value
+        .get("nodes")
+        .and_then(Value::as_array)
+        .ok_or(UndoFileError::Invalid("expected a list of nodes"))?;
+    for node_value in node_values {
+        let (data, child_indices) = match node_value.as_array().map(Vec::as_slice) {
+            Some([data, Value::Array(child_indices)]) => (data, child_indices),
+            _ => {
+                return Err(UndoFileError::Invalid(
+                    "expected nodes to be [data, children]",
+                ))
+            }
+        };
+        let children = child_indices
+            .iter()
+            .map(|i| as_index(i).and_then(|i| nodes.get(i)).copied())
+            .collect::<Option<Vec<_>>>()
+            .ok_or(UndoFileError::Invalid("child index out of range"))?;
+        let node = Node::from_undo_data(data, children)
+            .ok_or(UndoFileError::Invalid("node data isn't valid"))?;
+        nodes.push(arena.alloc(node));
+    }
+
+    // Recreate the snapshots, checking that their cursors point to real nodes
+    let to_path = |value: &Value, root: &'arena Node| -> Option<Path> {
+        let indices = value.as_array()?.iter().map(as_index);
+        let path = Path::from_vec(indices.collect::<Option<Vec<_>>>()?);
+        Some(path).filter(|path| path.is_valid_in(root))
+    };
+    let history = value
+        .get("history")
+        .and_then(Value::as_array)
+        .ok_or(UndoFileError::Invalid("expected a list of snapshots"))?
+        .iter()
+        .map(|snapshot| match snapshot.as_array().map(Vec::as_slice) {
+            Some([root, cursor_before, cursor_after, parent, time, description]) => {
+                let root = as_index(root).and_then(|i| nodes.get(i)).copied()?;
+                let cursor_after = to_path(cursor_after, root)?;
+                // The cursor before an edit points into the parent's tree, so can't be checked
+                // against this snapshot's root
+                let cursor_before = cursor_before.as_array()?.iter().map(as_index);
+                let cursor_before = Path::from_vec(cursor_before.collect::<Option<Vec<_>>>()?);
+                let parent = match parent {
+                    Value::Null => None,
+                    _ => Some(as_index(parent)?),
+                };
+                let time = UNIX_EPOCH + Duration::from_millis(time.as_u64()?);
+                Some(Snapshot::new(
+                    cursor_before,
+                    root,
+                    cursor_after,
+                    parent,
+                    time,
+                    description.as_str()?.to_owned(),
+                ))
+            }
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or(UndoFileError::Invalid("snapshot isn't valid"))?;
+    // Only the first snapshot has no parent, every other snapshot's parent must come before it and
+    // each `cursor_before` must point into the parent's tree
+    for (index, snapshot) in history.iter().enumerate() {
+        let is_valid = match snapshot.parent {
+            None => index == 0,
+            Some(parent) => {
+                parent < index && snapshot.cursor_before.is_valid_in(history[parent].root)
+            }
+        };
+        if !is_valid {
+            return Err(UndoFileError::Invalid("snapshot isn't valid"));
+        }
+    }
+
+    let history_index = value
+        .get("history-index")
+        .and_then(as_index)
+        .filter(|i| *i < history.len())
+        .ok_or(UndoFileError::Invalid("history index out of range"))?;
+    Ok(Dag::from_history(arena, history, history_index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_json, read, to_json, undo_file_path, write, UndoFileError};
+    use crate::arena::Arena;
+    use crate::ast::json::{add_value_to_arena, Json};
+    use crate::ast::Ast;
+    use crate::core::{Path, Side};
+    use crate::editor::dag::{Dag, Insertable, Snapshot};
+
+    use serde_json::json;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn round_trip() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(json!([true, {"key": "value"}, -1.5e3, null]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![0]));
+        dag.insert_next_to_cursor(2, Insertable::CountedNode(1, 'a'), Side::Next)
+            .unwrap();
+        dag.delete_cursor(1).unwrap();
+        dag.undo(1).unwrap();
+        // Make a second branch of the undo tree, then return to the first one
+        dag.delete_cursor(1).unwrap();
+        dag.jump_to_snapshot(2).unwrap();
+        dag.undo(1).unwrap();
+
+        let value = to_json(&dag);
+        // Nodes that are shared between snapshots are only stored once
+        let node_count = value["nodes"].as_array().unwrap().len();
+        assert_eq!(node_count, 13);
+
+        let new_arena: Arena<Json> = Arena::new();
+        let mut new_dag = from_json(&value, &new_arena).unwrap();
+        assert_eq!(new_dag.root(), dag.root());
+        assert_eq!(new_dag.cursor_path(), dag.cursor_path());
+        assert!(!new_dag.is_modified());
+        // The shared nodes are still shared
+        let new_root = new_dag.root();
+        assert!(std::ptr::eq(
+            new_dag.history()[0].root.children()[1],
+            new_root.children()[3]
+        ));
+        // Both branches are kept, along with when they were made (to the nearest millisecond)
+        assert_eq!(new_dag.branch_tips(), vec![2, 3]);
+        assert_eq!(new_dag.history()[3].parent, Some(1));
+        assert_eq!(
+            new_dag.history()[3].description,
+            dag.history()[3].description
+        );
+        let millis = |snapshot: &Snapshot<Json>| {
+            let time = snapshot.time.duration_since(UNIX_EPOCH);
+            time.unwrap().as_millis()
+        };
+        assert_eq!(millis(&new_dag.history()[3]), millis(&dag.history()[3]));
+        new_dag.redo(1).unwrap();
+        assert_eq!(
+            *new_dag.root(),
+            json!([true, [], {"key": "value"}, -1.5e3, null])
+        );
+        new_dag.undo(2).unwrap();
+        assert_eq!(
+            *new_dag.root(),
+            json!([true, {"key": "value"}, -1.5e3, null])
+        );
+        assert_eq!(new_dag.cursor_path(), &Path::from_vec(vec![0]));
+    }
+
+    #[test]
+    fn invalid_undo_files() {
+        let arena: Arena<Json> = Arena::new();
+        let valid_nodes = json!([["true", []], ["array", [0]]]);
+        for (value, expected_err) in &[
+            (json!({}), "Undo file has no version"),
+            (
+                json!({"version": 100}),
+                "Undo file has version 100 (expected 3)",
+            ),
+            (
+                json!({"version": 3, "nodes": [["array", [0]]]}),
+                "Invalid undo file: child index out of range",
+            ),
+            (
+                json!({"version": 3, "nodes": [["true", []], ["field", [0, 0]]]}),
+                "Invalid undo file: node data isn't valid",
+            ),
+            (
+                json!({"version": 3, "nodes": [[{"number": "1.2.3"}, []]]}),
+                "Invalid undo file: node data isn't valid",
+            ),
+            (
+                json!({"version": 3, "nodes": valid_nodes, "history": [[1, [], [1], null, 0, ""]]}),
+                "Invalid undo file: snapshot isn't valid",
+            ),
+            (
+                json!({"version": 3, "nodes": valid_nodes, "history": [[0, [], [], null, 0, ""], [1, [0], [0], 0, 0, ""]]}),
+                "Invalid undo file: snapshot isn't valid",
+            ),
+            (
+                json!({"version": 3, "nodes": valid_nodes, "history": [[0, [], [], null, 0, ""], [1, [], [0], 1, 0, ""]]}),
+                "Invalid undo file: snapshot isn't valid",
+            ),
+            (
+                json!({"version": 3, "nodes": valid_nodes, "history": [[1, [], [0], null, 0, ""]], "history-index": 1}),
+                "Invalid undo file: history index out of range",
+            ),
+        ] {
+            let err: UndoFileError = from_json::<Json>(value, &arena).err().unwrap();
+            assert_eq!(err.to_string(), *expected_err);
+        }
+    }
+
+    #[test]
+    fn read_and_write() {
+        let dir = std::env::temp_dir().join(format!("sapling-undo-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.json");
+        assert_eq!(
+            undo_file_path(&path),
+            Some(dir.join(".file.json.sapling-undo"))
+        );
+
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(json!([true]), &arena);
+        // With no undo file, there's no history to load
+        assert!(read(&path, &arena, root).unwrap().is_none());
+
+        let mut dag = Dag::new(&arena, root, Path::from_vec(vec![0]));
+        dag.delete_cursor(1).unwrap();
+        write(&dag, &path).unwrap();
+        // The undo file is only used if it matches the file's contents
+        let file_root = add_value_to_arena(json!([]), &arena);
+        let mut loaded_dag = read(&path, &arena, file_root).unwrap().unwrap();
+        loaded_dag.undo(1).unwrap();
+        assert_eq!(*loaded_dag.root(), json!([true]));
+        let changed_root = add_value_to_arena(json!([false]), &arena);
+        assert!(read(&path, &arena, changed_root).unwrap().is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,231 @@
+//! The undo tree panel, which draws the branches of the undo history down the side of the screen
+//! and lets the user jump to any snapshot in it
+
+use super::dag::Snapshot;
+use super::{keystroke_log::Category, normal_mode, state, Editor};
+use crate::ast::Ast;
+
+use std::borrow::Cow;
+use std::time::{Duration, SystemTime};
+
+use tuikit::prelude::Key;
+
+/// One row of the drawing of an undo tree, which shows a single [`Snapshot`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Row {
+    /// The lines of the graph to the left of the snapshot (in the style of `git log --graph`),
+    /// where the snapshot itself is drawn as `o`
+    pub graph: String,
+    /// The index of the snapshot shown on this row
+    pub index: usize,
+}
+
+/// Draws the undo tree made by a list of [`Snapshot`]s as a graph, with one [`Row`] per snapshot
+/// from newest to oldest.  Each branch of the tree gets its own column, which joins the column of
+/// the snapshot it branched from.
+pub fn graph_rows<'arena, Node: Ast<'arena>>(history: &[Snapshot<'arena, Node>]) -> Vec<Row> {
+    // For each column, the index of the snapshot that its line is leading to
+    let mut columns: Vec<Option<usize>> = Vec::new();
+    let mut rows = Vec::with_capacity(history.len());
+    for (index, snapshot) in history.iter().enumerate().rev() {
+        let joining_columns: Vec<usize> = (0..columns.len())
+            .filter(|c| columns[*c] == Some(index))
+            .collect();
+        // Snapshots which haven't been edited start a new column
+        let column = match joining_columns.first() {
+            Some(c) => *c,
+            None => match columns.iter().position(Option::is_none) {
+                Some(c) => c,
+                None => {
+                    columns.push(None);
+                    columns.len() - 1
+                }
+            },
+        };
+        let last_joining_column = joining_columns.last().copied().unwrap_or(column);
+
+        let mut graph = String::new();
+        for (c, line) in columns.iter().enumerate() {
+            graph.push(if c == column {
+                'o'
+            } else if c == last_joining_column {
+                '┘'
+            } else if c > column && c < last_joining_column {
+                // Lines which pass between the snapshot and the columns joining it are crossed
+                if joining_columns.contains(&c) {
+                    '┴'
+                } else if line.is_some() {
+                    '┼'
+                } else {
+                    '─'
+                }
+            } else if line.is_some() {
+                '│'
+            } else {
+                ' '
+            });
+            graph.push(if c >= column && c < last_joining_column {
+                '─'
+            } else {
+                ' '
+            });
+        }
+        rows.push(Row {
+            graph: graph.trim_end().to_owned(),
+            index,
+        });
+
+        // Every column which joined this snapshot continues to its parent as one line
+        for c in joining_columns {
+            columns[c] = None;
+        }
+        columns[column] = snapshot.parent;
+        while columns.last() == Some(&None) {
+            columns.pop();
+        }
+    }
+    rows
+}
+
+/// Formats how long ago `time` was (relative to `now`) in its largest whole unit (e.g. `45s`,
+/// `3m`, `2h` or `5d`)
+pub fn format_age(time: SystemTime, now: SystemTime) -> String {
+    let secs = now
+        .duration_since(time)
+        .unwrap_or(Duration::from_secs(0))
+        .as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+/// The [`State`](state::State) which Sapling is in whilst the undo tree panel is open.  The user
+/// moves a selection through the snapshots, and can jump to the selected snapshot (which moves the
+/// tree to that point in its history) before closing the panel.
+#[derive(Debug, Clone)]
+pub struct State {
+    /// The index of the selected [`Snapshot`]
+    selected: usize,
+}
+
+impl State {
+    /// Creates a new undo tree `State`, with a given [`Snapshot`] selected
+    pub fn new(selected: usize) -> Self {
+        State { selected }
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> state::State<'arena, Node> for State {
+    fn transition(
+        mut self: Box<Self>,
+        key: Key,
+        editor: &mut Editor<'arena, Node>,
+    ) -> (
+        Box<dyn state::State<'arena, Node>>,
+        Option<(String, Category)>,
+    ) {
+        match key {
+            // Move the selection up (to newer snapshots) or down (to older snapshots)
+            Key::Char('k') | Key::Up | Key::Char('j') | Key::Down => {
+                let rows = graph_rows(editor.buffer().tree.history());
+                let row = rows
+                    .iter()
+                    .position(|row| row.index == self.selected)
+                    .unwrap_or(0);
+                let new_row = match key {
+                    Key::Char('k') | Key::Up => row.saturating_sub(1),
+                    _ => (row + 1).min(rows.len() - 1),
+                };
+                self.selected = rows[new_row].index;
+                (self, None)
+            }
+            Key::Enter => {
+                let log_entry = match editor.buffer_mut().tree.jump_to_snapshot(self.selected) {
+                    Ok(_) => format!("go to snapshot {}", self.selected),
+                    Err(_) => format!("there is no snapshot {}", self.selected),
+                };
+                (self, Some((log_entry, Category::History)))
+            }
+            Key::ESC | Key::Char('q') => (
+                Box::new(normal_mode::State::default()),
+                Some(("close undo tree".to_owned(), Category::Undefined)),
+            ),
+            _ => (self, None),
+        }
+    }
+
+    fn keystroke_buffer(&self) -> Cow<'_, str> {
+        Cow::from("-- UNDO TREE --")
+    }
+
+    fn undo_tree_selection(&self) -> Option<usize> {
+        Some(self.selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_age, graph_rows};
+    use crate::arena::Arena;
+    use crate::ast::json::{add_value_to_arena, Json};
+    use crate::core::{Direction, Path};
+    use crate::editor::dag::{Dag, Insertable};
+
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn graphs() {
+        let arena: Arena<Json> = Arena::new();
+        let root = add_value_to_arena(serde_json::json!([]), &arena);
+        let mut dag = Dag::new(&arena, root, Path::root());
+        fn insert<'arena>(dag: &mut Dag<'arena, Json<'arena>>) {
+            dag.insert_child(1, Insertable::CountedNode(1, 't'))
+                .unwrap();
+            dag.move_cursor(1, Direction::Up).unwrap();
+        }
+        // Snapshot 1 and 4 are children of 0, 2 and 3 are children of 1, and 5 is a child of 4
+        insert(&mut dag);
+        insert(&mut dag);
+        dag.undo(1).unwrap();
+        insert(&mut dag);
+        dag.jump_to_snapshot(0).unwrap();
+        insert(&mut dag);
+        insert(&mut dag);
+        let rows: Vec<(String, usize)> = graph_rows(dag.history())
+            .into_iter()
+            .map(|row| (row.graph, row.index))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("o".to_owned(), 5),
+                ("o".to_owned(), 4),
+                ("│ o".to_owned(), 3),
+                ("│ │ o".to_owned(), 2),
+                ("│ o─┘".to_owned(), 1),
+                ("o─┘".to_owned(), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn ages() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        for (secs, expected) in &[
+            (0, "0s"),
+            (59, "59s"),
+            (60, "1m"),
+            (3599, "59m"),
+            (7200, "2h"),
+            (86400 * 3 + 5, "3d"),
+        ] {
+            let time = now - Duration::from_secs(*secs);
+            assert_eq!(format_age(time, now), *expected);
+        }
+        // Times in the future are treated as being now
+        assert_eq!(format_age(now + Duration::from_secs(5), now), "0s");
+    }
+}
@@ -0,0 +1,118 @@
+//! Code to keep track of which part of the rendered tree is visible on the screen
+
+/// The rectangle of the rendered tree which is visible on the screen.  All the positions used by
+/// the `Viewport` are `(row, column)` pairs measured from the top-left corner of the rendered
+/// tree, not of the screen.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct Viewport {
+    /// The first row of the rendered tree which is visible
+    pub top: usize,
+    /// The first column of the rendered tree which is visible
+    pub left: usize,
+    /// How many rows of the tree fit on the screen
+    pub height: usize,
+    /// How many columns of the tree fit on the screen
+    pub width: usize,
+}
+
+impl Viewport {
+    /// Returns `true` if a given row of the rendered tree is visible
+    pub fn contains_row(&self, row: usize) -> bool {
+        row >= self.top && row < self.top + self.height
+    }
+
+    /// Converts a position in the rendered tree into a position on the screen, returning `None`
+    /// if that position is outside the `Viewport`.
+    pub fn to_screen(&self, row: usize, col: usize) -> Option<(usize, usize)> {
+        if self.contains_row(row) && col >= self.left && col < self.left + self.width {
+            Some((row - self.top, col - self.left))
+        } else {
+            None
+        }
+    }
+
+    /// Scrolls vertically by the smallest amount which makes the rows `first_row..=last_row`
+    /// visible.  If these rows don't all fit on the screen, then `first_row` is put at the top.
+    pub fn scroll_to_rows(&mut self, first_row: usize, last_row: usize) {
+        if last_row >= self.top + self.height {
+            self.top = (last_row + 1).saturating_sub(self.height);
+        }
+        if first_row < self.top || last_row - first_row >= self.height {
+            self.top = first_row;
+        }
+    }
+
+    /// Scrolls horizontally by the smallest amount which makes a given column visible
+    pub fn scroll_to_col(&mut self, col: usize) {
+        if col < self.left {
+            self.left = col;
+        } else if col >= self.left + self.width {
+            self.left = (col + 1).saturating_sub(self.width);
+        }
+    }
+
+    /// Scrolls vertically so that a given row is in the middle of the screen (or as close as it
+    /// can get without scrolling above the top of the tree)
+    pub fn center_on_row(&mut self, row: usize) {
+        self.top = row.saturating_sub(self.height / 2);
+    }
+
+    /// Scrolls vertically by a number of rows (negative numbers scroll upwards), without scrolling
+    /// past the top of the tree or the last of its `total_rows` rows.
+    pub fn scroll_by(&mut self, rows: isize, total_rows: usize) {
+        let max_top = total_rows.saturating_sub(1);
+        let new_top = if rows < 0 {
+            self.top.saturating_sub(rows.unsigned_abs())
+        } else {
+            self.top.saturating_add(rows as usize)
+        };
+        self.top = new_top.min(max_top);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Viewport;
+
+    #[test]
+    fn scrolling() {
+        let mut viewport = Viewport {
+            top: 0,
+            left: 0,
+            height: 10,
+            width: 20,
+        };
+        // Rows which are already visible don't cause scrolling
+        viewport.scroll_to_rows(3, 9);
+        assert_eq!(viewport.top, 0);
+        // Scrolling down only goes as far as is needed
+        viewport.scroll_to_rows(12, 14);
+        assert_eq!(viewport.top, 5);
+        assert_eq!(viewport.to_screen(14, 0), Some((9, 0)));
+        assert_eq!(viewport.to_screen(15, 0), None);
+        // Scrolling up puts the first row at the top
+        viewport.scroll_to_rows(2, 3);
+        assert_eq!(viewport.top, 2);
+        // Ranges taller than the screen show their first row
+        viewport.scroll_to_rows(30, 50);
+        assert_eq!(viewport.top, 30);
+        // Horizontal scrolling
+        viewport.scroll_to_col(25);
+        assert_eq!(viewport.left, 6);
+        viewport.scroll_to_col(10);
+        assert_eq!(viewport.left, 6);
+        viewport.scroll_to_col(2);
+        assert_eq!(viewport.left, 2);
+        // Centring and paging
+        viewport.center_on_row(3);
+        assert_eq!(viewport.top, 0);
+        viewport.center_on_row(40);
+        assert_eq!(viewport.top, 35);
+        viewport.scroll_by(-10, 100);
+        assert_eq!(viewport.top, 25);
+        viewport.scroll_by(-30, 100);
+        assert_eq!(viewport.top, 0);
+        viewport.scroll_by(200, 100);
+        assert_eq!(viewport.top, 99);
+    }
+}
@@ -0,0 +1,193 @@
+//! The code for 'visual-mode', which selects a range of siblings so that a command can be applied
+//! to all of them at once
+
+use super::dag::LogMessage;
+use super::normal_mode::{self, parse_command, register_description, Action, ParseErr};
+use super::{keystroke_log::Category, state, Editor};
+use crate::ast::Ast;
+use crate::core::{keystrokes_to_string, Direction};
+
+use std::borrow::Cow;
+
+use tuikit::prelude::Key;
+
+/// The [`State`](state::State) which Sapling is in whilst the user is selecting a range of
+/// siblings.  The selection runs from the `anchor` (the sibling that the cursor was on when visual
+/// mode was entered) to the cursor, which is moved with the usual sibling motions.  Deleting,
+/// yanking, replacing or wrapping the selection edits every selected node as a single undo step,
+/// and returns to normal mode.
+#[derive(Debug, Clone)]
+pub struct State {
+    /// The child index of the sibling at the fixed end of the selection
+    anchor: usize,
+    keystroke_buffer: Vec<Key>,
+}
+
+impl State {
+    /// Creates a new visual mode `State`, where the selection is anchored to the sibling with a
+    /// given child index
+    pub fn new(anchor: usize) -> Self {
+        State {
+            anchor,
+            keystroke_buffer: Vec::new(),
+        }
+    }
+}
+
+/// Returns the child indices of the first and last selected siblings, when the selection is
+/// anchored at `anchor` and the cursor is the sibling with index `cursor_index`
+pub fn selected_range(anchor: usize, cursor_index: usize) -> (usize, usize) {
+    (anchor.min(cursor_index), anchor.max(cursor_index))
+}
+
+impl<'arena, Node: Ast<'arena>> state::State<'arena, Node> for State {
+    fn transition(
+        mut self: Box<Self>,
+        key: Key,
+        editor: &mut Editor<'arena, Node>,
+    ) -> (
+        Box<dyn state::State<'arena, Node>>,
+        Option<(String, Category)>,
+    ) {
+        if key == Key::ESC {
+            return (
+                Box::new(normal_mode::State::default()),
+                Some(("leave visual mode".to_owned(), Category::Undefined)),
+            );
+        }
+        self.keystroke_buffer.push(key);
+
+        let current_buffer = editor.current_buffer();
+        let tree = &mut editor.buffers[current_buffer].tree;
+
+        let (count, action) = match parse_command(&editor.config.keymap, &self.keystroke_buffer) {
+            Ok(command) => command,
+            Err(ParseErr::Incomplete) => return (self, None),
+            Err(ParseErr::Invalid) => {
+                let log_entry = format!(
+                    "Undefined command '{}'",
+                    keystrokes_to_string(&self.keystroke_buffer)
+                );
+                self.keystroke_buffer.clear();
+                return (self, Some((log_entry, Category::Undefined)));
+            }
+        };
+        self.keystroke_buffer.clear();
+        if count == 0 {
+            return (self, Some(("no action".to_owned(), Category::Undefined)));
+        }
+
+        // Moving between siblings extends or shrinks the selection
+        if let Action::MoveCursor(
+            direction @ Direction::Prev
+            | direction @ Direction::Next
+            | direction @ Direction::FirstSibling
+            | direction @ Direction::LastSibling,
+        ) = action
+        {
+            tree.move_cursor(count, direction).log_message();
+            return (self, Some((action.description(), action.category())));
+        }
+        // Typing `v` again leaves visual mode without doing anything
+        if action == Action::VisualMode {
+            return (
+                Box::new(normal_mode::State::default()),
+                Some(("leave visual mode".to_owned(), Category::Undefined)),
+            );
+        }
+
+        // Every other command is applied to the whole selection, starting from its first node.  We
+        // can unwrap because visual mode can't be entered at the root, and none of the motions
+        // allowed in visual mode can move the cursor to the root.
+        let cursor_index = tree.cursor_path().last().unwrap();
+        let (first, last) = selected_range(self.anchor, cursor_index);
+        let len = last - first + 1;
+        let mut path = tree.cursor_path().clone();
+        *path.last_mut().unwrap() = first;
+        // Each command gives its description, and whether or not it succeeded
+        let (description, succeeded) = match action {
+            Action::Delete(register) => {
+                tree.set_cursor_path(path);
+                let deleted_nodes = tree.cursor_and_next_siblings(len);
+                let result = tree.delete_cursor(len);
+                let succeeded = result.is_ok();
+                if succeeded {
+                    editor.registers.store(register, deleted_nodes);
+                }
+                result.log_message();
+                let description = format!(
+                    "delete {} nodes{}",
+                    len,
+                    register_description(" into", register)
+                );
+                (description, succeeded)
+            }
+            Action::Yank(register) => {
+                tree.set_cursor_path(path);
+                let nodes = tree.cursor_and_next_siblings(len);
+                editor.registers.store(register, nodes);
+                let description = format!(
+                    "yank {} nodes{}",
+                    len,
+                    register_description(" into", register)
+                );
+                (description, true)
+            }
+            Action::Replace(c) => {
+                tree.set_cursor_path(path);
+                let result = tree.replace_cursor(len, c);
+                let succeeded = result.is_ok();
+                result.log_message();
+                (format!("replace {} nodes with '{}'", len, c), succeeded)
+            }
+            Action::Wrap(c) => {
+                tree.set_cursor_path(path);
+                let result = tree.wrap_cursor(len, c);
+                let succeeded = result.is_ok();
+                result.log_message();
+                (format!("wrap {} nodes in '{}'", len, c), succeeded)
+            }
+            // Commands which don't make sense for a selection leave it as it is
+            _ => {
+                return (
+                    self,
+                    Some((
+                        format!("can't {} in visual mode", action.description()),
+                        Category::Undefined,
+                    )),
+                )
+            }
+        };
+        // Failed edits are logged as such, rather than under the category of the command
+        let log_entry = if succeeded {
+            (description, action.category())
+        } else {
+            (format!("can't {}", description), Category::Undefined)
+        };
+        (Box::new(normal_mode::State::default()), Some(log_entry))
+    }
+
+    fn keystroke_buffer(&self) -> Cow<'_, str> {
+        if self.keystroke_buffer.is_empty() {
+            Cow::from("-- VISUAL --")
+        } else {
+            Cow::from(keystrokes_to_string(&self.keystroke_buffer))
+        }
+    }
+
+    fn visual_anchor(&self) -> Option<usize> {
+        Some(self.anchor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::selected_range;
+
+    #[test]
+    fn selection() {
+        assert_eq!(selected_range(2, 2), (2, 2));
+        assert_eq!(selected_range(2, 5), (2, 5));
+        assert_eq!(selected_range(4, 1), (1, 4));
+    }
+}
@@ -0,0 +1,451 @@
+//! Windows, each of which shows a view of one [`Buffer`](super::buffer::Buffer), and the
+//! [`WindowLayout`] which divides the screen between them.
+
+use super::dag::Dag;
+use super::layout::LayoutCache;
+use super::search::MatchCache;
+use super::viewport::Viewport;
+use crate::ast::Ast;
+use crate::core::Path;
+
+/// A view of one [`Buffer`](super::buffer::Buffer) in part of the screen.  Several `Window`s can
+/// show the same buffer, each with its own cursor, format style and [`Viewport`].
+pub struct Window<'arena, Node: Ast<'arena>> {
+    /// The index of the [`Buffer`](super::buffer::Buffer) shown in this `Window`
+    pub buffer: usize,
+    /// The style that the tree is being printed in this `Window`
+    pub format_style: Node::FormatStyle,
+    /// The part of the rendered tree which is visible in this `Window`
+    pub viewport: Viewport,
+    /// The root and cursor path that the viewport last scrolled to show.  The viewport only
+    /// follows the cursor when these change, so that the user can scroll away from the cursor.
+    followed_cursor: Option<(&'arena Node, Path)>,
+    /// A cache of how the nodes are laid out in this `Window`, which depends on `format_style`
+    pub layout_cache: LayoutCache<'arena, Node>,
+    /// A cache of the matches of the last search in the tree shown in this `Window`
+    pub search_matches: MatchCache<'arena, Node>,
+    /// The cursor of this `Window` whilst it isn't the current window.  The current window uses
+    /// the cursor of its buffer's [`Dag`], which is copied here when the user leaves the window.
+    pub cursor_path: Path,
+}
+
+impl<'arena, Node: Ast<'arena>> Window<'arena, Node> {
+    /// Creates a new `Window` showing the buffer with a given index
+    pub fn new(buffer: usize, format_style: Node::FormatStyle, cursor_path: Path) -> Self {
+        Window {
+            buffer,
+            format_style,
+            viewport: Viewport::default(),
+            followed_cursor: None,
+            layout_cache: LayoutCache::new(),
+            search_matches: MatchCache::new(),
+            cursor_path,
+        }
+    }
+
+    /* ===== SCROLLING ===== */
+
+    /// If the cursor of `tree` has moved (or `tree` has changed) since the last call, scrolls the
+    /// viewport so that the cursor is visible.  If `caret` is given, then the cursor's text is
+    /// being edited and the caret (that many chars into the text) is always kept on the screen.
+    pub fn follow_cursor(&mut self, tree: &Dag<'arena, Node>, caret: Option<usize>) {
+        let (cursor_row, cursor_col) = self.cursor_position(tree);
+        let current_cursor = (tree.root(), tree.cursor_path().clone());
+        let has_cursor_moved = match &self.followed_cursor {
+            Some((root, path)) => {
+                !std::ptr::eq(*root, current_cursor.0) || *path != current_cursor.1
+            }
+            None => true,
+        };
+        if has_cursor_moved {
+            let cursor_lines = self
+                .layout_cache
+                .size(tree.cursor(), &self.format_style)
+                .lines();
+            self.viewport
+                .scroll_to_rows(cursor_row, cursor_row + cursor_lines);
+            self.viewport.scroll_to_col(cursor_col);
+            self.followed_cursor = Some(current_cursor);
+        }
+        if let Some(caret) = caret {
+            self.viewport.scroll_to_rows(cursor_row, cursor_row);
+            self.viewport.scroll_to_col(cursor_col + caret);
+        }
+    }
+
+    /// Returns the `(row, column)` in the rendered tree where the cursor of `tree` starts
+    pub fn cursor_position(&self, tree: &Dag<'arena, Node>) -> (usize, usize) {
+        self.layout_cache
+            .node_position(tree.root(), tree.cursor_path(), &self.format_style)
+    }
+
+    /// Scrolls the view of `tree` up or down by a number of pages (negative numbers scroll
+    /// upwards), without moving the cursor
+    pub fn scroll_pages(&mut self, tree: &Dag<'arena, Node>, pages: isize) {
+        let total_rows = self
+            .layout_cache
+            .size(tree.root(), &self.format_style)
+            .lines()
+            + 1;
+        let page_height = self.viewport.height as isize;
+        self.viewport.scroll_by(pages * page_height, total_rows);
+    }
+
+    /// Scrolls the view so that the cursor of `tree` is in the middle of the window
+    pub fn center_on_cursor(&mut self, tree: &Dag<'arena, Node>) {
+        let (cursor_row, _) = self.cursor_position(tree);
+        self.viewport.center_on_row(cursor_row);
+    }
+
+    /* ===== COMPACTION ===== */
+
+    /// Returns the parts of this `Window` which are kept when the arena is compacted.  None of
+    /// the [`LayoutCache`], the [`MatchCache`] or the followed cursor can be kept, since they refer
+    /// to nodes by their addresses.
+    pub fn detach(self) -> DetachedWindow<Node::FormatStyle> {
+        DetachedWindow {
+            buffer: self.buffer,
+            format_style: self.format_style,
+            viewport: self.viewport,
+            cursor_path: self.cursor_path,
+        }
+    }
+}
+
+/// The parts of a [`Window`] which are kept when the arena is compacted
+pub struct DetachedWindow<FormatStyle> {
+    buffer: usize,
+    format_style: FormatStyle,
+    viewport: Viewport,
+    cursor_path: Path,
+}
+
+impl<FormatStyle> DetachedWindow<FormatStyle> {
+    /// Re-creates the [`Window`] for use with a new arena
+    pub fn attach<'arena, Node: Ast<'arena, FormatStyle = FormatStyle>>(
+        self,
+    ) -> Window<'arena, Node> {
+        let mut window = Window::new(self.buffer, self.format_style, self.cursor_path);
+        window.viewport = self.viewport;
+        window
+    }
+}
+
+/* ===== LAYOUT ===== */
+
+/// A rectangle of the screen, measured in rows and columns from the top-left corner
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct Rect {
+    /// The first row of the rectangle
+    pub row: usize,
+    /// The first column of the rectangle
+    pub col: usize,
+    /// How many rows the rectangle covers
+    pub height: usize,
+    /// How many columns the rectangle covers
+    pub width: usize,
+}
+
+impl Rect {
+    /// Returns the row just below the rectangle
+    pub fn bottom(&self) -> usize {
+        self.row + self.height
+    }
+
+    /// Returns the column just right of the rectangle
+    pub fn right(&self) -> usize {
+        self.col + self.width
+    }
+}
+
+/// The ways that a [`WindowLayout::Split`] can divide its area
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SplitDirection {
+    /// One window above the other, like Vim's `:split`
+    Horizontal,
+    /// The windows side by side, separated by a vertical line, like Vim's `:vsplit`
+    Vertical,
+}
+
+/// The directions on the screen in which the user can move between [`Window`]s
+#[allow(missing_docs)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ScreenDirection {
+    Left,
+    Down,
+    Up,
+    Right,
+}
+
+/// How the screen is divided between the [`Window`]s, which are referred to by their indices.
+/// Each split divides its area in half.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum WindowLayout {
+    /// The whole area shows one window
+    Window(usize),
+    /// The area is divided in two, with `first` above or to the left of `second`
+    Split {
+        /// Whether the halves are above each other or side by side
+        direction: SplitDirection,
+        /// The top or left half
+        first: Box<WindowLayout>,
+        /// The bottom or right half
+        second: Box<WindowLayout>,
+    },
+}
+
+impl WindowLayout {
+    /// Divides the area of `window` in two, putting `new_window` above or to the left of it (as
+    /// Vim does).  Returns `false` if `window` isn't part of this `WindowLayout`.
+    pub fn split(&mut self, window: usize, new_window: usize, direction: SplitDirection) -> bool {
+        match self {
+            WindowLayout::Window(w) if *w == window => {
+                *self = WindowLayout::Split {
+                    direction,
+                    first: Box::new(WindowLayout::Window(new_window)),
+                    second: Box::new(WindowLayout::Window(window)),
+                };
+                true
+            }
+            WindowLayout::Window(_) => false,
+            WindowLayout::Split { first, second, .. } => {
+                first.split(window, new_window, direction)
+                    || second.split(window, new_window, direction)
+            }
+        }
+    }
+
+    /// Removes `window`, giving its area to the layout it was split from.  Every window with a
+    /// larger index has its index reduced by one, to match removing `window` from a [`Vec`].
+    /// Returns `false` (without changing anything) if `window` is the only window or isn't part
+    /// of this `WindowLayout`.
+    pub fn remove(&mut self, window: usize) -> bool {
+        if !self.remove_window(window) {
+            return false;
+        }
+        self.renumber_after(window);
+        true
+    }
+
+    /// Does the work of [`WindowLayout::remove`], without changing any indices
+    fn remove_window(&mut self, window: usize) -> bool {
+        let (first, second) = match self {
+            WindowLayout::Window(_) => return false,
+            WindowLayout::Split { first, second, .. } => (first, second),
+        };
+        let remaining = if **first == WindowLayout::Window(window) {
+            std::mem::replace(&mut **second, WindowLayout::Window(window))
+        } else if **second == WindowLayout::Window(window) {
+            std::mem::replace(&mut **first, WindowLayout::Window(window))
+        } else {
+            return first.remove_window(window) || second.remove_window(window);
+        };
+        *self = remaining;
+        true
+    }
+
+    /// Reduces the index of every window after `removed_window` by one
+    fn renumber_after(&mut self, removed_window: usize) {
+        match self {
+            WindowLayout::Window(w) => {
+                if *w > removed_window {
+                    *w -= 1;
+                }
+            }
+            WindowLayout::Split { first, second, .. } => {
+                first.renumber_after(removed_window);
+                second.renumber_after(removed_window);
+            }
+        }
+    }
+
+    /// Returns the index of every window in this `WindowLayout`, ordered from the top-left to the
+    /// bottom-right (the order that `<C-w>w` cycles through them)
+    pub fn windows(&self) -> Vec<usize> {
+        self.rects(Rect::default())
+            .into_iter()
+            .map(|(window, _)| window)
+            .collect()
+    }
+
+    /// Divides `area` between the windows, returning each window's index and [`Rect`] in the same
+    /// order as [`WindowLayout::windows`].  Vertical splits leave a column between their halves,
+    /// which is used to draw a separator line.
+    pub fn rects(&self, area: Rect) -> Vec<(usize, Rect)> {
+        let mut rects = Vec::new();
+        self.add_rects(area, &mut rects);
+        rects
+    }
+
+    fn add_rects(&self, area: Rect, rects: &mut Vec<(usize, Rect)>) {
+        match self {
+            WindowLayout::Window(window) => rects.push((*window, area)),
+            WindowLayout::Split {
+                direction: SplitDirection::Horizontal,
+                first,
+                second,
+            } => {
+                let first_height = area.height / 2;
+                first.add_rects(
+                    Rect {
+                        height: first_height,
+                        ..area
+                    },
+                    rects,
+                );
+                second.add_rects(
+                    Rect {
+                        row: area.row + first_height,
+                        height: area.height - first_height,
+                        ..area
+                    },
+                    rects,
+                );
+            }
+            WindowLayout::Split {
+                direction: SplitDirection::Vertical,
+                first,
+                second,
+            } => {
+                let first_width = area.width.saturating_sub(1) / 2;
+                first.add_rects(
+                    Rect {
+                        width: first_width,
+                        ..area
+                    },
+                    rects,
+                );
+                second.add_rects(
+                    Rect {
+                        col: (area.col + first_width + 1).min(area.right()),
+                        width: area.width.saturating_sub(first_width + 1),
+                        ..area
+                    },
+                    rects,
+                );
+            }
+        }
+    }
+}
+
+/// Given the [`Rect`]s of the windows (as returned by [`WindowLayout::rects`]), returns the window
+/// which is next to `window` in a given direction (like Vim's `<C-w>h`, `<C-w>j`, etc.).  If
+/// several windows are next to it, the one nearest to its top-left corner is chosen.
+pub fn neighbour(
+    rects: &[(usize, Rect)],
+    window: usize,
+    direction: ScreenDirection,
+) -> Option<usize> {
+    let (_, from) = rects.iter().find(|(w, _)| *w == window)?;
+    let overlaps_rows = |rect: &Rect| rect.row < from.bottom() && from.row < rect.bottom();
+    let overlaps_cols = |rect: &Rect| rect.col < from.right() && from.col < rect.right();
+    rects
+        .iter()
+        .filter_map(|(w, rect)| {
+            // The distance to the window, and how far its corner is from `window`'s corner
+            let (distance, offset) = match direction {
+                ScreenDirection::Left if rect.right() <= from.col && overlaps_rows(rect) => {
+                    (from.col - rect.right(), rect.row.max(from.row) - from.row)
+                }
+                ScreenDirection::Right if rect.col >= from.right() && overlaps_rows(rect) => {
+                    (rect.col - from.right(), rect.row.max(from.row) - from.row)
+                }
+                ScreenDirection::Up if rect.bottom() <= from.row && overlaps_cols(rect) => {
+                    (from.row - rect.bottom(), rect.col.max(from.col) - from.col)
+                }
+                ScreenDirection::Down if rect.row >= from.bottom() && overlaps_cols(rect) => {
+                    (rect.row - from.bottom(), rect.col.max(from.col) - from.col)
+                }
+                _ => return None,
+            };
+            Some(((distance, offset), *w))
+        })
+        .min()
+        .map(|(_, w)| w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{neighbour, Rect, ScreenDirection, SplitDirection, WindowLayout};
+
+    #[test]
+    fn split_and_remove() {
+        let mut layout = WindowLayout::Window(0);
+        // Splitting a window that doesn't exist does nothing
+        assert!(!layout.split(3, 1, SplitDirection::Vertical));
+        assert_eq!(layout, WindowLayout::Window(0));
+        // New windows go above or to the left of the window that was split
+        assert!(layout.split(0, 1, SplitDirection::Vertical));
+        assert!(layout.split(0, 2, SplitDirection::Horizontal));
+        assert_eq!(layout.windows(), vec![1, 2, 0]);
+        // The only window can't be removed
+        assert!(!WindowLayout::Window(0).remove(0));
+        // Removing a window renumbers the windows after it
+        assert!(layout.remove(1));
+        assert_eq!(
+            layout,
+            WindowLayout::Split {
+                direction: SplitDirection::Horizontal,
+                first: Box::new(WindowLayout::Window(1)),
+                second: Box::new(WindowLayout::Window(0)),
+            }
+        );
+        assert!(!layout.remove(2));
+        assert!(layout.remove(0));
+        assert_eq!(layout, WindowLayout::Window(0));
+    }
+
+    #[test]
+    fn rects() {
+        let mut layout = WindowLayout::Window(0);
+        layout.split(0, 1, SplitDirection::Vertical);
+        layout.split(0, 2, SplitDirection::Horizontal);
+        let area = Rect {
+            row: 0,
+            col: 0,
+            height: 11,
+            width: 41,
+        };
+        let rects = layout.rects(area);
+        assert_eq!(
+            rects,
+            vec![
+                (
+                    1,
+                    Rect {
+                        row: 0,
+                        col: 0,
+                        height: 11,
+                        width: 20
+                    }
+                ),
+                (
+                    2,
+                    Rect {
+                        row: 0,
+                        col: 21,
+                        height: 5,
+                        width: 20
+                    }
+                ),
+                (
+                    0,
+                    Rect {
+                        row: 5,
+                        col: 21,
+                        height: 6,
+                        width: 20
+                    }
+                ),
+            ]
+        );
+        // Moving between windows
+        assert_eq!(neighbour(&rects, 1, ScreenDirection::Right), Some(2));
+        assert_eq!(neighbour(&rects, 1, ScreenDirection::Left), None);
+        assert_eq!(neighbour(&rects, 2, ScreenDirection::Down), Some(0));
+        assert_eq!(neighbour(&rects, 0, ScreenDirection::Up), Some(2));
+        assert_eq!(neighbour(&rects, 0, ScreenDirection::Left), Some(1));
+        assert_eq!(neighbour(&rects, 2, ScreenDirection::Up), None);
+        assert_eq!(neighbour(&rects, 5, ScreenDirection::Up), None);
+    }
+}
@@ -13,14 +13,59 @@ pub mod core;
 pub mod editor;
 
 use crate::arena::Arena;
-use crate::ast::json::{add_value_to_arena, JsonFormat};
+use crate::ast::json::{add_value_to_arena, Json, JsonFormat};
 use crate::config::Config;
 use crate::core::Path;
-use crate::editor::{dag::Dag, Editor};
+use crate::editor::{buffer::Buffer, dag::Dag, load_dag, Editor};
 
+use std::hash::Hash;
 use std::path::PathBuf;
 
-use serde_json::Value;
+/// The languages that Sapling can edit
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+enum Language {
+    /// JSON, edited with [`Json`](crate::ast::json::Json) as the [`Ast`](crate::ast::Ast)
+    Json,
+}
+
+impl Language {
+    /// Picks the `Language` of a file from its extension, returning `None` if the extension isn't
+    /// recognised
+    fn from_path(path: &std::path::Path) -> Option<Language> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Language::Json),
+            _ => None,
+        }
+    }
+}
+
+/// The command-line arguments passed to Sapling
+#[derive(Debug, Clone, Default)]
+struct Args {
+    /// The files to open, each of which gets its own buffer
+    file_paths: Vec<PathBuf>,
+    /// The config file given with `--config`, if any
+    config_path: Option<PathBuf>,
+}
+
+impl Args {
+    /// Parses the command-line arguments of Sapling, which are `[--config <path>] [files...]`
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-c" | "--config" => {
+                    let path = args
+                        .next()
+                        .ok_or_else(|| format!("Expected a path after '{}'", arg))?;
+                    parsed.config_path = Some(PathBuf::from(path));
+                }
+                _ => parsed.file_paths.push(PathBuf::from(arg)),
+            }
+        }
+        Ok(parsed)
+    }
+}
 
 /// The entry point of Sapling.
 ///
@@ -33,32 +78,93 @@ fn main() {
         .init();
     log::info!("Starting up...");
 
-    // Read a file name as the CLI argument
-    let (file_path, initial_json) = if let Some(first_arg) = std::env::args().skip(1).next() {
-        let path = PathBuf::from(first_arg);
-        let file = match std::fs::File::open(&path) {
-            Ok(x) => x,
-            Err(e) => {
-                eprintln!("Error opening {:?}: {}", path, e);
-                return;
-            }
-        };
-        (
-            Some(path),
-            serde_json::from_reader::<_, Value>(file).unwrap(),
-        )
-    } else {
-        log::warn!("Expected a file-name as an argument.  Using default JSON instead.");
-        (None, serde_json::json!([true, false, { "value": false }]))
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Usage: sapling [--config <path>] [files...]");
+            return;
+        }
     };
 
-    // Create an empty arena for Sapling to use
-    log::trace!("Creating arena");
-    let arena = Arena::new();
-    // For the time being, start the editor with some pre-made Json
-    let root = add_value_to_arena(initial_json, &arena);
+    // Load the user's config before doing anything else, so that mistakes in the config file are
+    // reported before the editor takes over the terminal
+    let config = match Config::load(args.config_path.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error loading config: {}", e);
+            return;
+        }
+    };
 
-    let mut tree = Dag::new(&arena, root, Path::root());
-    let editor = Editor::new(&mut tree, JsonFormat::Pretty, Config::default(), file_path);
-    editor.run();
+    // Pick which language to edit from the extension of the first file.  Every buffer is edited
+    // as the same language.
+    let language = match args.file_paths.first() {
+        Some(path) => Language::from_path(path).unwrap_or_else(|| {
+            log::warn!("Unknown file extension of {:?}, editing as JSON", path);
+            Language::Json
+        }),
+        None => Language::Json,
+    };
+    match language {
+        Language::Json => edit_json(args.file_paths, config),
+    }
+}
+
+/// Starts Sapling editing JSON trees, read from `file_paths` (with one buffer per file)
+fn edit_json(file_paths: Vec<PathBuf>, config: Config) {
+    // The editor stops without quitting when its arena needs to be compacted, so the first arena
+    // is dropped at the end of this block (freeing every node which is no longer needed) and the
+    // editor carries on with the nodes it still needs copied into a new arena.
+    let intern_nodes = config.intern_nodes;
+    let mut detached = {
+        // Create an empty arena for Sapling to use
+        log::trace!("Creating arena");
+        let arena = new_arena(intern_nodes);
+
+        // Read and parse the files given as CLI arguments.  Any errors are reported before the
+        // editor starts, so that they aren't hidden by the editor taking over the terminal
+        let mut buffers = Vec::with_capacity(file_paths.len());
+        for path in file_paths {
+            match load_dag(&path, &arena, config.undo_file) {
+                Ok(tree) => buffers.push(Buffer::new(tree, Some(path))),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            }
+        }
+        if buffers.is_empty() {
+            log::warn!("Expected a file-name as an argument.  Using default JSON instead.");
+            // For the time being, start the editor with some pre-made Json
+            let root =
+                add_value_to_arena(serde_json::json!([true, false, { "value": false }]), &arena);
+            let tree = Dag::new(&arena, root, Path::root());
+            buffers.push(Buffer::new(tree, None));
+        }
+
+        let editor = Editor::new(buffers, JsonFormat::Pretty, config);
+        match editor.run() {
+            Some(detached) => detached,
+            None => return,
+        }
+    };
+    loop {
+        let arena: Arena<Json> = new_arena(intern_nodes);
+        let editor = Editor::attach(detached, &arena);
+        detached = match editor.run() {
+            Some(detached) => detached,
+            None => return,
+        };
+    }
+}
+
+/// Creates an empty [`Arena`], which [merges identical nodes](Arena::with_interning) if
+/// `intern_nodes` is `true`
+fn new_arena<T: Eq + Hash>(intern_nodes: bool) -> Arena<T> {
+    if intern_nodes {
+        Arena::with_interning()
+    } else {
+        Arena::new()
+    }
 }